@@ -1,106 +1,1066 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use clap::ValueEnum;
 use font8x8::UnicodeFonts;
-use image::{GrayImage, Luma, RgbaImage, Rgba};
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Pixel, Rgb, RgbImage, RgbaImage, Rgba};
+
+use crate::error::{AppError, Result};
+
+/// Characters used when the requested charset is empty.
+pub const DEFAULT_CHARSET: &str = "@#*+=-:. ";
+
+/// Ramp used when neither a charset nor a look picks one (the CLI's
+/// `--ramp standard`).
+pub const STANDARD_CHARSET: &str = "@%#*+=-:. ";
+
+/// Glyph drawn in place of characters the built-in font can't render.
+pub const DEFAULT_FALLBACK_CHAR: char = '?';
+
+/// Error-diffusion dithering applied to the cell grid in B/W mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dither {
+    /// Nearest character per cell
+    None,
+    /// Floyd–Steinberg error diffusion
+    Floyd,
+    /// Atkinson error diffusion: only 3/4 of the error is spread, keeping
+    /// local contrast for line-art and text-like output
+    Atkinson,
+}
+
+/// The 16 standard ANSI terminal colors (VGA values), normal then bright.
+pub const ANSI16: [Rgb<u8>; 16] = [
+    Rgb([0, 0, 0]),
+    Rgb([128, 0, 0]),
+    Rgb([0, 128, 0]),
+    Rgb([128, 128, 0]),
+    Rgb([0, 0, 128]),
+    Rgb([128, 0, 128]),
+    Rgb([0, 128, 128]),
+    Rgb([192, 192, 192]),
+    Rgb([128, 128, 128]),
+    Rgb([255, 0, 0]),
+    Rgb([0, 255, 0]),
+    Rgb([255, 255, 0]),
+    Rgb([0, 0, 255]),
+    Rgb([255, 0, 255]),
+    Rgb([0, 255, 255]),
+    Rgb([255, 255, 255]),
+];
+
+/// Fixed or per-video set of colors half-block cells are quantized to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// The 16 ANSI terminal colors
+    Ansi16,
+    /// This many colors picked from a sample frame by median cut
+    MedianCut(u32),
+}
+
+impl Palette {
+    /// Concrete colors for this palette; median-cut palettes are built from
+    /// the half-block cell colors of `sample`.
+    pub fn colors(self, sample: &RgbImage, options: &AsciiOptions) -> Vec<Rgb<u8>> {
+        match self {
+            Palette::Ansi16 => ANSI16.to_vec(),
+            Palette::MedianCut(count) => median_cut(&halfblock_colors(sample, options), count as usize),
+        }
+    }
+}
+
+/// Glyph style used when drawing characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GlyphFont {
+    /// Plain font8x8 glyphs
+    #[value(name = "8x8")]
+    Regular,
+    /// font8x8 glyphs thickened by one pixel horizontally for punchier B/W output
+    BuiltinBold,
+}
+
+impl GlyphFont {
+    /// Row bitmaps of `ch` in this style (bit `n` is column `n`), or of
+    /// `fallback` when the font has no `ch`.
+    fn rows(self, ch: char, fallback: char) -> [u8; 8] {
+        let glyph = glyph_bitmap(ch, fallback);
+        match self {
+            GlyphFont::Regular => glyph,
+            // OR each row with itself shifted one column right
+            GlyphFont::BuiltinBold => glyph.map(|row| row | (row << 1)),
+        }
+    }
+}
+
+/// Floyd–Steinberg weights as (dx, dy, weight) offsets from the current cell.
+const FLOYD_STEINBERG: &[(isize, isize, f32)] = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+/// Atkinson weights: 1/8 of the error to each of six neighbours, the rest dropped.
+const ATKINSON: &[(isize, isize, f32)] = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+impl Dither {
+    fn kernel(self) -> Option<&'static [(isize, isize, f32)]> {
+        match self {
+            Dither::None => None,
+            Dither::Floyd => Some(FLOYD_STEINBERG),
+            Dither::Atkinson => Some(ATKINSON),
+        }
+    }
+}
+
+/// How color source frames are reduced to grayscale before cell averaging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LumaMethod {
+    /// ITU-R BT.709 weights (0.2126 R + 0.7152 G + 0.0722 B)
+    Rec709,
+    /// ITU-R BT.601 weights (0.299 R + 0.587 G + 0.114 B)
+    Rec601,
+    /// Plain mean of R, G and B
+    Average,
+}
+
+impl LumaMethod {
+    pub fn to_gray(self, frame: &DynamicImage) -> GrayImage {
+        let rgba = frame.to_rgba8();
+        match self {
+            LumaMethod::Rec709 => luma_709(&rgba),
+            LumaMethod::Rec601 => luma_601(&rgba),
+            LumaMethod::Average => luma_average(&rgba),
+        }
+    }
+}
+
+pub fn luma_709(rgba: &RgbaImage) -> GrayImage {
+    weighted_luma(rgba, [0.2126, 0.7152, 0.0722])
+}
+
+pub fn luma_601(rgba: &RgbaImage) -> GrayImage {
+    weighted_luma(rgba, [0.299, 0.587, 0.114])
+}
+
+pub fn luma_average(rgba: &RgbaImage) -> GrayImage {
+    weighted_luma(rgba, [1.0 / 3.0; 3])
+}
+
+fn weighted_luma(rgba: &RgbaImage, [wr, wg, wb]: [f32; 3]) -> GrayImage {
+    GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, _] = rgba.get_pixel(x, y).0;
+        let luma = wr * r as f32 + wg * g as f32 + wb * b as f32;
+        Luma([luma.round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+/// What the character ramp is driven by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RenderMode {
+    /// Cell brightness
+    Brightness,
+    /// Sobel gradient magnitude: edges get dense glyphs, flat areas stay blank
+    Edges,
+    /// Full-color upper-half-block cells: two stacked color samples per cell
+    /// (ignores charset and shades)
+    Halfblock,
+    /// Solid quadrant-block mosaic: each quarter of a cell is filled when it
+    /// is dark (light when inverted), like `▖▗▘▝▚▞█` (ignores charset and shades)
+    Blocks,
+}
+
+/// Where `convert_frame_to_ascii` spends its finest cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Focus {
+    /// Uniform cells across the whole frame
+    None,
+    /// Regular cells in the middle of the frame and double-size cells around
+    /// it, so the subject gets more characters than flat surroundings
+    Center,
+}
+
+/// Smoothing applied to each source frame before conversion, so grain
+/// doesn't make cell averages (and their characters) flicker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Prefilter {
+    /// Convert frames as decoded
+    None,
+    /// Gaussian blur; the radius is its sigma
+    Blur,
+    /// Median of each pixel's neighbourhood: removes speckle, keeps edges
+    Median,
+}
+
+impl Prefilter {
+    /// Filter `frame` with strength `radius` (pixels).
+    pub fn apply(self, frame: &DynamicImage, radius: f32) -> DynamicImage {
+        match self {
+            Prefilter::None => frame.clone(),
+            Prefilter::Blur => frame.blur(radius),
+            Prefilter::Median => DynamicImage::ImageRgb8(median_filter(&frame.to_rgb8(), radius.round() as u32)),
+        }
+    }
+}
+
+/// Per-channel median over the `(2 * radius + 1)`-pixel square around each
+/// pixel, clamped at the borders.
+pub fn median_filter(image: &RgbImage, radius: u32) -> RgbImage {
+    if radius == 0 {
+        return image.clone();
+    }
+    let (width, height) = image.dimensions();
+    let radius = radius as i64;
+    let mut window: [Vec<u8>; 3] = Default::default();
+    RgbImage::from_fn(width, height, |x, y| {
+        for channel in &mut window {
+            channel.clear();
+        }
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let sx = (x as i64 + dx).clamp(0, width as i64 - 1) as u32;
+                let sy = (y as i64 + dy).clamp(0, height as i64 - 1) as u32;
+                let pixel = image.get_pixel(sx, sy);
+                for (channel, values) in window.iter_mut().enumerate() {
+                    values.push(pixel[channel]);
+                }
+            }
+        }
+        Rgb(window.each_mut().map(|values| {
+            values.sort_unstable();
+            values[values.len() / 2]
+        }))
+    })
+}
+
+/// Unsharp mask over each pixel's 3x3 neighbourhood: adds `amount` times
+/// the difference from its four direct neighbours, so edges gain contrast
+/// while flat areas stay as they are. Channels are clamped to 0..=255,
+/// neighbours to the image edge, and alpha is kept as it is.
+pub fn sharpen(frame: &DynamicImage, amount: f32) -> DynamicImage {
+    let image = frame.to_rgba8();
+    let (width, height) = image.dimensions();
+    let at = |x: u32, dx: i64, y: u32, dy: i64| {
+        let sx = (x as i64 + dx).clamp(0, width as i64 - 1) as u32;
+        let sy = (y as i64 + dy).clamp(0, height as i64 - 1) as u32;
+        image.get_pixel(sx, sy)
+    };
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+        let center = image.get_pixel(x, y);
+        let neighbours = [at(x, -1, y, 0), at(x, 1, y, 0), at(x, 0, y, -1), at(x, 0, y, 1)];
+        let mut pixel = *center;
+        for channel in 0..3 {
+            let around: f32 = neighbours.iter().map(|pixel| pixel[channel] as f32).sum();
+            let value = center[channel] as f32 * (1.0 + 4.0 * amount) - around * amount;
+            pixel[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+        pixel
+    }))
+}
+
+/// Sobel gradient magnitude, scaled so a full black/white step reaches 255.
+/// Neighbours outside the image are clamped to the nearest edge pixel.
+pub fn sobel_magnitude(image: &GrayImage) -> GrayImage {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return image.clone();
+    }
+
+    let at = |x: i64, y: i64| -> f32 {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        image.get_pixel(x, y)[0] as f32
+    };
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let gx = (at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1))
+            - (at(x - 1, y - 1) + 2.0 * at(x - 1, y) + at(x - 1, y + 1));
+        let gy = (at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1))
+            - (at(x - 1, y - 1) + 2.0 * at(x, y - 1) + at(x + 1, y - 1));
+        let magnitude = (gx * gx + gy * gy).sqrt() / 4.0;
+        Luma([magnitude.round().min(255.0) as u8])
+    })
+}
 
 #[derive(Debug, Clone)]
 pub struct AsciiOptions {
     pub columns: u32,
     pub charset: Vec<char>,
     pub shades: u32,  // Number of grayscale shades (1 = B/W, 2-256 = grayscale)
+    pub invert: bool, // Light glyphs on a black background
+    pub dither: Dither, // Error diffusion across cells (B/W mode only)
+    pub mode: RenderMode, // Brightness or edge-driven character selection
+    pub font_scale: u32, // Each glyph pixel becomes a font_scale x font_scale block
+    pub bw_threshold: u8, // Dark/light cutoff for 1-2 shade rendering and blocks mode
+    pub sample_stride: u32, // Read every Nth pixel per row/column when averaging a cell
+    pub font: GlyphFont, // Regular or bold glyph rendering
+    pub contrast: bool, // Stretch cell luma around mid-gray before picking glyphs (below TONAL_SHADES)
+    pub focus: Focus, // Uniform cells or finer cells in the middle of the frame
+    pub focus_falloff: f32, // Fraction of the frame, from the center out, kept at full detail
+    pub palette: Option<Vec<Rgb<u8>>>, // Colors half-block cells are snapped to
+    pub cell_padding: u32, // Background pixels of gutter after every cell, like terminal spacing
+    pub max_colors_per_cell: Option<u8>, // Color glyphs from their cell's source colors (1 or 2 per cell)
+    pub fallback_char: char, // Drawn in place of charset characters without a glyph
 }
 
 impl AsciiOptions {
     pub fn new(columns: u32, charset: &str, shades: u32) -> Self {
         let mut chars: Vec<char> = charset.chars().collect();
         if chars.is_empty() {
-            chars = "@#*+=-:. ".chars().collect();
+            chars = DEFAULT_CHARSET.chars().collect();
         }
 
         Self {
             columns: columns.max(1),
             charset: chars,
             shades: shades.clamp(1, 256),
+            invert: false,
+            dither: Dither::None,
+            mode: RenderMode::Brightness,
+            font_scale: 1,
+            bw_threshold: 128,
+            sample_stride: 1,
+            font: GlyphFont::Regular,
+            contrast: true,
+            focus: Focus::None,
+            focus_falloff: 0.5,
+            palette: None,
+            cell_padding: 0,
+            max_colors_per_cell: None,
+            fallback_char: DEFAULT_FALLBACK_CHAR,
+        }
+    }
+}
+
+/// Non-fatal problems with a charset that degrade the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CharsetWarning {
+    /// The charset was empty and the default ramp is used instead
+    EmptyFallback,
+    /// Every cell maps to the same character
+    SingleStep(char),
+    /// Fewer distinct characters than requested gray shades
+    FewerStepsThanShades { steps: usize, shades: u32 },
+    /// A character without a glyph is drawn as the fallback
+    Substituted { ch: char, fallback: char },
+}
+
+impl fmt::Display for CharsetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CharsetWarning::EmptyFallback => {
+                write!(f, "empty charset, falling back to \"{DEFAULT_CHARSET}\"")
+            }
+            CharsetWarning::SingleStep(ch) => {
+                write!(f, "charset has a single distinct character '{ch}', output will be uniform")
+            }
+            CharsetWarning::FewerStepsThanShades { steps, shades } => {
+                write!(f, "charset has {steps} distinct characters for {shades} shades")
+            }
+            CharsetWarning::Substituted { ch, fallback } => {
+                write!(f, "charset character {ch:?} has no glyph and is drawn as '{fallback}'")
+            }
         }
     }
 }
 
+/// Check a charset before conversion. Whitespace-only charsets are rejected
+/// since they can only ever render blank frames. Characters without a glyph
+/// are rejected too, unless a `fallback` to draw instead was chosen.
+pub fn validate_charset(charset: &str, shades: u32, fallback: Option<char>) -> Result<Vec<CharsetWarning>> {
+    if charset.is_empty() {
+        return Ok(vec![CharsetWarning::EmptyFallback]);
+    }
+
+    if charset.trim().is_empty() {
+        return Err(AppError::BlankCharset);
+    }
+
+    if let Some(ch) = fallback.filter(|&ch| !has_glyph(ch)) {
+        return Err(AppError::UnsupportedFallback { ch });
+    }
+
+    let mut warnings = Vec::new();
+    if let Some(ch) = charset.chars().find(|&ch| !has_glyph(ch)) {
+        match fallback {
+            Some(fallback) => warnings.push(CharsetWarning::Substituted { ch, fallback }),
+            None => return Err(AppError::UnsupportedChar { ch }),
+        }
+    }
+
+    let distinct: HashSet<char> = charset.chars().collect();
+    if distinct.len() == 1 {
+        warnings.push(CharsetWarning::SingleStep(charset.chars().next().unwrap_or(' ')));
+    } else if shades > 1 && distinct.len() < shades as usize {
+        warnings.push(CharsetWarning::FewerStepsThanShades {
+            steps: distinct.len(),
+            shades,
+        });
+    }
+
+    Ok(warnings)
+}
+
+/// `charset` reordered dark to light by how many pixels each glyph's 8x8
+/// bitmap sets (the `fallback`'s for characters without one), so a scrambled
+/// ramp still maps luma monotonically. The sort is stable: glyphs with equal
+/// ink keep their relative order.
+pub fn order_by_ink(charset: &str, fallback: char) -> String {
+    let mut chars: Vec<char> = charset.chars().collect();
+    chars.sort_by_key(|&ch| std::cmp::Reverse(glyph_bitmap(ch, fallback).iter().map(|row| row.count_ones()).sum::<u32>()));
+    chars.into_iter().collect()
+}
+
 pub fn convert_frame_to_ascii(source: &GrayImage, options: &AsciiOptions) -> GrayImage {
-    // Calculate grid size based on character size (8x8 pixels per char)
-    let char_width = 8u32;
-    let char_height = 8u32;
-    
-    // Calculate how many characters fit in the original dimensions
-    let columns = source.width() / char_width;
-    let rows = source.height() / char_height;
-    
-    // Output will be SAME size as input (each char = 8x8 block)
-    let out_width = columns * char_width;
-    let out_height = rows * char_height;
-
-    let mut output = GrayImage::from_pixel(out_width, out_height, Luma([255]));
+    render_ascii(source, options, None)
+}
+
+/// `convert_frame_to_ascii` for one frame of a sequence: cell luma is
+/// blended with `history` before characters are picked, then folded into it.
+/// Blocks mode has no cell luma and is rendered unsmoothed.
+pub fn convert_frame_to_ascii_smoothed(source: &GrayImage, options: &AsciiOptions, history: &mut CellHistory) -> GrayImage {
+    render_ascii(source, options, Some(history))
+}
+
+fn render_ascii(source: &GrayImage, options: &AsciiOptions, history: Option<&mut CellHistory>) -> GrayImage {
+    let output = if options.mode == RenderMode::Blocks {
+        convert_frame_to_blocks(source, options)
+    } else {
+        draw_glyphs(source, options, history)
+    };
+    let cell = CELL_SIZE * options.font_scale.max(1);
+    pad_cells(output, cell, options.cell_padding, Luma([background_luma(options.invert)]))
+}
+
+/// Glyph grid for `source`, with the coarse surround `options.focus` asks for.
+fn draw_glyphs(source: &GrayImage, options: &AsciiOptions, mut history: Option<&mut CellHistory>) -> GrayImage {
+    let scale = options.font_scale.max(1);
+    let output = draw_grid(source, options, scale, history.as_deref_mut().map(|history| &mut history.fine));
+    let output = match options.focus {
+        Focus::None => output,
+        Focus::Center => {
+            // The surround is rendered from a half-size frame with glyphs drawn
+            // twice as large, so it lines up with the full-detail render
+            let (width, height) = (source.width().div_ceil(2), source.height().div_ceil(2));
+            let half = image::imageops::resize(source, width, height, image::imageops::FilterType::Triangle);
+            let coarse = draw_grid(&half, options, scale * 2, history.map(|history| &mut history.coarse));
+            focus_center(output, &coarse, options.focus_falloff, CELL_SIZE * scale * 2)
+        }
+    };
+
+    crop_to_source(output, source, scale)
+}
+
+/// Glyphs for every 8x8 cell of `source`, each drawn `scale` times larger.
+/// The canvas covers whole cells, so it can overhang `source` at `scale`.
+fn draw_grid(source: &GrayImage, options: &AsciiOptions, scale: u32, history: Option<&mut GridHistory>) -> GrayImage {
+    let grid = char_grid(source, options, history);
+
+    // Output matches the input size at scale 1 (each char = 8x8 block)
+    let out_width = grid.columns * CELL_SIZE * scale;
+    let out_height = grid.rows * CELL_SIZE * scale;
+
+    let mut output = GrayImage::from_pixel(out_width, out_height, Luma([background_luma(options.invert)]));
+
+    for row in 0..grid.rows {
+        for col in 0..grid.columns {
+            let index = (row * grid.columns + col) as usize;
+            let (x0, y0) = (col * CELL_SIZE * scale, row * CELL_SIZE * scale);
+
+            // Draw with grayscale if shades > 1, otherwise pure B/W
+            let value = if options.shades > 1 {
+                shade_value(grid.cells[index], options.shades, options.bw_threshold)
+            } else {
+                255 - background_luma(options.invert)
+            };
+            draw_glyph(&mut output, x0, y0, grid.chars[index], value, options, scale);
+        }
+    }
+
+    output
+}
+
+/// Keep `fine` inside a centered rectangle spanning `falloff` of each
+/// dimension and take `coarse` everywhere else. The rectangle snaps to
+/// `block`, the coarse cell size, so neither render is cut mid-glyph.
+fn focus_center(mut fine: GrayImage, coarse: &GrayImage, falloff: f32, block: u32) -> GrayImage {
+    let snap = |value: f32| ((value / block as f32).round() as u32) * block;
+    let zone = |extent: u32| {
+        let center = extent as f32 / 2.0;
+        let reach = center * falloff.clamp(0.0, 1.0);
+        (snap(center - reach), snap(center + reach))
+    };
+    let ((x0, x1), (y0, y1)) = (zone(fine.width()), zone(fine.height()));
+
+    for (x, y, pixel) in fine.enumerate_pixels_mut() {
+        let inside = (x0..x1).contains(&x) && (y0..y1).contains(&y);
+        if !inside {
+            *pixel = *coarse.get_pixel(x, y);
+        }
+    }
+    fine
+}
+
+/// The characters `convert_frame_to_ascii` would draw, one line per row.
+/// Blocks mode yields quadrant block characters; half-block mode has no
+/// text form and falls back to brightness, as the glyph renderer does.
+pub fn convert_frame_to_text(source: &GrayImage, options: &AsciiOptions) -> String {
+    let (columns, chars) = if options.mode == RenderMode::Blocks {
+        let columns = source.width().div_ceil(CELL_SIZE);
+        let rows = source.height().div_ceil(CELL_SIZE);
+        let half = CELL_SIZE / 2;
+        let chars = (0..rows)
+            .flat_map(|row| (0..columns).map(move |col| (col * CELL_SIZE, row * CELL_SIZE)))
+            .map(|(x0, y0)| {
+                let quarters = [(0, 0), (half, 0), (0, half), (half, half)];
+                let bits = quarters.iter().enumerate().fold(0, |bits, (bit, &(dx, dy))| {
+                    if quarter_inked(source, x0 + dx, y0 + dy, options) { bits | 1 << bit } else { bits }
+                });
+                QUADRANT_CHARS[bits]
+            })
+            .collect();
+        (columns, chars)
+    } else {
+        let grid = char_grid(source, options, None);
+        (grid.columns, grid.chars)
+    };
+
+    chars
+        .chunks(columns.max(1) as usize)
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `convert_frame_to_text` with each character colored by the average of
+/// its cell in `color`, as 24-bit ANSI escapes for printing to a terminal.
+/// A run of same-colored cells shares one escape; every line ends with a
+/// reset so the terminal's own color returns after the last cell.
+pub fn convert_frame_to_ansi_text(source: &GrayImage, color: &RgbImage, options: &AsciiOptions) -> String {
+    let text = convert_frame_to_text(source, options);
+    let mut output = String::with_capacity(text.len() * 4);
+
+    for (row, line) in text.lines().enumerate() {
+        if row > 0 {
+            output.push('\n');
+        }
+        let mut current = None;
+        for (col, ch) in line.chars().enumerate() {
+            let (x0, y0) = (col as u32 * CELL_SIZE, row as u32 * CELL_SIZE);
+            let cell = average_rgb(color, x0, x0 + CELL_SIZE, y0, y0 + CELL_SIZE);
+            if current != Some(cell) {
+                let Rgb([r, g, b]) = cell;
+                output.push_str(&format!("\x1b[38;2;{r};{g};{b}m"));
+                current = Some(cell);
+            }
+            output.push(ch);
+        }
+        output.push_str("\x1b[0m");
+    }
+    output
+}
+
+/// Source pixels per character cell along each axis.
+const CELL_SIZE: u32 = 8;
+
+/// Shade count from which cells keep their raw luma even with `contrast`:
+/// that many gray levels are about tonal fidelity, which the stretch would
+/// undo by clipping both ends of the range.
+pub const TONAL_SHADES: u32 = 16;
+
+/// Quadrant block per 4-bit mask: top-left = 1, top-right = 2,
+/// bottom-left = 4, bottom-right = 8.
+const QUADRANT_CHARS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// Cell luma carried from frame to frame by `convert_frame_to_ascii_smoothed`.
+/// Each cell follows an exponential moving average of its luma, so cells
+/// hovering around a ramp boundary stop flipping between characters.
+#[derive(Debug, Clone)]
+pub struct CellHistory {
+    fine: GridHistory,
+    /// The double-size surround drawn with `Focus::Center`
+    coarse: GridHistory,
+}
+
+impl CellHistory {
+    /// `alpha` is the weight of the newest frame: 1.0 disables smoothing and
+    /// lower values hold cells steadier but let motion trail.
+    pub fn new(alpha: f32) -> Self {
+        let grid = GridHistory {
+            alpha: alpha.clamp(0.0, 1.0),
+            cells: Vec::new(),
+        };
+        Self {
+            fine: grid.clone(),
+            coarse: grid,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GridHistory {
+    alpha: f32,
+    cells: Vec<f32>,
+}
 
+impl GridHistory {
+    /// Blend `cells` into the running averages and replace them with the result.
+    /// A grid of a different size (the first frame, or a resized one) restarts it.
+    fn smooth(&mut self, cells: &mut [u8]) {
+        if self.cells.len() != cells.len() {
+            self.cells = cells.iter().map(|&luma| luma as f32).collect();
+            return;
+        }
+        for (luma, average) in cells.iter_mut().zip(&mut self.cells) {
+            *average += (*luma as f32 - *average) * self.alpha;
+            *luma = average.round() as u8;
+        }
+    }
+}
+
+/// Per-cell brightness and chosen character for a frame, row by row.
+struct CharGrid {
+    columns: u32,
+    rows: u32,
+    cells: Vec<u8>,
+    chars: Vec<char>,
+}
+
+fn char_grid(source: &GrayImage, options: &AsciiOptions, history: Option<&mut GridHistory>) -> CharGrid {
+    // Edge mode feeds gradient magnitude through the normal pipeline, oriented
+    // so edges land on the dense end of the ramp (bright when inverted)
+    let edges;
+    let source = match options.mode {
+        // Half-block cells are drawn by `convert_frame_to_halfblock`; glyphs fall back to brightness
+        RenderMode::Brightness | RenderMode::Halfblock | RenderMode::Blocks => source,
+        RenderMode::Edges => {
+            let mut magnitude = sobel_magnitude(source);
+            if !options.invert {
+                image::imageops::invert(&mut magnitude);
+            }
+            edges = magnitude;
+            &edges
+        }
+    };
+
+    // Round up so a partial cell on the right/bottom edge still gets a glyph,
+    // averaged over the pixels it has
+    let columns = source.width().div_ceil(CELL_SIZE);
+    let rows = source.height().div_ceil(CELL_SIZE);
+
+    let stretch = options.contrast && options.shades < TONAL_SHADES;
+
+    // Average each cell first so dithering can spread error across the grid
+    let mut cells = Vec::with_capacity((columns * rows) as usize);
     for row in 0..rows {
-        let y0 = row * char_height;
-        let y1 = y0 + char_height;
+        let y0 = row * CELL_SIZE;
+        let y1 = y0 + CELL_SIZE;
 
         for col in 0..columns {
-            let x0 = col * char_width;
-            let x1 = x0 + char_width;
+            let x0 = col * CELL_SIZE;
+            let x1 = x0 + CELL_SIZE;
 
-            let luma = average_luma(source, x0, x1, y0, y1);
+            let luma = average_luma(source, x0, x1, y0, y1, options.sample_stride);
             // Enhance contrast: stretch 0-255 to have more separation
-            let enhanced = enhance_contrast(luma);
-            let ch = map_luma_to_char(enhanced, &options.charset);
-            
-            // Draw with grayscale if shades > 1, otherwise pure B/W
-            if options.shades > 1 {
-                draw_glyph_gray(&mut output, x0, y0, ch, enhanced, options.shades);
-            } else {
-                draw_glyph_bw(&mut output, x0, y0, ch);
+            cells.push(if stretch { enhance_contrast(luma) } else { luma });
+        }
+    }
+    if let Some(history) = history {
+        history.smooth(&mut cells);
+    }
+
+    let chars = select_chars(&cells, columns as usize, options);
+    CharGrid {
+        columns,
+        rows,
+        cells,
+        chars,
+    }
+}
+
+/// Split every 8x8 cell into four 4x4 quarters and fill each one solid when
+/// it counts as ink, so a cell shows one of the 16 quadrant block shapes:
+/// blank when no quarter is inked, a full block when all four are.
+fn convert_frame_to_blocks(source: &GrayImage, options: &AsciiOptions) -> GrayImage {
+    let half = CELL_SIZE / 2;
+    let columns = source.width().div_ceil(CELL_SIZE);
+    let rows = source.height().div_ceil(CELL_SIZE);
+    let scale = options.font_scale.max(1);
+
+    let background = background_luma(options.invert);
+    let ink = 255 - background;
+    let mut output = GrayImage::from_pixel(columns * CELL_SIZE * scale, rows * CELL_SIZE * scale, Luma([background]));
+
+    for y0 in (0..rows * CELL_SIZE).step_by(half as usize) {
+        for x0 in (0..columns * CELL_SIZE).step_by(half as usize) {
+            if !quarter_inked(source, x0, y0, options) {
+                continue;
+            }
+
+            for y in y0 * scale..(y0 + half) * scale {
+                for x in x0 * scale..(x0 + half) * scale {
+                    output.put_pixel(x, y, Luma([ink]));
+                }
             }
         }
     }
 
+    crop_to_source(output, source, scale)
+}
+
+/// Whether the 4x4 quarter cell at (`x0`, `y0`) is filled in blocks mode.
+fn quarter_inked(source: &GrayImage, x0: u32, y0: u32, options: &AsciiOptions) -> bool {
+    let half = CELL_SIZE / 2;
+    let mut luma = average_luma(source, x0, x0 + half, y0, y0 + half, options.sample_stride);
+    if options.contrast {
+        luma = enhance_contrast(luma);
+    }
+    // Dark quarters take ink normally; bright ones when inverted
+    if options.invert {
+        luma >= options.bw_threshold
+    } else {
+        luma < options.bw_threshold
+    }
+}
+
+/// Trim a canvas drawn on the rounded-up cell grid back to the source size
+/// (times `scale`), so partial edge cells are kept but nothing is padded.
+fn crop_to_source<P: Pixel + 'static, S: Pixel>(
+    canvas: ImageBuffer<P, Vec<P::Subpixel>>,
+    source: &ImageBuffer<S, Vec<S::Subpixel>>,
+    scale: u32,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let (width, height) = (source.width() * scale, source.height() * scale);
+    if canvas.dimensions() == (width, height) {
+        return canvas;
+    }
+    image::imageops::crop_imm(&canvas, 0, 0, width, height).to_image()
+}
+
+/// Spread a canvas of `cell` x `cell` blocks apart with `padding` pixels of
+/// `background` after every block, partial edge blocks included, like the
+/// gaps between characters in a terminal. Gutters follow the fine grid, so
+/// the coarse surround of `Focus::Center` is split mid-glyph.
+fn pad_cells<P: Pixel + 'static>(
+    canvas: ImageBuffer<P, Vec<P::Subpixel>>,
+    cell: u32,
+    padding: u32,
+    background: P,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    if padding == 0 {
+        return canvas;
+    }
+    let padded = |extent: u32| extent + extent.div_ceil(cell) * padding;
+    let mut output = ImageBuffer::from_pixel(padded(canvas.width()), padded(canvas.height()), background);
+    for (x, y, pixel) in canvas.enumerate_pixels() {
+        output.put_pixel(x + x / cell * padding, y + y / cell * padding, *pixel);
+    }
     output
 }
 
+/// Pick a character for every cell of a `columns`-wide luma grid.
+fn select_chars(cells: &[u8], columns: usize, options: &AsciiOptions) -> Vec<char> {
+    // When inverted, dense characters go on bright regions
+    let ramp: Vec<u8> = cells
+        .iter()
+        .map(|&luma| if options.shades == 1 { shift_midpoint(luma, options.bw_threshold) } else { luma })
+        .map(|luma| if options.invert { 255 - luma } else { luma })
+        .collect();
+
+    match options.dither.kernel() {
+        // Dithering only applies to pure B/W, where a cell has no other tonal cue
+        Some(kernel) if options.shades == 1 => {
+            diffuse_error(&ramp, columns, options.charset.len(), kernel)
+                .into_iter()
+                .map(|idx| options.charset[idx])
+                .collect()
+        }
+        _ => ramp
+            .iter()
+            .map(|&luma| map_luma_to_char(luma, &options.charset))
+            .collect(),
+    }
+}
+
+/// Quantize `cells` to `levels` evenly spaced ramp indices, pushing each
+/// cell's quantization error onto its not-yet-visited neighbours.
+fn diffuse_error(cells: &[u8], columns: usize, levels: usize, kernel: &[(isize, isize, f32)]) -> Vec<usize> {
+    let last = levels.saturating_sub(1);
+    if last == 0 || columns == 0 {
+        return vec![0; cells.len()];
+    }
+
+    let rows = cells.len() / columns;
+    let step = 255.0 / last as f32;
+    let mut values: Vec<f32> = cells.iter().map(|&luma| luma as f32).collect();
+    let mut indices = vec![0; cells.len()];
+
+    for y in 0..rows {
+        for x in 0..columns {
+            let i = y * columns + x;
+            let value = values[i].clamp(0.0, 255.0);
+            let idx = ((value / step).round() as usize).min(last);
+            indices[i] = idx;
+
+            let error = value - idx as f32 * step;
+            for &(dx, dy, weight) in kernel {
+                let nx = x as isize + dx;
+                let ny = y as isize + dy;
+                if nx >= 0 && (nx as usize) < columns && ny >= 0 && (ny as usize) < rows {
+                    values[ny as usize * columns + nx as usize] += error * weight;
+                }
+            }
+        }
+    }
+
+    indices
+}
+
+/// Render each 8x8 cell like a terminal `▀` with separate foreground and
+/// background colors: the top half is filled with the average color of the
+/// cell's upper half, the bottom with that of its lower half.
+pub fn convert_frame_to_halfblock(source: &RgbImage, options: &AsciiOptions) -> RgbImage {
+    let half = CELL_SIZE / 2;
+    let columns = source.width().div_ceil(CELL_SIZE);
+    let rows = source.height().div_ceil(CELL_SIZE);
+    let scale = options.font_scale.max(1);
+
+    let mut output = RgbImage::new(columns * CELL_SIZE * scale, rows * CELL_SIZE * scale);
+
+    for row in 0..rows {
+        for col in 0..columns {
+            let x0 = col * CELL_SIZE;
+
+            for y0 in [row * CELL_SIZE, row * CELL_SIZE + half] {
+                let mut color = half_cell_color(source, x0, y0, options);
+                if let Some(palette) = &options.palette {
+                    color = nearest_color(palette, color);
+                }
+
+                for y in y0 * scale..(y0 + half) * scale {
+                    for x in x0 * scale..(x0 + CELL_SIZE) * scale {
+                        output.put_pixel(x, y, color);
+                    }
+                }
+            }
+        }
+    }
+
+    let background = Rgb([background_luma(options.invert); 3]);
+    pad_cells(crop_to_source(output, source, scale), CELL_SIZE * scale, options.cell_padding, background)
+}
+
+/// Color of the 8x4 half cell at (`x0`, `y0`) before any palette is applied.
+fn half_cell_color(source: &RgbImage, x0: u32, y0: u32, options: &AsciiOptions) -> Rgb<u8> {
+    let color = average_rgb(source, x0, x0 + CELL_SIZE, y0, y0 + CELL_SIZE / 2);
+    if options.invert { Rgb(color.0.map(|c| 255 - c)) } else { color }
+}
+
+/// Color the glyphs of `ascii`, the render of `source` (the same frame at
+/// source size) by `convert_frame_to_ascii`, from the source colors of each
+/// cell. With one color per cell, ink takes the cell's average color on the
+/// plain background. With two, the glyph's set bits take the cell's darkest
+/// source color (lightest when inverted) and its unset bits the opposite
+/// extreme, so every cell reads as a two-tone stipple. Gray glyph levels
+/// blend the two; `cell_padding` gutters stay background.
+pub fn colorize_glyphs(ascii: &GrayImage, source: &RgbImage, options: &AsciiOptions) -> RgbImage {
+    let glyph = CELL_SIZE * options.font_scale.max(1);
+    let pitch = glyph + options.cell_padding;
+    let (columns, rows) = (source.width().div_ceil(CELL_SIZE), source.height().div_ceil(CELL_SIZE));
+    let background = background_luma(options.invert);
+    let canvas = Rgb([background; 3]);
+
+    let two_tone = options.max_colors_per_cell.unwrap_or(1) >= 2;
+    let cells: Vec<(Rgb<u8>, Rgb<u8>)> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |col| (col * CELL_SIZE, row * CELL_SIZE)))
+        .map(|(x0, y0)| {
+            if !two_tone {
+                return (average_rgb(source, x0, x0 + CELL_SIZE, y0, y0 + CELL_SIZE), canvas);
+            }
+            let (darkest, lightest) = cell_extremes(source, x0, y0);
+            if options.invert { (lightest, darkest) } else { (darkest, lightest) }
+        })
+        .collect();
+
+    RgbImage::from_fn(ascii.width(), ascii.height(), |x, y| {
+        let (col, row) = (x / pitch, y / pitch);
+        if x % pitch >= glyph || y % pitch >= glyph || col >= columns || row >= rows {
+            return canvas;
+        }
+        let (ink, paper) = cells[(row * columns + col) as usize];
+        let weight = ascii.get_pixel(x, y)[0].abs_diff(background) as u32;
+        Rgb(std::array::from_fn(|channel| {
+            ((ink[channel] as u32 * weight + paper[channel] as u32 * (255 - weight) + 127) / 255) as u8
+        }))
+    })
+}
+
+/// Darkest and lightest pixel (by Rec. 709 luma) of the 8x8 cell at (`x0`, `y0`).
+fn cell_extremes(source: &RgbImage, x0: u32, y0: u32) -> (Rgb<u8>, Rgb<u8>) {
+    let luma = |pixel: &Rgb<u8>| 2126 * pixel[0] as u32 + 7152 * pixel[1] as u32 + 722 * pixel[2] as u32;
+    let pixels: Vec<Rgb<u8>> = (y0..(y0 + CELL_SIZE).min(source.height()))
+        .flat_map(|y| (x0..(x0 + CELL_SIZE).min(source.width())).map(move |x| *source.get_pixel(x, y)))
+        .collect();
+    let darkest = pixels.iter().copied().min_by_key(luma).unwrap_or(Rgb([0; 3]));
+    let lightest = pixels.iter().copied().max_by_key(luma).unwrap_or(Rgb([255; 3]));
+    (darkest, lightest)
+}
+
+/// Every half-cell color `convert_frame_to_halfblock` would draw for `source`.
+fn halfblock_colors(source: &RgbImage, options: &AsciiOptions) -> Vec<Rgb<u8>> {
+    let columns = source.width().div_ceil(CELL_SIZE);
+    let rows = source.height().div_ceil(CELL_SIZE);
+    (0..rows * 2)
+        .flat_map(|half_row| (0..columns).map(move |col| (col * CELL_SIZE, half_row * (CELL_SIZE / 2))))
+        .map(|(x0, y0)| half_cell_color(source, x0, y0, options))
+        .collect()
+}
+
+/// Reduce `colors` to at most `count` representatives: repeatedly split the
+/// box with the widest channel range at its median, then average each box.
+pub fn median_cut(colors: &[Rgb<u8>], count: usize) -> Vec<Rgb<u8>> {
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colors)| {
+                let (channel, span) = widest_channel(colors);
+                (index, channel, span)
+            })
+            .max_by_key(|&(_, _, span)| span);
+        // Every remaining box is a single color
+        let Some((index, channel, _)) = widest.filter(|&(_, _, span)| span > 0) else {
+            break;
+        };
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|color| color.0[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    // A median can fall between equal colors, so two boxes may average the same
+    let mut seen = HashSet::new();
+    boxes.iter().filter(|colors| !colors.is_empty()).map(|colors| mean_rgb(colors)).filter(|&color| seen.insert(color)).collect()
+}
+
+/// Channel with the largest max - min spread in `colors`, and that spread.
+fn widest_channel(colors: &[Rgb<u8>]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = colors.iter().fold((u8::MAX, u8::MIN), |(min, max), color| {
+                (min.min(color.0[channel]), max.max(color.0[channel]))
+            });
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|&(_, span)| span)
+        .unwrap_or((0, 0))
+}
+
+fn mean_rgb(colors: &[Rgb<u8>]) -> Rgb<u8> {
+    let mut sum = [0u64; 3];
+    for color in colors {
+        for (total, &channel) in sum.iter_mut().zip(color.0.iter()) {
+            *total += channel as u64;
+        }
+    }
+    Rgb(sum.map(|total| total.checked_div(colors.len() as u64).unwrap_or(0) as u8))
+}
+
+/// Palette entry closest to `color` in RGB space; `color` itself for an
+/// empty palette.
+fn nearest_color(palette: &[Rgb<u8>], color: Rgb<u8>) -> Rgb<u8> {
+    let distance = |entry: &&Rgb<u8>| -> u32 {
+        entry.0.iter().zip(color.0.iter()).map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32).sum()
+    };
+    palette.iter().min_by_key(distance).copied().unwrap_or(color)
+}
+
+fn average_rgb(image: &RgbImage, x0: u32, x1: u32, y0: u32, y1: u32) -> Rgb<u8> {
+    let mut sum = [0u64; 3];
+    let mut count: u64 = 0;
+
+    for y in y0..y1.min(image.height()) {
+        for x in x0..x1.min(image.width()) {
+            for (total, &channel) in sum.iter_mut().zip(image.get_pixel(x, y).0.iter()) {
+                *total += channel as u64;
+            }
+            count += 1;
+        }
+    }
+
+    Rgb(sum.map(|total| total.checked_div(count).unwrap_or(0) as u8))
+}
+
 /// Detect the most common background color in the image
 pub fn detect_background_color(image: &GrayImage) -> u8 {
-    let mut histogram = [0usize; 256];
+    let mut histogram = LumaHistogram::default();
+    histogram.add(image);
+    histogram.mode()
+}
 
-    for pixel in image.pixels() {
-        histogram[pixel[0] as usize] += 1;
-    }
+/// 256-bin grayscale histogram that can accumulate over many frames.
+#[derive(Debug, Clone)]
+pub struct LumaHistogram {
+    bins: [u64; 256],
+}
 
-    // Find the most frequent color (likely background)
-    let mut max_count = 0;
-    let mut bg_color = 255u8;
+impl Default for LumaHistogram {
+    fn default() -> Self {
+        Self { bins: [0; 256] }
+    }
+}
 
-    for (color, &count) in histogram.iter().enumerate() {
-        if count > max_count {
-            max_count = count;
-            bg_color = color as u8;
+impl LumaHistogram {
+    pub fn add(&mut self, image: &GrayImage) {
+        for pixel in image.pixels() {
+            self.bins[pixel[0] as usize] += 1;
         }
     }
 
-    bg_color
+    /// Most frequent value (likely background); 255 when empty.
+    pub fn mode(&self) -> u8 {
+        let mut max_count = 0;
+        let mut bg_color = 255u8;
+
+        for (color, &count) in self.bins.iter().enumerate() {
+            if count > max_count {
+                max_count = count;
+                bg_color = color as u8;
+            }
+        }
+
+        bg_color
+    }
 }
 
 /// Convert grayscale ASCII to RGBA with transparency.
 /// Pixels whose grayscale value is within `threshold` of `bg_color`
 /// (i.e. `|pixel - bg_color| <= threshold`) become fully transparent.
-/// Pass `threshold = 0` for exact-match behaviour.
-pub fn convert_to_transparent(source: &GrayImage, bg_color: u8, threshold: u8) -> RgbaImage {
+/// Pass `threshold = 0` for exact-match behaviour. With a non-zero `feather`,
+/// pixels up to `feather` beyond the threshold fade in instead of snapping to
+/// opaque, softening glyph edges. Transparent pixels keep `bg_color` as their
+/// gray, so bilinear scaling blends edges toward the background, not white.
+pub fn convert_to_transparent(source: &GrayImage, bg_color: u8, threshold: u8, feather: u8) -> RgbaImage {
     let mut rgba = RgbaImage::new(source.width(), source.height());
 
     for (x, y, pixel) in source.enumerate_pixels() {
         let luma = pixel[0];
-        let is_background = (luma as i16 - bg_color as i16).unsigned_abs() as u8 <= threshold;
 
         // If background, make transparent; otherwise, keep grayscale
-        let rgba_pixel = if is_background {
-            Rgba([255, 255, 255, 0]) // Fully transparent
-        } else {
-            Rgba([luma, luma, luma, 255]) // Opaque grayscale
+        let rgba_pixel = match key_alpha(luma, bg_color, threshold, feather) {
+            0 => Rgba([bg_color, bg_color, bg_color, 0]), // Fully transparent
+            alpha => Rgba([luma, luma, luma, alpha]),
         };
 
         rgba.put_pixel(x, y, rgba_pixel);
@@ -109,6 +1069,122 @@ pub fn convert_to_transparent(source: &GrayImage, bg_color: u8, threshold: u8) -
     rgba
 }
 
+/// Color counterpart of [`convert_to_transparent`]: pixels whose Rec. 709
+/// luma is within `threshold` of `bg_color` become transparent, the rest keep
+/// their color. `feather` ramps alpha the same way.
+pub fn convert_color_to_transparent(source: &RgbImage, bg_color: u8, threshold: u8, feather: u8) -> RgbaImage {
+    let mut rgba = RgbaImage::new(source.width(), source.height());
+
+    for (x, y, pixel) in source.enumerate_pixels() {
+        let [r, g, b] = pixel.0;
+        let luma = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8;
+
+        let alpha = key_alpha(luma, bg_color, threshold, feather);
+        rgba.put_pixel(x, y, Rgba([r, g, b, alpha]));
+    }
+
+    rgba
+}
+
+/// Opaque counterpart of [`convert_to_transparent`]: keyed background pixels
+/// are painted `fill` instead of becoming transparent, with `feather` blending
+/// glyph edges into the fill.
+pub fn convert_with_background(source: &GrayImage, bg_color: u8, threshold: u8, feather: u8, fill: Rgb<u8>) -> RgbaImage {
+    flatten_onto(convert_to_transparent(source, bg_color, threshold, feather), fill)
+}
+
+/// Composite `image` over a solid `fill`, leaving every pixel opaque.
+pub fn flatten_onto(mut image: RgbaImage, fill: Rgb<u8>) -> RgbaImage {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u16;
+        for channel in 0..3 {
+            let blended = (pixel[channel] as u16 * alpha + fill[channel] as u16 * (255 - alpha) + 127) / 255;
+            pixel[channel] = blended as u8;
+        }
+        pixel[3] = 255;
+    }
+    image
+}
+
+/// Make every pixel of `rgba` within `tolerance` of `target` in HSV space
+/// transparent, for colored backdrops (green screens) whose luma collides
+/// with the foreground. `tolerance` runs from 0 (exact) to 1 (everything):
+/// the largest of the hue, saturation and value differences, with hue
+/// weighted by saturation since it means little for near-grays.
+pub fn key_by_color(rgba: &RgbaImage, target: Rgb<u8>, tolerance: f32) -> RgbaImage {
+    let target = hsv(target.0);
+    let mut keyed = rgba.clone();
+    for pixel in keyed.pixels_mut() {
+        if hsv_distance(hsv([pixel[0], pixel[1], pixel[2]]), target) <= tolerance {
+            pixel[3] = 0;
+        }
+    }
+    keyed
+}
+
+/// Copy the alpha of `mask` onto `image`, stretching the mask with
+/// nearest-neighbor when the image was rendered at a larger scale.
+pub fn apply_alpha_mask(mut image: RgbaImage, mask: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let (mask_width, mask_height) = mask.dimensions();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let mx = (x as u64 * mask_width as u64 / width as u64).min(mask_width as u64 - 1) as u32;
+        let my = (y as u64 * mask_height as u64 / height as u64).min(mask_height as u64 - 1) as u32;
+        pixel[3] = mask.get_pixel(mx, my)[3];
+    }
+    image
+}
+
+/// Hue in degrees, saturation and value in 0..=1.
+fn hsv([r, g, b]: [u8; 3]) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue, saturation, max)
+}
+
+fn hsv_distance((h1, s1, v1): (f32, f32, f32), (h2, s2, v2): (f32, f32, f32)) -> f32 {
+    let hue = (h1 - h2).abs();
+    let hue = hue.min(360.0 - hue) / 180.0 * s1.min(s2);
+    hue.max((s1 - s2).abs()).max((v1 - v2).abs())
+}
+
+/// Alpha for a pixel `luma` when keying out `bg_color`: 0 within `threshold`,
+/// rising linearly to 255 over the next `feather` levels.
+fn key_alpha(luma: u8, bg_color: u8, threshold: u8, feather: u8) -> u8 {
+    let distance = (luma as i16 - bg_color as i16).unsigned_abs();
+    let beyond = distance.saturating_sub(threshold as u16);
+    if distance <= threshold as u16 {
+        0
+    } else if beyond < feather as u16 {
+        (beyond * 255 / feather as u16) as u8
+    } else {
+        255
+    }
+}
+
+/// Stretch `luma` piecewise-linearly so `threshold` lands on mid-gray, moving
+/// the dark/light split of the charset with it.
+fn shift_midpoint(luma: u8, threshold: u8) -> u8 {
+    let (luma, threshold) = (luma as f32, threshold.clamp(1, 254) as f32);
+    let shifted = if luma < threshold {
+        luma * 128.0 / threshold
+    } else {
+        128.0 + (luma - threshold) * 127.0 / (255.0 - threshold)
+    };
+    shifted.round() as u8
+}
+
 fn enhance_contrast(luma: u8) -> u8 {
     // Apply mild contrast stretch to make edges more visible
     let f = luma as f32 / 255.0;
@@ -116,18 +1192,20 @@ fn enhance_contrast(luma: u8) -> u8 {
     (enhanced * 255.0) as u8
 }
 
-fn average_luma(image: &GrayImage, x0: u32, x1: u32, y0: u32, y1: u32) -> u8 {
+/// Mean luma of the region, reading every `stride`th pixel along each axis.
+fn average_luma(image: &GrayImage, x0: u32, x1: u32, y0: u32, y1: u32, stride: u32) -> u8 {
+    let stride = stride.max(1) as usize;
     let mut sum: u64 = 0;
     let mut count: u64 = 0;
 
-    for y in y0..y1.min(image.height()) {
-        for x in x0..x1.min(image.width()) {
+    for y in (y0..y1.min(image.height())).step_by(stride) {
+        for x in (x0..x1.min(image.width())).step_by(stride) {
             sum += image.get_pixel(x, y)[0] as u64;
             count += 1;
         }
     }
 
-    if count == 0 { 0 } else { (sum / count) as u8 }
+    sum.checked_div(count).unwrap_or(0) as u8
 }
 
 fn map_luma_to_char(luma: u8, charset: &[char]) -> char {
@@ -136,41 +1214,65 @@ fn map_luma_to_char(luma: u8, charset: &[char]) -> char {
     charset[idx]
 }
 
-fn draw_glyph_gray(canvas: &mut GrayImage, x: u32, y: u32, ch: char, brightness: u8, num_shades: u32) {
-    let fallback = font8x8::BASIC_FONTS.get('?').unwrap_or([0; 8]);
-    let glyph = font8x8::BASIC_FONTS.get(ch).unwrap_or(fallback);
-    
+/// Luma of glyph-off pixels: white normally, black when inverted.
+pub fn background_luma(invert: bool) -> u8 {
+    if invert { 0 } else { 255 }
+}
+
+/// 8x8 bitmap for `ch` from the font8x8 tables that cover ASCII, Latin-1,
+/// block and box-drawing characters; anything else is drawn as `fallback`,
+/// or as `?` when the fallback has no glyph either.
+fn glyph_bitmap(ch: char, fallback: char) -> [u8; 8] {
+    find_glyph(ch)
+        .or_else(|| find_glyph(fallback))
+        .or_else(|| font8x8::BASIC_FONTS.get(DEFAULT_FALLBACK_CHAR))
+        .unwrap_or([0; 8])
+}
+
+/// Whether the built-in font can draw `ch`.
+pub fn has_glyph(ch: char) -> bool {
+    find_glyph(ch).is_some()
+}
+
+/// 8x8 bitmap for `ch`, or `None` when no font8x8 table covers it.
+fn find_glyph(ch: char) -> Option<[u8; 8]> {
+    font8x8::BASIC_FONTS
+        .get(ch)
+        .or_else(|| font8x8::LATIN_FONTS.get(ch))
+        .or_else(|| font8x8::BLOCK_FONTS.get(ch))
+        .or_else(|| font8x8::BOX_FONTS.get(ch))
+}
+
+/// Gray value for glyph pixels of a cell with the given brightness.
+fn shade_value(brightness: u8, num_shades: u32, bw_threshold: u8) -> u8 {
     // Map brightness (0-255) to grayscale value based on num_shades
     // More shades = smoother gradients, fewer shades = more contrasty
     let shade_step = 255.0 / (num_shades as f32 - 1.0);
     let shade_index = (brightness as f32 / shade_step).round().clamp(0.0, num_shades as f32 - 1.0) as u8;
-    let pixel_value = if num_shades == 2 {
+    if num_shades == 2 {
         // For 2 shades, use pure B/W for maximum contrast
-        if brightness < 128 { 0 } else { 255 }
+        if brightness < bw_threshold { 0 } else { 255 }
     } else {
         // For 3+ shades, use actual grayscale
         (shade_index as f32 * 255.0 / (num_shades as f32 - 1.0)).round() as u8
-    };
-
-    for (gy, row_bits) in glyph.iter().enumerate() {
-        for gx in 0..8_u32 {
-            let bit_on = (row_bits >> gx) & 1 == 1;
-            // If bit is on, use the brightness value; if off, use white (255)
-            let value = if bit_on { pixel_value } else { 255 };
-            canvas.put_pixel(x + gx, y + gy as u32, Luma([value]));
-        }
     }
 }
 
-fn draw_glyph_bw(canvas: &mut GrayImage, x: u32, y: u32, ch: char) {
-    let fallback = font8x8::BASIC_FONTS.get('?').unwrap_or([0; 8]);
-    let glyph = font8x8::BASIC_FONTS.get(ch).unwrap_or(fallback);
+/// Draw `ch` with its top-left corner at (`x`, `y`), each font bit becoming a
+/// `scale` x `scale` block of `value` (nearest-neighbor upscaling).
+fn draw_glyph(canvas: &mut GrayImage, x: u32, y: u32, ch: char, value: u8, options: &AsciiOptions, scale: u32) {
+    let glyph = options.font.rows(ch, options.fallback_char);
 
     for (gy, row_bits) in glyph.iter().enumerate() {
         for gx in 0..8_u32 {
             let bit_on = (row_bits >> gx) & 1 == 1;
-            let value = if bit_on { 0 } else { 255 };
-            canvas.put_pixel(x + gx, y + gy as u32, Luma([value]));
+            // If bit is on, use the glyph value; if off, use the background
+            let value = if bit_on { value } else { background_luma(options.invert) };
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    canvas.put_pixel(x + gx * scale + dx, y + gy as u32 * scale + dy, Luma([value]));
+                }
+            }
         }
     }
 }
@@ -186,6 +1288,54 @@ mod tests {
         assert_eq!(map_luma_to_char(255, &charset), ' ');
     }
 
+    #[test]
+    fn validate_charset_flags_degenerate_ramps() {
+        assert_eq!(validate_charset("@%#*+=-:. ", 1, None).unwrap(), vec![]);
+        assert_eq!(validate_charset("", 1, None).unwrap(), vec![CharsetWarning::EmptyFallback]);
+        assert_eq!(validate_charset("#", 1, None).unwrap(), vec![CharsetWarning::SingleStep('#')]);
+        assert_eq!(validate_charset("##", 4, None).unwrap(), vec![CharsetWarning::SingleStep('#')]);
+        assert_eq!(
+            validate_charset("@. ", 8, None).unwrap(),
+            vec![CharsetWarning::FewerStepsThanShades { steps: 3, shades: 8 }]
+        );
+    }
+
+    #[test]
+    fn order_by_ink_sorts_a_scrambled_ramp_dark_to_light() {
+        assert_eq!(order_by_ink(". :@", DEFAULT_FALLBACK_CHAR), "@:. ");
+        assert_eq!(order_by_ink(" #", DEFAULT_FALLBACK_CHAR), "# ");
+        assert_eq!(order_by_ink("", DEFAULT_FALLBACK_CHAR), "");
+    }
+
+    #[test]
+    fn validate_charset_rejects_whitespace_only() {
+        assert!(matches!(validate_charset("   ", 1, None), Err(AppError::BlankCharset)));
+        assert!(matches!(validate_charset("\t", 1, None), Err(AppError::BlankCharset)));
+    }
+
+    #[test]
+    fn validate_charset_rejects_glyphs_without_bitmaps() {
+        assert!(matches!(validate_charset("🎥🙂 ", 1, None), Err(AppError::UnsupportedChar { ch: '🎥' })));
+        assert!(matches!(validate_charset("@一 ", 1, None), Err(AppError::UnsupportedChar { ch: '一' })));
+        // Space, Latin-1, block and box-drawing characters all have glyphs
+        assert!(validate_charset("█▓▒░─é· ", 1, None).is_ok());
+    }
+
+    #[test]
+    fn unrenderable_chars_draw_the_fallback_glyph() {
+        let source = GrayImage::from_pixel(8, 8, Luma([0]));
+        let draw = |charset: &str, fallback_char| {
+            let options = AsciiOptions { fallback_char, ..AsciiOptions::new(1, charset, 1) };
+            convert_frame_to_ascii(&source, &options)
+        };
+        assert_eq!(draw("一 ", '█'), draw("█ ", '?'));
+        assert_eq!(draw("一 ", ' '), draw("  ", '?'));
+        assert_eq!(draw("一 ", '?'), draw("? ", '?'));
+
+        assert_eq!(validate_charset("@一 ", 1, Some(' ')).unwrap(), vec![CharsetWarning::Substituted { ch: '一', fallback: ' ' }]);
+        assert!(matches!(validate_charset("@ ", 1, Some('🙂')), Err(AppError::UnsupportedFallback { ch: '🙂' })));
+    }
+
     #[test]
     fn conversion_creates_expected_dimensions() {
         let source = GrayImage::from_pixel(64, 32, Luma([120]));
@@ -198,6 +1348,185 @@ mod tests {
         assert_eq!(output.height(), 4 * 8);
     }
 
+    #[test]
+    fn raising_bw_threshold_darkens_mid_gray() {
+        let source = GrayImage::from_pixel(64, 64, Luma([150]));
+        let mean = |image: &GrayImage| image.pixels().map(|p| p[0] as f64).sum::<f64>() / image.len() as f64;
+
+        for shades in [1, 2] {
+            let default = AsciiOptions::new(8, DEFAULT_CHARSET, shades);
+            let high = AsciiOptions { bw_threshold: 200, ..default.clone() };
+            let normal = convert_frame_to_ascii(&source, &default);
+            let darker = convert_frame_to_ascii(&source, &high);
+            assert!(mean(&darker) < mean(&normal), "{shades} shades did not darken");
+        }
+
+        assert_eq!(shift_midpoint(200, 200), 128);
+        assert_eq!(shift_midpoint(0, 200), 0);
+        assert_eq!(shift_midpoint(255, 200), 255);
+    }
+
+    #[test]
+    fn disabling_contrast_keeps_raw_cell_luma() {
+        let options = AsciiOptions { contrast: false, ..AsciiOptions::new(1, DEFAULT_CHARSET, 4) };
+
+        // Mid-gray lands exactly on the middle of the 9-step ramp
+        let grid = char_grid(&GrayImage::from_pixel(8, 8, Luma([128])), &options, None);
+        assert_eq!(grid.cells, vec![128]);
+        assert_eq!(grid.chars, vec!['=']);
+
+        // Below mid-gray the stretch would push the cell a step darker
+        let source = GrayImage::from_pixel(8, 8, Luma([100]));
+        assert_eq!(char_grid(&source, &options, None).chars, vec!['+']);
+        let stretched = AsciiOptions { contrast: true, ..options };
+        assert_eq!(char_grid(&source, &stretched, None).chars, vec!['*']);
+    }
+
+    #[test]
+    fn high_shade_counts_skip_the_contrast_stretch() {
+        let gradient = GrayImage::from_fn(64, 8, |x, _| Luma([(x * 4) as u8]));
+        let cells = |shades| char_grid(&gradient, &AsciiOptions::new(8, DEFAULT_CHARSET, shades), None).cells;
+        let raw: Vec<u8> = (0..8).map(|col| average_luma(&gradient, col * 8, col * 8 + 8, 0, 8, 1)).collect();
+
+        assert_eq!(cells(32), raw);
+        assert_eq!(cells(TONAL_SHADES), raw);
+        let stretched: Vec<u8> = raw.iter().map(|&luma| enhance_contrast(luma)).collect();
+        assert_eq!(cells(TONAL_SHADES - 1), stretched);
+        assert_ne!(stretched, raw);
+    }
+
+    #[test]
+    fn two_colors_per_cell_split_ink_and_paper() {
+        // One cell, dark blue on top and yellow below
+        let navy = Rgb([0, 0, 128]);
+        let yellow = Rgb([255, 255, 0]);
+        let source = RgbImage::from_fn(8, 8, |_, y| if y < 4 { navy } else { yellow });
+        let gray = DynamicImage::ImageRgb8(source.clone()).to_luma8();
+        let colors = |count, invert| {
+            let options = AsciiOptions { max_colors_per_cell: Some(count), invert, ..AsciiOptions::new(1, "#", 1) };
+            let ascii = convert_frame_to_ascii(&gray, &options);
+            colorize_glyphs(&ascii, &source, &options).pixels().copied().collect::<HashSet<_>>()
+        };
+
+        // Ink takes the darkest color, the glyph's unset bits the lightest
+        assert_eq!(colors(2, false), HashSet::from([navy, yellow]));
+        assert_eq!(colors(2, true), HashSet::from([navy, yellow]));
+        // One color: ink in the cell average on the white background
+        let average = average_rgb(&source, 0, 8, 0, 8);
+        assert_eq!(colors(1, false), HashSet::from([average, Rgb([255; 3])]));
+    }
+
+    #[test]
+    fn sharpening_spreads_cell_luma_on_a_soft_edge() {
+        // A mid-gray step on a cell boundary, softened over a few pixels
+        let edge = GrayImage::from_fn(64, 8, |x, _| Luma([if x < 32 { 64 } else { 192 }]));
+        let soft = DynamicImage::ImageLuma8(edge).blur(2.0);
+        let options = AsciiOptions { contrast: false, ..AsciiOptions::new(8, DEFAULT_CHARSET, 32) };
+        let variance = |image: &DynamicImage| {
+            let cells = char_grid(&image.to_luma8(), &options, None).cells;
+            let mean = cells.iter().map(|&luma| luma as f32).sum::<f32>() / cells.len() as f32;
+            cells.iter().map(|&luma| (luma as f32 - mean).powi(2)).sum::<f32>() / cells.len() as f32
+        };
+
+        assert!(variance(&sharpen(&soft, 1.5)) > variance(&soft));
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(16, 16, Luma([90])));
+        assert_eq!(sharpen(&flat, 1.5).to_luma8(), flat.to_luma8());
+    }
+
+    #[test]
+    fn strided_average_tracks_full_average_on_gradient() {
+        let gradient = GrayImage::from_fn(64, 64, |x, y| Luma([(x * 2 + y * 2) as u8]));
+
+        for stride in [2, 3, 4] {
+            for (x0, y0) in [(0, 0), (16, 8), (40, 48)] {
+                let full = average_luma(&gradient, x0, x0 + 16, y0, y0 + 16, 1) as i32;
+                let sampled = average_luma(&gradient, x0, x0 + 16, y0, y0 + 16, stride) as i32;
+                assert!((full - sampled).abs() <= 2 * stride as i32, "stride {stride}: {full} vs {sampled}");
+            }
+        }
+    }
+
+    #[test]
+    fn partial_edge_cells_are_kept() {
+        // 70x13: a 6px strip on the right and a 5px strip at the bottom
+        let source = GrayImage::from_pixel(70, 13, Luma([0]));
+        let options = AsciiOptions::new(16, "@ ", 1);
+        let output = convert_frame_to_ascii(&source, &options);
+        assert_eq!(output.dimensions(), (70, 13));
+
+        // The partial cells are drawn from their own (dark) pixels, not left blank
+        let inked = |x0: u32, y0: u32| (y0..13).any(|y| (x0..70).any(|x| output.get_pixel(x, y)[0] == 0));
+        assert!(inked(64, 0), "right strip dropped");
+        assert!(inked(0, 8), "bottom strip dropped");
+
+        let scaled = AsciiOptions { font_scale: 2, ..options.clone() };
+        assert_eq!(convert_frame_to_ascii(&source, &scaled).dimensions(), (140, 26));
+        let blocks = AsciiOptions { mode: RenderMode::Blocks, ..options };
+        assert_eq!(convert_frame_to_ascii(&source, &blocks).dimensions(), (70, 13));
+    }
+
+    #[test]
+    fn center_focus_keeps_full_detail_only_in_the_middle() {
+        // 8px checkerboard: every fine cell flips, every coarse 16px cell averages to gray
+        let source = GrayImage::from_fn(64, 64, |x, y| Luma([if (x / 8 + y / 8) % 2 == 0 { 0 } else { 255 }]));
+        let options = AsciiOptions::new(8, "@ ", 1);
+        let fine = convert_frame_to_ascii(&source, &options);
+        let focused = convert_frame_to_ascii(&source, &AsciiOptions { focus: Focus::Center, ..options.clone() });
+        assert_eq!(focused.dimensions(), fine.dimensions());
+
+        let block = |image: &GrayImage, x0: u32, y0: u32| -> Vec<u8> {
+            (y0..y0 + 16).flat_map(|y| (x0..x0 + 16).map(move |x| (x, y))).map(|(x, y)| image.get_pixel(x, y)[0]).collect()
+        };
+        assert_eq!(block(&focused, 16, 16), block(&fine, 16, 16), "center should match the uniform render");
+        assert_ne!(block(&focused, 0, 0), block(&fine, 0, 0), "corner should use coarse cells");
+
+        let everywhere = AsciiOptions { focus: Focus::Center, focus_falloff: 1.0, ..options };
+        assert_eq!(convert_frame_to_ascii(&source, &everywhere), fine);
+    }
+
+    #[test]
+    fn prefilter_steadies_noisy_cells() {
+        // Two-tone checkerboard of 32px squares with 40% salt-and-pepper noise
+        let mut seed = 7u32;
+        let noisy = GrayImage::from_fn(128, 128, |x, y| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            match (seed >> 16) % 5 {
+                0 => Luma([0]),
+                1 => Luma([255]),
+                _ => Luma([if (x / 32 + y / 32) % 2 == 0 { 100 } else { 160 }]),
+            }
+        });
+        let options = AsciiOptions::new(16, "@#-. ", 1);
+        let distinct = |image: &DynamicImage| {
+            let text = convert_frame_to_text(&image.to_luma8(), &options);
+            text.chars().filter(|&ch| ch != '\n').collect::<HashSet<char>>().len()
+        };
+
+        let raw = DynamicImage::ImageLuma8(noisy);
+        assert!(distinct(&raw) > 2, "noise should scatter the characters");
+        // Filtered, each tone settles on a single character
+        assert_eq!(distinct(&Prefilter::Blur.apply(&raw, 2.0)), 2);
+        assert_eq!(distinct(&Prefilter::Median.apply(&raw, 2.0)), 2);
+        assert_eq!(Prefilter::None.apply(&raw, 2.0), raw);
+    }
+
+    #[test]
+    fn temporal_smoothing_holds_characters_through_small_changes() {
+        let mut options = AsciiOptions::new(2, "@#-. ", 1);
+        options.contrast = false;
+        // 124 and 131 straddle the '#'/'-' boundary at 127.5
+        let frames = [GrayImage::from_pixel(16, 16, Luma([124])), GrayImage::from_pixel(16, 16, Luma([131]))];
+        assert_ne!(
+            convert_frame_to_ascii(&frames[0], &options),
+            convert_frame_to_ascii(&frames[1], &options)
+        );
+
+        let mut history = CellHistory::new(0.3);
+        let first = convert_frame_to_ascii_smoothed(&frames[0], &options, &mut history);
+        let second = convert_frame_to_ascii_smoothed(&frames[1], &options, &mut history);
+        assert_eq!(first, second);
+    }
+
     #[test]
     fn conversion_is_strictly_black_and_white() {
         let mut source = GrayImage::from_pixel(16, 16, Luma([0]));
@@ -215,6 +1544,264 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invert_turns_all_white_output_all_black() {
+        let source = GrayImage::from_pixel(16, 16, Luma([255]));
+        let options = AsciiOptions::new(2, " ", 1);
+        assert!(convert_frame_to_ascii(&source, &options).pixels().all(|p| p[0] == 255));
+
+        let inverted = AsciiOptions { invert: true, ..options };
+        assert!(convert_frame_to_ascii(&source, &inverted).pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn invert_puts_dense_glyphs_on_bright_regions() {
+        let source = GrayImage::from_pixel(8, 8, Luma([255]));
+        let options = AsciiOptions { invert: true, ..AsciiOptions::new(1, "@ ", 1) };
+        let output = convert_frame_to_ascii(&source, &options);
+
+        // Bright input maps to '@', whose lit pixels are white on black
+        assert!(output.pixels().any(|p| p[0] == 255));
+        assert!(output.pixels().any(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn luma_weightings_differ_on_saturated_colors() {
+        let red = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255]));
+        assert_eq!(luma_709(&red).get_pixel(0, 0)[0], 54);
+        assert_eq!(luma_601(&red).get_pixel(0, 0)[0], 76);
+        assert_eq!(luma_average(&red).get_pixel(0, 0)[0], 85);
+
+        let white = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        for gray in [luma_709(&white), luma_601(&white), luma_average(&white)] {
+            assert_eq!(gray.get_pixel(0, 0)[0], 255);
+        }
+    }
+
+    #[test]
+    fn histogram_mode_accumulates_across_frames() {
+        let mut histogram = LumaHistogram::default();
+        assert_eq!(histogram.mode(), 255);
+
+        // One frame is mostly 10, the other two mostly 200
+        let mut title = GrayImage::from_pixel(4, 4, Luma([10]));
+        title.put_pixel(0, 0, Luma([200]));
+        histogram.add(&title);
+        assert_eq!(histogram.mode(), 10);
+
+        histogram.add(&GrayImage::from_pixel(4, 4, Luma([200])));
+        histogram.add(&GrayImage::from_pixel(4, 4, Luma([200])));
+        assert_eq!(histogram.mode(), 200);
+    }
+
+    #[test]
+    fn floyd_dither_mixes_characters_across_flat_row() {
+        // Vertical gradient: each row is flat, brightness increases downwards
+        let columns = 8;
+        let cells: Vec<u8> = (0..4).flat_map(|row| vec![60 + row * 40; columns]).collect();
+
+        let plain = AsciiOptions::new(columns as u32, "@ ", 1);
+        let dithered = AsciiOptions { dither: Dither::Floyd, ..plain.clone() };
+
+        // Second row: receives error diffused down from the first
+        let row = |chars: Vec<char>| -> Vec<char> { chars[columns..2 * columns].to_vec() };
+        let distinct = |row: &[char]| row.iter().collect::<std::collections::HashSet<_>>().len();
+
+        assert_eq!(distinct(&row(select_chars(&cells, columns, &plain))), 1);
+        assert!(distinct(&row(select_chars(&cells, columns, &dithered))) > 1);
+    }
+
+    #[test]
+    fn atkinson_diffuses_less_error_further_than_floyd() {
+        // One column: Floyd pushes 5/16 of the error one row down, Atkinson
+        // 1/8 to each of the next two rows
+        let column = [110, 110, 110];
+        assert_eq!(diffuse_error(&column, 1, 2, FLOYD_STEINBERG), [0, 1, 0]);
+        assert_eq!(diffuse_error(&column, 1, 2, ATKINSON), [0, 0, 1]);
+
+        // A flat dark row: with a quarter of the error dropped per cell,
+        // Atkinson lights far fewer cells
+        let row = [96; 8];
+        assert_eq!(diffuse_error(&row, 8, 2, FLOYD_STEINBERG), [0, 1, 0, 0, 1, 0, 0, 1]);
+        assert_eq!(diffuse_error(&row, 8, 2, ATKINSON), [0, 0, 0, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn sobel_highlights_edges_and_clamps_borders() {
+        let mut source = GrayImage::from_pixel(8, 4, Luma([0]));
+        for y in 0..4 {
+            for x in 4..8 {
+                source.put_pixel(x, y, Luma([255]));
+            }
+        }
+
+        let magnitude = sobel_magnitude(&source);
+        assert_eq!(magnitude.get_pixel(0, 0)[0], 0, "flat area has no gradient");
+        assert_eq!(magnitude.get_pixel(7, 3)[0], 0, "clamped border stays flat");
+        assert_eq!(magnitude.get_pixel(3, 2)[0], 255, "step edge saturates");
+        assert_eq!(magnitude.get_pixel(4, 0)[0], 255, "edge at top border");
+    }
+
+    #[test]
+    fn edge_mode_leaves_flat_areas_blank() {
+        let source = GrayImage::from_pixel(16, 16, Luma([90]));
+        let options = AsciiOptions { mode: RenderMode::Edges, ..AsciiOptions::new(2, "@ ", 1) };
+        assert!(convert_frame_to_ascii(&source, &options).pixels().all(|p| p[0] == 255));
+
+        let inverted = AsciiOptions { invert: true, ..options };
+        assert!(convert_frame_to_ascii(&source, &inverted).pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn block_and_latin_glyphs_have_bitmaps() {
+        let unknown = glyph_bitmap('\u{4e00}', DEFAULT_FALLBACK_CHAR);
+        assert_eq!(glyph_bitmap('█', DEFAULT_FALLBACK_CHAR), [0xff; 8]);
+        for ch in ['▓', '▒', '░', '°', '·'] {
+            assert_ne!(glyph_bitmap(ch, DEFAULT_FALLBACK_CHAR), unknown, "{ch} falls back to ?");
+        }
+    }
+
+    #[test]
+    fn blocks_mode_fills_dark_quadrants() {
+        // Left half dark, right half bright: every cell becomes a left half block
+        let mut source = GrayImage::from_pixel(16, 8, Luma([255]));
+        for y in 0..8 {
+            for x in 0..4 {
+                source.put_pixel(x, y, Luma([0]));
+                source.put_pixel(x + 8, y, Luma([0]));
+            }
+        }
+        let options = AsciiOptions { mode: RenderMode::Blocks, ..AsciiOptions::new(2, "@ ", 1) };
+        let output = convert_frame_to_ascii(&source, &options);
+
+        assert_eq!(output.dimensions(), (16, 8));
+        assert_eq!(output.get_pixel(0, 7)[0], 0);
+        assert_eq!(output.get_pixel(4, 0)[0], 255);
+        assert_eq!(output.get_pixel(11, 3)[0], 0);
+
+        // All quarters on → full block; all off → blank cell
+        let black = GrayImage::from_pixel(8, 8, Luma([0]));
+        assert!(convert_frame_to_ascii(&black, &options).pixels().all(|p| p[0] == 0));
+        let white = GrayImage::from_pixel(8, 8, Luma([255]));
+        assert!(convert_frame_to_ascii(&white, &options).pixels().all(|p| p[0] == 255));
+
+        // Inverted: bright quarters are inked white on black
+        let inverted = AsciiOptions { invert: true, ..options };
+        assert!(convert_frame_to_ascii(&white, &inverted).pixels().all(|p| p[0] == 255));
+        assert!(convert_frame_to_ascii(&black, &inverted).pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn bold_font_inks_at_least_as_many_pixels() {
+        let inked = |font: GlyphFont, ch: char| {
+            let options = AsciiOptions { font, ..AsciiOptions::new(1, "@", 1) };
+            let mut canvas = GrayImage::from_pixel(CELL_SIZE, CELL_SIZE, Luma([255]));
+            draw_glyph(&mut canvas, 0, 0, ch, 0, &options, 1);
+            canvas.pixels().filter(|p| p[0] == 0).count()
+        };
+
+        for ch in ['@', 'i', '.', '#', '?'] {
+            let (regular, bold) = (inked(GlyphFont::Regular, ch), inked(GlyphFont::BuiltinBold, ch));
+            assert!(bold >= regular, "{ch}: bold {bold} < regular {regular}");
+        }
+        assert!(inked(GlyphFont::BuiltinBold, 'i') > inked(GlyphFont::Regular, 'i'));
+    }
+
+    #[test]
+    fn font_scale_multiplies_output_size() {
+        let source = GrayImage::from_pixel(64, 32, Luma([0]));
+        let plain = AsciiOptions::new(8, "@ ", 1);
+        let scaled = AsciiOptions { font_scale: 2, ..plain.clone() };
+
+        let base = convert_frame_to_ascii(&source, &plain);
+        let output = convert_frame_to_ascii(&source, &scaled);
+        assert_eq!(output.dimensions(), (base.width() * 2, base.height() * 2));
+
+        // Every font pixel becomes a 2x2 block
+        for (x, y, pixel) in base.enumerate_pixels() {
+            assert_eq!(output.get_pixel(x * 2 + 1, y * 2 + 1), pixel);
+        }
+
+        let color = RgbImage::from_pixel(64, 32, Rgb([10, 20, 30]));
+        assert_eq!(convert_frame_to_halfblock(&color, &scaled).dimensions(), (128, 64));
+    }
+
+    #[test]
+    fn halfblock_splits_cells_into_top_and_bottom_colors() {
+        let mut source = RgbImage::from_pixel(16, 9, Rgb([255, 0, 0]));
+        for y in 4..9 {
+            for x in 0..16 {
+                source.put_pixel(x, y, Rgb([0, 0, 255]));
+            }
+        }
+
+        let options = AsciiOptions { mode: RenderMode::Halfblock, ..AsciiOptions::new(2, "@ ", 1) };
+        let output = convert_frame_to_halfblock(&source, &options);
+
+        // 16x9 → 2x2 cells (the second row partial) → 16x9 pixels
+        assert_eq!(output.dimensions(), (16, 9));
+        assert_eq!(output.get_pixel(0, 0), &Rgb([255, 0, 0]));
+        assert_eq!(output.get_pixel(15, 3), &Rgb([255, 0, 0]));
+        assert_eq!(output.get_pixel(0, 4), &Rgb([0, 0, 255]));
+        assert_eq!(output.get_pixel(9, 7), &Rgb([0, 0, 255]));
+
+        let inverted = AsciiOptions { invert: true, ..options };
+        assert_eq!(convert_frame_to_halfblock(&source, &inverted).get_pixel(0, 0), &Rgb([0, 255, 255]));
+    }
+
+    #[test]
+    fn palette_limits_halfblock_output_to_its_colors() {
+        let source = RgbImage::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8]));
+        let options = AsciiOptions { mode: RenderMode::Halfblock, ..AsciiOptions::new(8, "@ ", 1) };
+
+        for (palette, size) in [(Palette::MedianCut(4), 4), (Palette::Ansi16, 16)] {
+            let colors = palette.colors(&source, &options);
+            assert_eq!(colors.len(), size);
+            let quantized = AsciiOptions { palette: Some(colors.clone()), ..options.clone() };
+            let used: HashSet<Rgb<u8>> = convert_frame_to_halfblock(&source, &quantized).pixels().copied().collect();
+            assert!(used.iter().all(|color| colors.contains(color)), "{palette:?}: {used:?}");
+            assert!(used.len() > 1);
+        }
+    }
+
+    #[test]
+    fn cell_padding_adds_a_gutter_per_column_and_row() {
+        let source = GrayImage::from_pixel(64, 44, Luma([0]));
+        let options = AsciiOptions::new(8, "@ ", 1);
+        assert_eq!(convert_frame_to_ascii(&source, &options).dimensions(), (64, 44));
+
+        // 8 columns and 6 rows, the last one partial
+        let padded = AsciiOptions { cell_padding: 1, ..options.clone() };
+        let output = convert_frame_to_ascii(&source, &padded);
+        assert_eq!(output.dimensions(), (64 + 8, 44 + 6));
+        assert_eq!(output.get_pixel(8, 3)[0], 255, "gutter after the first column");
+        assert_eq!(output.get_pixel(3, 8)[0], 255, "gutter after the first row");
+
+        let blocks = AsciiOptions { mode: RenderMode::Blocks, font_scale: 2, cell_padding: 2, ..options.clone() };
+        assert_eq!(convert_frame_to_ascii(&source, &blocks).dimensions(), (128 + 16, 88 + 12));
+        let color = RgbImage::from_pixel(64, 44, Rgb([10, 200, 30]));
+        assert_eq!(convert_frame_to_halfblock(&color, &padded).dimensions(), (72, 50));
+    }
+
+    #[test]
+    fn median_cut_never_invents_more_colors_than_it_sees() {
+        let colors = [Rgb([10, 20, 30]), Rgb([10, 20, 30]), Rgb([200, 0, 0])];
+        assert_eq!(median_cut(&colors, 8), vec![Rgb([10, 20, 30]), Rgb([200, 0, 0])]);
+        assert_eq!(median_cut(&colors, 1), vec![Rgb([73, 13, 20])]);
+        assert!(median_cut(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn color_transparency_keys_on_luma() {
+        let mut img = RgbImage::new(2, 1);
+        img.put_pixel(0, 0, Rgb([255, 255, 255]));
+        img.put_pixel(1, 0, Rgb([255, 0, 0]));
+
+        let rgba = convert_color_to_transparent(&img, 255, 0, 0);
+        assert_eq!(rgba.get_pixel(0, 0)[3], 0);
+        assert_eq!(rgba.get_pixel(1, 0), &Rgba([255, 0, 0, 255]));
+    }
+
     #[test]
     fn transparent_exact_match_makes_bg_transparent() {
         // 4x1 image: pixels 0, 100, 200, 255
@@ -224,7 +1811,7 @@ mod tests {
         img.put_pixel(2, 0, Luma([200]));
         img.put_pixel(3, 0, Luma([255]));
 
-        let rgba = convert_to_transparent(&img, 255, 0);
+        let rgba = convert_to_transparent(&img, 255, 0, 0);
 
         // Only pixel at value 255 should be transparent
         assert_eq!(rgba.get_pixel(0, 0)[3], 255, "pixel 0 should be opaque");
@@ -233,6 +1820,20 @@ mod tests {
         assert_eq!(rgba.get_pixel(3, 0)[3], 0, "pixel 255 (bg) should be transparent");
     }
 
+    #[test]
+    fn transparent_pixels_carry_the_background_gray() {
+        let mut img = GrayImage::from_pixel(2, 1, Luma([0]));
+        img.put_pixel(1, 0, Luma([255]));
+
+        // Inverted render: black background, white glyphs
+        let rgba = convert_to_transparent(&img, 0, 0, 0);
+        assert_eq!(rgba.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(rgba.get_pixel(1, 0), &Rgba([255, 255, 255, 255]));
+
+        let rgba = convert_to_transparent(&GrayImage::from_pixel(1, 1, Luma([40])), 30, 20, 0);
+        assert_eq!(rgba.get_pixel(0, 0), &Rgba([30, 30, 30, 0]));
+    }
+
     #[test]
     fn transparent_threshold_removes_nearby_colors() {
         // bg_color = 240, threshold = 20 → values 220..=255 become transparent
@@ -242,11 +1843,73 @@ mod tests {
         img.put_pixel(2, 0, Luma([255])); // within threshold (255 - 240 = 15 <= 20)
         img.put_pixel(3, 0, Luma([100])); // well outside threshold
 
-        let rgba = convert_to_transparent(&img, 240, 20);
+        let rgba = convert_to_transparent(&img, 240, 20, 0);
 
         assert_eq!(rgba.get_pixel(0, 0)[3], 255, "219 should be opaque");
         assert_eq!(rgba.get_pixel(1, 0)[3], 0, "220 should be transparent (|220-240|=20)");
         assert_eq!(rgba.get_pixel(2, 0)[3], 0, "255 should be transparent (|255-240|=15)");
         assert_eq!(rgba.get_pixel(3, 0)[3], 255, "100 should be opaque");
     }
+
+    #[test]
+    fn flattened_background_takes_fill_color() {
+        let mut img = GrayImage::from_pixel(3, 1, Luma([255]));
+        img.put_pixel(1, 0, Luma([0]));
+        img.put_pixel(2, 0, Luma([100]));
+
+        let fill = Rgb([0, 64, 128]);
+        let rgba = convert_with_background(&img, 255, 0, 0, fill);
+        assert_eq!(rgba.get_pixel(0, 0), &Rgba([0, 64, 128, 255]), "background takes the fill");
+        assert_eq!(rgba.get_pixel(1, 0), &Rgba([0, 0, 0, 255]), "glyphs keep their gray");
+        assert_eq!(rgba.get_pixel(2, 0), &Rgba([100, 100, 100, 255]));
+
+        // Feathered edges blend between glyph and fill
+        let feathered = convert_with_background(&GrayImage::from_pixel(1, 1, Luma([250])), 255, 0, 10, fill);
+        let pixel = feathered.get_pixel(0, 0);
+        assert_eq!(pixel[3], 255);
+        assert!(pixel[1] > 64 && pixel[1] < 250, "{pixel:?}");
+    }
+
+    #[test]
+    fn feather_ramps_alpha_past_threshold() {
+        // bg_color = 240, threshold = 20, feather = 10 → 219..=211 fade in
+        let mut img = GrayImage::new(4, 1);
+        img.put_pixel(0, 0, Luma([220])); // at the threshold
+        img.put_pixel(1, 0, Luma([219])); // just beyond it
+        img.put_pixel(2, 0, Luma([215])); // halfway through the feather
+        img.put_pixel(3, 0, Luma([210])); // past the feather
+
+        let rgba = convert_to_transparent(&img, 240, 20, 10);
+        let alphas: Vec<u8> = (0..4).map(|x| rgba.get_pixel(x, 0)[3]).collect();
+
+        assert_eq!(alphas[0], 0);
+        assert!(alphas[1] > 0 && alphas[1] < 255, "just past threshold should be partial: {}", alphas[1]);
+        assert!(alphas[1] < alphas[2] && alphas[2] < 255, "alpha should keep rising: {alphas:?}");
+        assert_eq!(alphas[3], 255);
+    }
+
+    #[test]
+    fn key_by_color_removes_green_and_keeps_red() {
+        let mut img = RgbaImage::from_pixel(4, 1, Rgba([0, 255, 0, 255]));
+        img.put_pixel(1, 0, Rgba([20, 235, 30, 255])); // slightly off green
+        img.put_pixel(2, 0, Rgba([255, 0, 0, 255]));
+        // Same Rec. 709 luma as the red pixel, so a grayscale key can't tell them apart
+        img.put_pixel(3, 0, Rgba([0, 76, 0, 255]));
+
+        let keyed = key_by_color(&img, Rgb([0, 255, 0]), 0.15);
+        let alphas: Vec<u8> = (0..4).map(|x| keyed.get_pixel(x, 0)[3]).collect();
+
+        assert_eq!(alphas, [0, 0, 255, 255]);
+        assert_eq!(keyed.get_pixel(2, 0).0, [255, 0, 0, 255], "foreground color must be untouched");
+    }
+
+    #[test]
+    fn alpha_mask_stretches_to_scaled_output() {
+        let mut mask = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 255]));
+        mask.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+
+        let masked = apply_alpha_mask(RgbaImage::from_pixel(4, 2, Rgba([9, 9, 9, 255])), &mask);
+        let alphas: Vec<u8> = (0..4).map(|x| masked.get_pixel(x, 1)[3]).collect();
+        assert_eq!(alphas, [0, 0, 255, 255]);
+    }
 }
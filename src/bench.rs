@@ -0,0 +1,91 @@
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use image::{GrayImage, Luma};
+
+use crate::ascii::{AsciiOptions, convert_frame_to_ascii};
+
+/// Timing of repeated `convert_frame_to_ascii` calls on one synthetic frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchReport {
+    pub width: u32,
+    pub height: u32,
+    pub iterations: u32,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn fps(&self) -> f64 {
+        self.iterations as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn micros_per_frame(&self) -> f64 {
+        self.elapsed.as_secs_f64() * 1e6 / self.iterations.max(1) as f64
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, {} iterations: {:.1} fps ({:.0} µs/frame)",
+            self.width,
+            self.height,
+            self.iterations,
+            self.fps(),
+            self.micros_per_frame()
+        )
+    }
+}
+
+/// Diagonal gradient with a fine checker on top, so every cell sees a
+/// different luma and the ramp is exercised end to end.
+pub fn synthetic_frame(width: u32, height: u32) -> GrayImage {
+    let span = (width + height).max(1) as u64;
+    GrayImage::from_fn(width, height, |x, y| {
+        let ramp = ((x + y) as u64 * 223 / span) as u8;
+        Luma([ramp + if (x / 2 + y / 2) % 2 == 0 { 32 } else { 0 }])
+    })
+}
+
+/// Convert a `width` x `height` synthetic frame `iterations` times. Frame
+/// generation is outside the timed loop, so only conversion is measured.
+pub fn run_bench(options: &AsciiOptions, width: u32, height: u32, iterations: u32) -> BenchReport {
+    let frame = synthetic_frame(width, height);
+    let started = Instant::now();
+    for _ in 0..iterations {
+        black_box(convert_frame_to_ascii(black_box(&frame), options));
+    }
+    BenchReport {
+        width,
+        height,
+        iterations,
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_divides_elapsed_time_by_iterations() {
+        let report = BenchReport {
+            width: 64,
+            height: 48,
+            iterations: 50,
+            elapsed: Duration::from_millis(250),
+        };
+        assert!((report.fps() - 200.0).abs() < 1e-9);
+        assert!((report.micros_per_frame() - 5000.0).abs() < 1e-9);
+        assert_eq!(report.to_string(), "64x48, 50 iterations: 200.0 fps (5000 µs/frame)");
+    }
+
+    #[test]
+    fn synthetic_frame_spans_the_luma_range() {
+        let frame = synthetic_frame(64, 48);
+        let (min, max) = frame.pixels().fold((255, 0), |(min, max), pixel| (min.min(pixel[0]), max.max(pixel[0])));
+        assert!(min < 32 && max > 220, "{min}..{max}");
+    }
+}
@@ -1,36 +1,400 @@
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use image::Rgb;
+
+use crate::ascii::{Dither, Focus, GlyphFont, LumaMethod, Palette, Prefilter, RenderMode, STANDARD_CHARSET, has_glyph};
+use crate::pipeline::{BackgroundSample, Flip, PipelineConfig, ResizeFilter, Resolution};
+use crate::video::{
+    AudioMode, BitDepth, CompareLayout, Crop, LabelPosition, PixelFormat, Timing, DEFAULT_CRF, DEFAULT_FFMPEG, DEFAULT_FFPROBE, DEFAULT_PRESET, DEFAULT_WEBP_QUALITY, EncodeOptions, OutputFormat,
+    TimeRange, VideoCodec, is_gif_path, is_image_path, is_stdout, is_url,
+};
+
+/// Built-in density ramps, dark to light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Ramp {
+    /// `@%#*+=-:. `
+    Standard,
+    /// Shade blocks `█▓▒░ `
+    Blocks,
+    /// Paul Bourke's 70-character ramp for fine gradients
+    Long,
+    /// Three steps, for a stark look
+    Minimal,
+    /// Round dots shrinking to nothing
+    Dots,
+}
+
+impl Ramp {
+    pub fn charset(self) -> &'static str {
+        match self {
+            Ramp::Standard => STANDARD_CHARSET,
+            Ramp::Blocks => "█▓▒░ ",
+            Ramp::Long => "$@B%8&WM#*oahkbdpqwmZO0QLCJUYXzcvunxrjft/\\|()1{}[]?-_+~<>i!lI;:,\"^`'. ",
+            Ramp::Minimal => "@+. ",
+            Ramp::Dots => "Oo°·. ",
+        }
+    }
+}
+
+/// Named bundles of rendering options. Explicit flags override whatever the
+/// look sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Look {
+    /// Dense glyphs in several shades, light on black. Frames stay grayscale,
+    /// so there is no green tint
+    Matrix,
+    /// Coarse dithered black-and-white ramp
+    Newspaper,
+    /// Full-color half-block cells
+    Blocks,
+    /// Default ramp in a few shades, light on black
+    Terminal,
+}
+
+/// Values a look supplies; `None` leaves the flag's own default in place.
+#[derive(Debug, Default)]
+struct LookSettings {
+    charset: Option<&'static str>,
+    shades: Option<u32>,
+    mode: Option<RenderMode>,
+    dither: Option<Dither>,
+    invert: bool,
+}
+
+impl Look {
+    fn settings(self) -> LookSettings {
+        match self {
+            Look::Matrix => LookSettings {
+                charset: Some("@$0O1l;:,. "),
+                shades: Some(8),
+                invert: true,
+                ..LookSettings::default()
+            },
+            Look::Newspaper => LookSettings {
+                charset: Some("#+. "),
+                shades: Some(1),
+                dither: Some(Dither::Floyd),
+                ..LookSettings::default()
+            },
+            Look::Blocks => LookSettings {
+                mode: Some(RenderMode::Halfblock),
+                ..LookSettings::default()
+            },
+            Look::Terminal => LookSettings {
+                shades: Some(4),
+                invert: true,
+                ..LookSettings::default()
+            },
+        }
+    }
+}
+
+/// One tile of `--tile`: render settings layered over the flags. A look is
+/// applied first, so the variant's other keys override it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variant {
+    pub look: Option<Look>,
+    pub charset: Option<String>,
+    pub shades: Option<u32>,
+    pub mode: Option<RenderMode>,
+    pub dither: Option<Dither>,
+    pub invert: Option<bool>,
+}
+
+impl Variant {
+    fn apply(&self, config: &mut PipelineConfig) {
+        let look = self.look.map(Look::settings).unwrap_or_default();
+        if let Some(charset) = self.charset.as_deref().or(look.charset) {
+            config.charset = charset.to_string();
+        }
+        if let Some(shades) = self.shades.or(look.shades) {
+            config.shades = shades;
+        }
+        if let Some(mode) = self.mode.or(look.mode) {
+            config.mode = mode;
+        }
+        if let Some(dither) = self.dither.or(look.dither) {
+            config.dither = dither;
+        }
+        config.invert = self.invert.unwrap_or(config.invert || look.invert);
+    }
+}
 
 #[derive(Debug, Parser)]
 #[command(
     author,
     version,
-    about = "Convert video frames into black-and-white ASCII art"
+    about = "Convert video frames into black-and-white ASCII art",
+    subcommand_negates_reqs = true
 )]
 pub struct Cli {
-    /// Input video path
-    pub input: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Input video/image paths or URLs; each is converted with the same settings
+    #[arg(value_name = "INPUT", required = true)]
+    pub inputs: Vec<PathBuf>,
 
-    /// Output video path (defaults to <input-stem>_ascii.mp4)
-    #[arg(short, long)]
+    /// Output video path (defaults to <input-stem>_ascii.mp4; single input
+    /// only, unless --concat joins them). `-` streams the video to stdout,
+    /// as Matroska unless --format says otherwise
+    #[arg(short, long, conflicts_with = "output_dir")]
     pub output: Option<PathBuf>,
 
+    /// Directory for all outputs (defaults to each input's own directory)
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Join all inputs, in order, into one silent video named after the first
+    /// (or --output), sampled at the first input's fps and row count
+    #[arg(long, conflicts_with_all = ["tile", "frames_out", "compare", "with_audio_copy", "dry_run", "info"])]
+    pub concat: bool,
+
     /// Number of ASCII columns per frame
-    #[arg(long, default_value_t = 120)]
+    #[arg(long, default_value_t = 120, conflicts_with = "rows")]
     pub columns: u32,
 
-    /// Override output framerate
-    #[arg(long)]
+    /// Size the output to this many character rows instead (handy for portrait video)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rows: Option<u32>,
+
+    /// Downscale wider sources to at most this many pixels before conversion
+    #[arg(long, value_parser = clap::value_parser!(u32).range(8..))]
+    pub max_width: Option<u32>,
+
+    /// Scale the source so the output fits WIDTHxHEIGHT (e.g. 1280x720) as
+    /// closely as its aspect ratio allows: the most detail that fits a canvas
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_fit, conflicts_with = "rows")]
+    pub fit: Option<Resolution>,
+
+    /// Interpolation used when --rows/--max-width resize the source: nearest
+    /// looks blocky, lanczos smoothest
+    #[arg(long, value_enum, default_value_t = ResizeFilter::Triangle)]
+    pub resize_filter: ResizeFilter,
+
+    /// Mirror each frame before conversion (horizontal for selfie footage)
+    #[arg(long, value_enum, default_value_t = Flip::None)]
+    pub flip: Flip,
+
+    /// Draw each 8x8 glyph at this multiple (cells become 8N x 8N pixels)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub font_scale: u32,
+
+    /// Blank background pixels between neighbouring glyphs, horizontally and
+    /// vertically, for the looser spacing of a real terminal
+    #[arg(long, value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=16))]
+    pub cell_padding: u32,
+
+    /// Glyph style: plain 8x8 or a thickened bold variant
+    #[arg(long, value_enum, default_value_t = GlyphFont::Regular)]
+    pub font: GlyphFont,
+
+    /// Average only every Nth pixel (per row and column) of each cell; faster
+    /// on large frames at a small cost in accuracy
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    pub sample_stride: u32,
+
+    /// Override output framerate (frames are dropped while decoding when below the source rate);
+    /// --in-fps with --out-fps samples at this rate but plays back at another
+    #[arg(long, visible_alias = "in-fps", value_parser = parse_fps)]
     pub fps: Option<f64>,
 
-    /// Characters from dark to light
-    #[arg(long, default_value = "@%#*+=-:. ")]
-    pub charset: String,
+    /// Play the output at this framerate instead of the sampling rate: lower
+    /// for slow motion, higher for a time-lapse (drops the audio)
+    #[arg(long, conflicts_with = "timing", value_parser = parse_fps)]
+    pub out_fps: Option<f64>,
+
+    /// Frame timing of the output: a constant rate, or each source frame's
+    /// own timestamp (keeps irregularly timed sources exactly in sync)
+    /// [default: cfr, or preserve for GIF input]
+    #[arg(long, value_enum, conflicts_with = "fps")]
+    pub timing: Option<Timing>,
+
+    /// Start processing at this many seconds into the input
+    #[arg(long, value_parser = parse_seconds)]
+    pub start: Option<f64>,
+
+    /// Only process this many seconds of the input
+    #[arg(long, value_parser = parse_seconds)]
+    pub duration: Option<f64>,
+
+    /// Start exactly at --start instead of the nearest keyframe; slower, as
+    /// everything before it is decoded and thrown away
+    #[arg(long, requires = "start")]
+    pub seek_accurate: bool,
+
+    /// Only convert this region of the frame, as WIDTH:HEIGHT:X:Y or
+    /// WIDTHxHEIGHT+X+Y in source pixels
+    #[arg(long, value_name = "W:H:X:Y")]
+    pub crop: Option<Crop>,
+
+    /// Named look setting charset, shades, mode, dither and invert at once;
+    /// any of those flags given explicitly still wins
+    #[arg(long, value_enum)]
+    pub look: Option<Look>,
+
+    /// Characters from dark to light [default: "@%#*+=-:. "]
+    #[arg(long)]
+    pub charset: Option<String>,
+
+    /// Read the characters from dark to light from a file, for long or
+    /// Unicode ramps that are awkward to quote; line breaks are dropped
+    #[arg(long, value_name = "PATH", value_parser = parse_charset_file, conflicts_with = "charset")]
+    pub charset_file: Option<String>,
+
+    /// Built-in charset; --charset and --charset-file take precedence
+    #[arg(long, value_enum)]
+    pub ramp: Option<Ramp>,
+
+    /// Reorder the charset dark to light by how much ink each glyph draws,
+    /// so a ramp doesn't need to be hand-sorted
+    #[arg(long)]
+    pub auto_order: bool,
+
+    /// Draw this character in place of charset characters the built-in font
+    /// can't render, e.g. a space to leave them blank (without it they're an error)
+    #[arg(long, value_name = "CHAR", value_parser = parse_fallback_char)]
+    pub fallback_char: Option<char>,
+
+    /// Number of grayscale shades (1 = pure B/W, 2-256 = grayscale depth) [default: 1]
+    #[arg(long)]
+    pub shades: Option<u32>,
 
-    /// Number of grayscale shades (1 = pure B/W, 2-256 = grayscale depth)
-    #[arg(long, default_value_t = 1)]
-    pub shades: u32,
+    /// Output format (default: inferred from --output extension, else WebP when
+    /// --transparent is set, otherwise MP4)
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Constant rate factor for MP4 output (0-51, lower = better quality)
+    #[arg(long, default_value_t = DEFAULT_CRF, value_parser = clap::value_parser!(u8).range(0..=51))]
+    pub crf: u8,
+
+    /// x264 preset for MP4 output (ultrafast ... veryslow, placebo); mapped to
+    /// the nearest SVT-AV1 speed with --codec av1
+    #[arg(long, default_value = DEFAULT_PRESET)]
+    pub preset: String,
+
+    /// Pixel format for MP4 output; yuv444p and rgb24 keep full color detail
+    /// but may not play in browsers
+    #[arg(long, value_enum, default_value_t = PixelFormat::Yuv420p)]
+    pub pix_fmt: PixelFormat,
+
+    /// Bits per channel for MP4 output; 10 removes gradient banding with many
+    /// --shades but needs a 10-bit capable x264 and won't play in browsers
+    #[arg(long, value_enum, default_value_t = BitDepth::Eight)]
+    pub bit_depth: BitDepth,
+
+    /// Video codec for MP4 output; x265 and av1 make much smaller files but
+    /// encode slower. --crf is passed through as is, so raise it for the same
+    /// quality (around 24 for x265, 30 for av1)
+    #[arg(long, value_enum, default_value_t = VideoCodec::X264)]
+    pub codec: VideoCodec,
+
+    /// libwebp quality for WebP output (0-100)
+    #[arg(long, default_value_t = DEFAULT_WEBP_QUALITY, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub webp_quality: u8,
+
+    /// Encode WebP output losslessly (hard-edged ASCII frames often come out smaller)
+    #[arg(long)]
+    pub webp_lossless: bool,
+
+    /// How many times GIF/WebP/APNG output plays (0 = loop forever)
+    #[arg(long, default_value_t = 0)]
+    pub loop_count: u32,
+
+    /// Make the encode reproducible: one encoder thread and no timestamps or
+    /// metadata in the file, so the same input and flags give byte-identical
+    /// output (slower)
+    #[arg(long)]
+    pub deterministic: bool,
+
+    /// Burn a caption into the output video (needs ffmpeg with drawtext)
+    #[arg(long, value_name = "TEXT")]
+    pub label: Option<String>,
+
+    /// Corner the --label caption is drawn in
+    #[arg(long, value_enum, default_value_t = LabelPosition::BottomLeft, requires = "label")]
+    pub label_position: LabelPosition,
+
+    /// Source audio in MP4 output: copy it (falling back to AAC when the codec
+    /// doesn't fit), always copy, re-encode to AAC, or drop it
+    #[arg(long, value_enum, default_value_t = AudioMode::Auto)]
+    pub audio: AudioMode,
+
+    /// Grayscale conversion for color frames
+    #[arg(long, value_enum, default_value_t = LumaMethod::Rec709)]
+    pub luma: LumaMethod,
+
+    /// Render light glyphs on a black background
+    #[arg(long, overrides_with = "no_invert")]
+    pub invert: bool,
+
+    /// Dark glyphs on white even when --look inverts
+    #[arg(long, overrides_with = "invert")]
+    pub no_invert: bool,
+
+    /// Skip the contrast stretch, for footage that is already graded
+    #[arg(long)]
+    pub no_contrast: bool,
+
+    /// Smooth each frame before conversion so grainy footage doesn't make
+    /// the characters flicker
+    #[arg(long, value_enum, default_value_t = Prefilter::None)]
+    pub prefilter: Prefilter,
+
+    /// Strength of --prefilter in pixels: the blur's sigma or the median's radius
+    #[arg(long, default_value_t = 1.0, value_parser = parse_radius)]
+    pub blur_radius: f32,
+
+    /// Sharpen each frame before conversion (after --prefilter) so soft or
+    /// out-of-focus footage gives the characters more edges to follow;
+    /// AMOUNT around 0.5-2
+    #[arg(long, value_name = "AMOUNT", value_parser = parse_amount)]
+    pub sharpen: Option<f32>,
+
+    /// Blend each cell's brightness with the previous frame's before picking
+    /// its character, weighting the new frame by ALPHA (lower is steadier
+    /// but smears motion); stops flicker in near-static scenes
+    #[arg(long, value_name = "ALPHA", value_parser = parse_alpha)]
+    pub temporal_smooth: Option<f32>,
+
+    /// Keep full-size cells in the middle of the frame and use double-size
+    /// cells around it, giving the subject more characters
+    #[arg(long, value_enum, default_value_t = Focus::None)]
+    pub focus: Focus,
+
+    /// With --focus center, fraction of the frame (0-1, from the center out)
+    /// kept at full detail
+    #[arg(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    pub focus_falloff: f32,
+
+    /// What drives character selection: brightness, edge strength (outline look),
+    /// full-color half-block cells, or a quadrant-block mosaic [default: brightness]
+    #[arg(long, value_enum)]
+    pub mode: Option<RenderMode>,
+
+    /// Quantize halfblock colors to `ansi16` or to N colors (2-256) picked
+    /// from the first frame by median cut, for a retro look and smaller files
+    #[arg(long, value_name = "N|ansi16", value_parser = parse_palette)]
+    pub palette: Option<Palette>,
+
+    /// Color each glyph from its cell's source colors: 1 draws it in the
+    /// cell's average color, 2 draws its ink in the darkest color and the
+    /// rest of the cell in the lightest, for a stippled two-tone look
+    #[arg(long, value_name = "1|2", value_parser = clap::value_parser!(u8).range(1..=2))]
+    pub max_colors_per_cell: Option<u8>,
+
+    /// Brightness (0-255) splitting dark from light cells in B/W, 2-shade and
+    /// blocks rendering; raise it for high-key footage, lower it for low-key
+    #[arg(long, default_value_t = 128)]
+    pub bw_threshold: u8,
+
+    /// Dithering for pure B/W mode (--shades 1) [default: none]
+    #[arg(long, value_enum)]
+    pub dither: Option<Dither>,
 
     /// Make background transparent (outputs WebP instead of MP4)
     #[arg(long)]
@@ -40,6 +404,15 @@ pub struct Cli {
     #[arg(long)]
     pub bg_color: Option<u8>,
 
+    /// Frames used to auto-detect the background color
+    #[arg(long, value_enum, default_value_t = BackgroundSample::First)]
+    pub bg_sample: BackgroundSample,
+
+    /// Detect the background on every converted frame instead of once, so the
+    /// key follows lighting drift (may flicker when frames differ a lot)
+    #[arg(long, requires = "transparent", conflicts_with_all = ["bg_color", "bg_sample"])]
+    pub bg_per_frame: bool,
+
     /// Tolerance for background detection (0-255, default: 0 = exact match).
     /// Pixels whose grayscale value is within ±threshold of the background color
     /// are treated as background and made transparent.
@@ -47,32 +420,457 @@ pub struct Cli {
     #[arg(long, default_value_t = 0)]
     pub threshold: u8,
 
+    /// Soften keyed edges: pixels up to N levels beyond --threshold get
+    /// partial alpha instead of snapping to opaque
+    #[arg(long, default_value_t = 0, requires = "transparent")]
+    pub feather: u8,
+
+    /// Fill the keyed background with this R,G,B color instead of making it
+    /// transparent, giving opaque output (e.g. MP4) on a solid color
+    #[arg(long, value_name = "R,G,B", value_parser = parse_rgb, requires = "transparent")]
+    pub flatten_bg: Option<Rgb<u8>>,
+
+    /// With --transparent, also write an opaque <output-stem>.mp4 from the
+    /// same frames that keeps the source audio, which WebP/GIF/APNG can't
+    #[arg(long, requires = "transparent", conflicts_with_all = ["flatten_bg", "frames_out"])]
+    pub with_audio_copy: bool,
+
+    /// Write the converted frames to this directory as frame_00000001.png...
+    /// instead of encoding a video (RGBA with --transparent)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "compare"])]
+    pub frames_out: Option<PathBuf>,
+
+    /// Write 24-bit ANSI colored text to <input-stem>_ascii.txt (or --output)
+    /// instead of images or video, to `cat` in a terminal: the image, or
+    /// every video frame redrawn in place
+    #[arg(long, conflicts_with_all = ["transparent", "frames_out", "compare", "tile", "concat", "dry_run"])]
+    pub text_ansi: bool,
+
+    /// Key out this R,G,B color from the source frame (e.g. a green screen)
+    /// instead of the luma-based --bg-color/--threshold key
+    #[arg(long, value_name = "R,G,B", value_parser = parse_rgb, requires = "transparent", conflicts_with_all = ["bg_color", "bg_per_frame"])]
+    pub key_color: Option<Rgb<u8>>,
+
+    /// How far from --key-color (0-1, largest HSV channel difference) still
+    /// counts as background
+    #[arg(long, default_value_t = 0.2, value_parser = parse_fraction, requires = "key_color")]
+    pub key_tolerance: f32,
+
     /// Create a comparison video with original and ASCII versions stacked vertically
     #[arg(long)]
     pub compare: bool,
+
+    /// Render the input once per --variant and stack the renders into one MP4
+    /// grid, filled left to right and top to bottom, to compare looks in one pass
+    #[arg(long, requires = "variants", conflicts_with_all = ["transparent", "frames_out", "compare", "dry_run", "info"])]
+    pub tile: bool,
+
+    /// One --tile variant: comma-separated overrides out of look, charset,
+    /// ramp, shades, mode, dither and invert (e.g. `ramp=blocks,shades=4`);
+    /// repeat once per tile
+    #[arg(long = "variant", value_name = "KEY=VALUE,...", value_parser = parse_variant, requires = "tile")]
+    pub variants: Vec<Variant>,
+
+    /// Arrangement of the comparison video
+    #[arg(long, value_enum, default_value_t = CompareLayout::Vertical)]
+    pub compare_layout: CompareLayout,
+
+    /// Extract frames to PNG files in the temp dir instead of streaming them
+    /// from ffmpeg (slower, uses more disk; useful for debugging)
+    #[arg(long)]
+    pub disk_frames: bool,
+
+    /// Warn about and skip extracted frames that are truncated or corrupt
+    /// instead of failing the whole run
+    #[arg(long)]
+    pub skip_bad_frames: bool,
+
+    /// Stop after converting this many frames (quick look/feel previews)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_frames: Option<u32>,
+
+    /// Convert every Nth frame and hold it for N frames, for a choppy
+    /// stop-motion look ("on twos" with 2); the duration is unchanged
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub hold: u32,
+
+    /// Kill ffmpeg and fail the input when one of its runs (probing,
+    /// extracting, encoding, comparing or tiling) takes longer than SECONDS,
+    /// or when streamed decoding waits longer than that for a frame
+    #[arg(long, value_name = "SECONDS", value_parser = parse_timeout)]
+    pub timeout: Option<Duration>,
+
+    /// Try inputs whose extension isn't a known video or image type anyway,
+    /// for unusual but valid files
+    #[arg(long)]
+    pub force: bool,
+
+    /// Reuse the previous converted frame when a source frame is identical
+    /// to the one before it, instead of converting it again; speeds up
+    /// static screen recordings and slideshows without changing the output
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// With --disk-frames, extract and convert this many frames at a time so
+    /// disk usage stays bounded (each batch is a separate ffmpeg run)
+    #[arg(long, requires = "disk_frames", value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_size: Option<u32>,
+
+    /// Keep intermediate frames in this directory and resume from it on rerun
+    #[arg(long)]
+    pub work_dir: Option<PathBuf>,
+
+    /// Discard frames cached in --work-dir and start from scratch
+    #[arg(long, requires = "work_dir")]
+    pub clean: bool,
+
+    /// Print run statistics as a JSON object on stdout instead of the summary
+    #[arg(long)]
+    pub stats_json: bool,
+
+    /// After each successful run, write <output>.json recording the input's
+    /// SHA-256, the render settings, the run statistics and the crate version
+    #[arg(long)]
+    pub sidecar: bool,
+
+    /// Open each finished output in the system's default viewer
+    #[arg(long)]
+    pub preview: bool,
+
+    /// Probe the inputs and print the planned output and ffmpeg commands
+    /// without writing anything
+    #[arg(long, conflicts_with = "stats_json")]
+    pub dry_run: bool,
+
+    /// Probe the inputs and print their resolution, fps, duration and the
+    /// character grid they would render to, then exit (JSON with --stats-json)
+    #[arg(long, alias = "stats-only", conflicts_with = "dry_run")]
+    pub info: bool,
+
+    /// Print nothing but errors: no progress, summary or warnings
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Log the resolved config, ffmpeg commands, probe results and stage
+    /// timings to stderr; repeat (-vv) for more detail
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Path to the ffmpeg binary
+    #[arg(long, env = "FFMPEG", default_value = DEFAULT_FFMPEG)]
+    pub ffmpeg_path: PathBuf,
+
+    /// Path to the ffprobe binary
+    #[arg(long, env = "FFPROBE", default_value = DEFAULT_FFPROBE)]
+    pub ffprobe_path: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Time ASCII conversion of a synthetic frame, without ffmpeg; rendering
+    /// flags given before `bench` (--columns, --shades, --mode, ...) apply
+    Bench(BenchArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// Width of the synthetic frame in pixels
+    #[arg(long, default_value_t = 1280, value_parser = clap::value_parser!(u32).range(8..))]
+    pub width: u32,
+
+    /// Height of the synthetic frame in pixels
+    #[arg(long, default_value_t = 720, value_parser = clap::value_parser!(u32).range(8..))]
+    pub height: u32,
+
+    /// Number of conversions to time
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
 }
 
 impl Cli {
-    pub fn output_path(&self) -> PathBuf {
+    /// Path of the plain ASCII output. With `--compare` and an explicit
+    /// `--output`, the comparison takes that path and the ASCII video is written
+    /// next to it as `<stem>_ascii.<ext>`.
+    pub fn output_path(&self, input: &Path) -> PathBuf {
         match &self.output {
+            Some(path) if self.compare => ascii_sibling_path(path, self.output_format_for(input)),
             Some(path) => path.clone(),
-            None => default_output_path(&self.input, self.transparent, self.compare),
+            None => self.derived_output_path(input, false),
         }
     }
-}
 
-fn default_output_path(input: &Path, transparent: bool, compare: bool) -> PathBuf {
-    let stem = input
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
+    pub fn time_range(&self) -> TimeRange {
+        TimeRange {
+            start: self.start,
+            duration: self.duration,
+            accurate: self.seek_accurate,
+        }
+    }
+
+    /// Path of the comparison video, when `--compare` is set.
+    pub fn compare_output_path(&self, input: &Path) -> Option<PathBuf> {
+        if !self.compare {
+            return None;
+        }
+
+        Some(match &self.output {
+            Some(path) => path.clone(),
+            None => self.derived_output_path(input, true),
+        })
+    }
+
+    /// Path of the opaque MP4 carrying the audio, when `--with-audio-copy` is set:
+    /// the output path with an `.mp4` extension.
+    pub fn audio_copy_output_path(&self, input: &Path) -> Option<PathBuf> {
+        self.with_audio_copy.then(|| self.output_path(input).with_extension("mp4"))
+    }
+
+    /// `default_output_path`, moved into `--output-dir` when one is given.
+    fn derived_output_path(&self, input: &Path, compare: bool) -> PathBuf {
+        let mut path = default_output_path(input, self.output_format_for(input), compare);
+        if self.text_ansi {
+            path.set_extension("txt");
+        }
+        match (&self.output_dir, path.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => path,
+        }
+    }
+
+    fn work_dir_for(&self, input: &Path) -> Option<PathBuf> {
+        self.batch_subdir(self.work_dir.as_ref(), input)
+    }
+
+    /// In a batch each input gets its own subdirectory of `--work-dir` and
+    /// `--frames-out`, so their frames don't evict or overwrite each other.
+    fn batch_subdir(&self, dir: Option<&PathBuf>, input: &Path) -> Option<PathBuf> {
+        let dir = dir?;
+        if self.inputs.len() == 1 {
+            return Some(dir.clone());
+        }
+
+        let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("input");
+        Some(dir.join(stem))
+    }
+
+    /// Most detailed log records shown on stderr: errors with --quiet,
+    /// warnings by default, then debug (-v) and trace (-vv).
+    pub fn log_level(&self) -> log::LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => log::LevelFilter::Error,
+            (false, 0) => log::LevelFilter::Warn,
+            (false, 1) => log::LevelFilter::Debug,
+            (false, _) => log::LevelFilter::Trace,
+        }
+    }
+
+    /// Reject flag combinations that only make sense for a single input.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.output.is_some() && self.inputs.len() > 1 && !self.concat {
+            return Err("--output can only be used with a single input; use --output-dir instead".to_string());
+        }
+        if self.concat {
+            if self.inputs.len() < 2 {
+                return Err("--concat needs at least two inputs".to_string());
+            }
+            if let Some(input) = self.inputs.iter().find(|input| is_image_path(input)) {
+                return Err(format!("--concat only works with video input, not {}", input.display()));
+            }
+        }
+        if self.output.as_deref().is_some_and(is_stdout) {
+            if let Some(input) = self.inputs.iter().find(|input| is_image_path(input)).filter(|_| !self.text_ansi) {
+                return Err(format!("--output - only works with video input, not {}", input.display()));
+            }
+            let flags = [
+                (self.compare, "--compare"),
+                (self.with_audio_copy, "--with-audio-copy"),
+                (self.tile, "--tile"),
+                (self.sidecar, "--sidecar"),
+                (self.stats_json, "--stats-json"),
+                (self.preview, "--preview"),
+            ];
+            if let Some((_, flag)) = flags.iter().find(|(set, _)| *set) {
+                return Err(format!("{flag} needs a file output, not --output -"));
+            }
+        }
+        if self.tile {
+            if self.variants.len() < 2 {
+                return Err("--tile needs at least two --variant settings".to_string());
+            }
+            if let Some(input) = self.inputs.iter().find(|input| is_image_path(input)) {
+                return Err(format!("--tile only works with video input, not {}", input.display()));
+            }
+            if self.inputs.iter().any(|input| self.output_format_for(input) != OutputFormat::Mp4) {
+                return Err("--tile always writes MP4 output".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The settings for `input` once per --variant, each variant's overrides
+    /// layered over the flags.
+    pub fn variant_configs(&self, input: &Path) -> Vec<PipelineConfig> {
+        let base = self.pipeline_config(input);
+        self.variants
+            .iter()
+            .map(|variant| {
+                let mut config = base.clone();
+                variant.apply(&mut config);
+                config
+            })
+            .collect()
+    }
+
+    /// Resolve the flags into the pipeline settings for one input.
+    pub fn pipeline_config(&self, input: &Path) -> PipelineConfig {
+        let look = self.look.map(Look::settings).unwrap_or_default();
+
+        PipelineConfig {
+            input: input.to_path_buf(),
+            output: self.output_path(input),
+            encode: EncodeOptions {
+                format: self.output_format_for(input),
+                audio: self.audio,
+                crf: self.crf,
+                preset: self.preset.clone(),
+                pix_fmt: self.pix_fmt,
+                bit_depth: self.bit_depth,
+                codec: self.codec,
+                webp_quality: self.webp_quality,
+                webp_lossless: self.webp_lossless,
+                loop_count: self.loop_count,
+                label: self.label.clone(),
+                label_position: self.label_position,
+                timing: self.timing_for(input),
+                deterministic: self.deterministic,
+            },
+            columns: self.columns,
+            rows: self.rows,
+            max_width: self.max_width,
+            fit: self.fit,
+            resize_filter: self.resize_filter,
+            flip: self.flip,
+            font_scale: self.font_scale,
+            cell_padding: self.cell_padding,
+            font: self.font,
+            sample_stride: self.sample_stride,
+            fps: self.fps,
+            out_fps: self.out_fps,
+            range: self.time_range(),
+            crop: self.crop,
+            charset: self.charset.clone().or_else(|| self.charset_file.clone()).unwrap_or_else(|| {
+                let ramp = self.ramp.map(Ramp::charset);
+                ramp.or(look.charset).unwrap_or(STANDARD_CHARSET).to_string()
+            }),
+            auto_order: self.auto_order,
+            fallback_char: self.fallback_char,
+            shades: self.shades.or(look.shades).unwrap_or(1),
+            luma: self.luma,
+            invert: !self.no_invert && (self.invert || look.invert),
+            contrast: !self.no_contrast,
+            prefilter: self.prefilter,
+            blur_radius: self.blur_radius,
+            sharpen: self.sharpen,
+            temporal_smooth: self.temporal_smooth,
+            focus: self.focus,
+            focus_falloff: self.focus_falloff,
+            dither: self.dither.or(look.dither).unwrap_or(Dither::None),
+            bw_threshold: self.bw_threshold,
+            mode: self.mode.or(look.mode).unwrap_or(RenderMode::Brightness),
+            palette: self.palette,
+            max_colors_per_cell: self.max_colors_per_cell,
+            transparent: self.transparent,
+            bg_color: self.bg_color,
+            bg_sample: self.bg_sample,
+            bg_per_frame: self.bg_per_frame,
+            threshold: self.threshold,
+            feather: self.feather,
+            flatten_bg: self.flatten_bg,
+            key_color: self.key_color,
+            key_tolerance: self.key_tolerance,
+            frames_out: self.batch_subdir(self.frames_out.as_ref(), input),
+            text_ansi: self.text_ansi,
+            compare_output: self.compare_output_path(input),
+            audio_copy_output: self.audio_copy_output_path(input),
+            compare_layout: self.compare_layout,
+            ffmpeg: self.ffmpeg_path.clone(),
+            ffprobe: self.ffprobe_path.clone(),
+            work_dir: self.work_dir_for(input),
+            clean: self.clean,
+            disk_frames: self.disk_frames,
+            skip_bad_frames: self.skip_bad_frames,
+            max_frames: self.max_frames,
+            hold: self.hold,
+            dedup: self.dedup,
+            force: self.force,
+            timeout: self.timeout,
+            batch_size: self.batch_size,
+            progress: !self.quiet && std::io::stdout().is_terminal(),
+        }
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        if let Some(format) = self.format {
+            return format;
+        }
+
+        if let Some(format) = self.output.as_deref().and_then(OutputFormat::from_path) {
+            return format;
+        }
+
+        // A pipe has no extension to go by; Matroska streams without seeking
+        if self.output.as_deref().is_some_and(is_stdout) {
+            return if self.transparent && self.flatten_bg.is_none() { OutputFormat::Webm } else { OutputFormat::Mkv };
+        }
+
+        if self.transparent && self.flatten_bg.is_none() {
+            OutputFormat::Webp
+        } else {
+            OutputFormat::Mp4
+        }
+    }
+
+    /// `output_format` for one input: an animated GIF converts to a GIF
+    /// when nothing else picks the format.
+    pub fn output_format_for(&self, input: &Path) -> OutputFormat {
+        let chosen = self.format.is_some()
+            || self.output.as_deref().is_some_and(|output| is_stdout(output) || OutputFormat::from_path(output).is_some());
+        if !chosen && !self.transparent && is_gif_path(input) {
+            OutputFormat::Gif
+        } else {
+            self.output_format()
+        }
+    }
+
+    /// GIF frames each carry their own delay, so GIF input keeps its source
+    /// timing unless a rate was asked for.
+    fn timing_for(&self, input: &Path) -> Timing {
+        match self.timing {
+            Some(timing) => timing,
+            None if is_gif_path(input) && self.fps.is_none() && self.out_fps.is_none() => Timing::Preserve,
+            None => Timing::Cfr,
+        }
+    }
+}
 
-    let mut output = input
-        .parent()
-        .unwrap_or_else(|| Path::new("."))
-        .to_path_buf();
+fn default_output_path(input: &Path, format: OutputFormat, compare: bool) -> PathBuf {
+    // URLs are written to the current directory, named after the last path segment
+    let (stem, mut output) = match input.to_str().filter(|_| is_url(input)) {
+        Some(url) => (url_file_stem(url), PathBuf::new()),
+        None => (
+            input
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("output"),
+            input
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .to_path_buf(),
+        ),
+    };
 
-    let ext = if transparent { "webp" } else { "mp4" };
+    // Still images are converted to a single PNG rather than a video
+    let ext = if is_image_path(input) { "png" } else { format.extension() };
 
     if compare {
         output.push(format!("{stem}_compare.{ext}"));
@@ -83,18 +881,218 @@ fn default_output_path(input: &Path, transparent: bool, compare: bool) -> PathBu
     output
 }
 
+/// File stem of a URL's last path segment, ignoring query and fragment.
+fn url_file_stem(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = without_scheme.split(['?', '#']).next().unwrap_or("");
+    // Everything before the first '/' is the host, not a file name
+    let segment = path
+        .split_once('/')
+        .map_or("", |(_, rest)| rest.rsplit('/').next().unwrap_or(""));
+    let stem = segment.rsplit_once('.').map_or(segment, |(stem, _)| stem);
+    if stem.is_empty() { "output" } else { stem }
+}
+
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number of seconds"))?;
+    if seconds.is_finite() && seconds >= 0.0 {
+        Ok(seconds)
+    } else {
+        Err(format!("`{value}` must be a non-negative number of seconds"))
+    }
+}
+
+fn parse_fps(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fps) if fps.is_finite() && fps > 0.0 => Ok(fps),
+        _ => Err(format!("`{value}` must be a positive framerate")),
+    }
+}
+
+fn parse_radius(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(radius) if radius.is_finite() && radius > 0.0 => Ok(radius),
+        _ => Err(format!("`{value}` is not a positive radius")),
+    }
+}
+
+fn parse_fit(value: &str) -> Result<Resolution, String> {
+    let invalid = || format!("`{value}` is not a size like 1280x720");
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    match (width.trim().parse::<u32>(), height.trim().parse::<u32>()) {
+        (Ok(width), Ok(height)) if width >= 8 && height >= 8 => Ok(Resolution { width, height }),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_timeout(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(timeout)) if !timeout.is_zero() => Ok(timeout),
+        _ => Err(format!("`{value}` is not a positive number of seconds")),
+    }
+}
+
+fn parse_amount(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(amount) if amount.is_finite() && amount > 0.0 => Ok(amount),
+        _ => Err(format!("`{value}` is not a positive amount")),
+    }
+}
+
+/// A single character the built-in font has a glyph for.
+fn parse_fallback_char(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if has_glyph(ch) => Ok(ch),
+        (Some(ch), None) => Err(format!("{ch:?} has no glyph in the built-in font")),
+        _ => Err(format!("expected a single character, got `{value}`")),
+    }
+}
+
+/// The ramp in the file at `value`, joined across lines. Only line breaks
+/// are removed, so a trailing space (a blank lightest shade) survives.
+fn parse_charset_file(value: &str) -> Result<String, String> {
+    let contents = std::fs::read_to_string(value).map_err(|err| format!("can't read charset file `{value}`: {err}"))?;
+    let charset: String = contents.trim_start_matches('\u{feff}').chars().filter(|ch| !matches!(ch, '\n' | '\r')).collect();
+    if charset.is_empty() {
+        return Err(format!("charset file `{value}` is empty"));
+    }
+    Ok(charset)
+}
+
+/// A `--variant` like `ramp=blocks,shades=4`. A comma not followed by
+/// `KEY=` belongs to the previous value, so charsets can contain commas.
+fn parse_variant(value: &str) -> Result<Variant, String> {
+    let mut settings: Vec<(&str, String)> = Vec::new();
+    for part in value.split(',') {
+        match (part.split_once('='), settings.last_mut()) {
+            (Some((key, value)), _) => settings.push((key.trim(), value.to_string())),
+            (None, Some((_, previous))) => {
+                previous.push(',');
+                previous.push_str(part);
+            }
+            (None, None) => return Err(format!("`{part}` is not a KEY=VALUE setting")),
+        }
+    }
+
+    fn choice<T: ValueEnum>(key: &str, value: &str) -> Result<T, String> {
+        T::from_str(value, true).map_err(|_| format!("`{value}` is not a valid {key}"))
+    }
+    let mut variant = Variant::default();
+    for (key, value) in settings {
+        match key {
+            "look" => variant.look = Some(choice(key, &value)?),
+            "charset" if value.is_empty() => return Err("charset can't be empty".to_string()),
+            "charset" => variant.charset = Some(value),
+            "ramp" => variant.charset = Some(choice::<Ramp>(key, &value)?.charset().to_string()),
+            "shades" => match value.parse::<u32>() {
+                Ok(shades) if (1..=256).contains(&shades) => variant.shades = Some(shades),
+                _ => return Err(format!("`{value}` is not a shade count from 1 to 256")),
+            },
+            "mode" => variant.mode = Some(choice(key, &value)?),
+            "dither" => variant.dither = Some(choice(key, &value)?),
+            "invert" => variant.invert = Some(value.parse().map_err(|_| format!("`{value}` is not true or false"))?),
+            _ => {
+                return Err(format!(
+                    "unknown variant setting `{key}` (expected look, charset, ramp, shades, mode, dither or invert)"
+                ));
+            }
+        }
+    }
+    Ok(variant)
+}
+
+fn parse_alpha(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok(alpha),
+        _ => Err(format!("`{value}` is not a weight above 0 and at most 1")),
+    }
+}
+
+fn parse_palette(value: &str) -> Result<Palette, String> {
+    if value.eq_ignore_ascii_case("ansi16") {
+        return Ok(Palette::Ansi16);
+    }
+    match value.parse::<u32>() {
+        Ok(count) if (2..=256).contains(&count) => Ok(Palette::MedianCut(count)),
+        _ => Err(format!("`{value}` is not `ansi16` or a color count from 2 to 256")),
+    }
+}
+
+fn parse_fraction(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err(format!("`{value}` is not a number between 0 and 1")),
+    }
+}
+
+fn parse_rgb(value: &str) -> Result<Rgb<u8>, String> {
+    let channels: Vec<u8> = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<u8>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("`{value}` is not an R,G,B color with channels 0-255"))?;
+    match channels[..] {
+        [r, g, b] => Ok(Rgb([r, g, b])),
+        _ => Err(format!("`{value}` must have exactly three channels, e.g. 0,0,0")),
+    }
+}
+
+fn ascii_sibling_path(path: &Path, format: OutputFormat) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+
+    path.with_file_name(format!("{stem}_ascii.{}", format.extension()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use clap::Parser;
+    use image::imageops::FilterType;
 
     #[test]
     fn derives_default_output_path() {
         let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
-        assert_eq!(cli.output_path(), PathBuf::from("input_ascii.mp4"));
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("input_ascii.mp4"));
 
         let cli_transparent = Cli::parse_from(["video-ascii-cli", "input.mp4", "--transparent"]);
-        assert_eq!(cli_transparent.output_path(), PathBuf::from("input_ascii.webp"));
+        assert_eq!(cli_transparent.output_path(&cli_transparent.inputs[0]), PathBuf::from("input_ascii.webp"));
+    }
+
+    #[test]
+    fn text_ansi_writes_a_txt_file_or_stdout_for_any_input() {
+        let cli = Cli::parse_from(["video-ascii-cli", "clip.mp4", "photo.png", "--text-ansi", "--output-dir", "out"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("out/clip_ascii.txt"));
+        assert_eq!(cli.output_path(&cli.inputs[1]), PathBuf::from("out/photo_ascii.txt"));
+        assert!(cli.pipeline_config(&cli.inputs[1]).text_ansi);
+
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "photo.png", "--text-ansi", "-o", "-"]).validate(), Ok(()));
+        assert!(Cli::try_parse_from(["video-ascii-cli", "clip.mp4", "--text-ansi", "--transparent"]).is_err());
+    }
+
+    #[test]
+    fn batch_inputs_share_settings_and_output_dir() {
+        let cli = Cli::parse_from(["video-ascii-cli", "a/one.mp4", "b/two.mov", "--output-dir", "out"]);
+        assert!(cli.validate().is_ok());
+        assert_eq!(cli.inputs.len(), 2);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("out/one_ascii.mp4"));
+        assert_eq!(cli.output_path(&cli.inputs[1]), PathBuf::from("out/two_ascii.mp4"));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "one.mp4", "two.mp4"]);
+        assert_eq!(cli.output_path(&cli.inputs[1]), PathBuf::from("two_ascii.mp4"));
+    }
+
+    #[test]
+    fn output_rejected_for_multiple_inputs() {
+        let cli = Cli::parse_from(["video-ascii-cli", "one.mp4", "two.mp4", "-o", "out.mp4"]);
+        assert!(cli.validate().is_err());
+
+        assert!(Cli::try_parse_from(["video-ascii-cli"]).is_err());
     }
 
     #[test]
@@ -114,12 +1112,595 @@ mod tests {
             "4",
         ]);
 
-        assert_eq!(cli.input, PathBuf::from("in.mp4"));
+        assert_eq!(cli.inputs, vec![PathBuf::from("in.mp4")]);
         assert_eq!(cli.output, Some(PathBuf::from("out.mp4")));
         assert_eq!(cli.columns, 80);
         assert_eq!(cli.fps, Some(12.0));
-        assert_eq!(cli.charset, "# ");
-        assert_eq!(cli.shades, 4);
+        assert_eq!(cli.charset.as_deref(), Some("# "));
+        assert_eq!(cli.shades, Some(4));
+    }
+
+    #[test]
+    fn url_input_derives_local_output_path() {
+        let cli = Cli::parse_from(["video-ascii-cli", "https://example.com/media/clip.mp4?token=abc"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("clip_ascii.mp4"));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "https://example.com/", "--format", "gif"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("output_ascii.gif"));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "http://example.com"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("output_ascii.mp4"));
+    }
+
+    #[test]
+    fn image_input_defaults_to_png_output() {
+        let cli = Cli::parse_from(["video-ascii-cli", "photos/cat.jpg"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("photos/cat_ascii.png"));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "cat.png", "--transparent"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("cat_ascii.png"));
+    }
+
+    #[test]
+    fn gif_format_picks_gif_extension() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--format", "gif"]);
+        assert_eq!(cli.output_format(), OutputFormat::Gif);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("input_ascii.gif"));
+    }
+
+    #[test]
+    fn webm_format_picks_webm_extension() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--transparent", "--format", "webm"]);
+        assert_eq!(cli.output_format(), OutputFormat::Webm);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("input_ascii.webm"));
+    }
+
+    #[test]
+    fn apng_format_picks_apng_extension() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--transparent", "--format", "apng"]);
+        assert_eq!(cli.output_format(), OutputFormat::Apng);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("input_ascii.apng"));
+    }
+
+    #[test]
+    fn infers_format_from_output_extension() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "anim.gif"]);
+        assert_eq!(cli.output_format(), OutputFormat::Gif);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "clip.webp"]);
+        assert_eq!(cli.output_format(), OutputFormat::Webp);
+    }
+
+    #[test]
+    fn parses_encoder_settings() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.crf, 18);
+        assert_eq!(cli.preset, "veryfast");
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--crf", "14", "--preset", "slow"]);
+        assert_eq!(cli.crf, 14);
+        assert_eq!(cli.preset, "slow");
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--crf", "52"]).is_err());
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--bit-depth", "10"]);
+        assert_eq!(cli.bit_depth, BitDepth::Ten);
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--bit-depth", "12"]).is_err());
+
+        assert_eq!(cli.codec, VideoCodec::X264);
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--codec", "av1"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).encode.encoder(), "libsvtav1");
+    }
+
+    #[test]
+    fn parses_tool_path_overrides() {
+        let cli = Cli::parse_from([
+            "video-ascii-cli",
+            "input.mp4",
+            "--ffmpeg-path",
+            "/opt/ffmpeg/bin/ffmpeg",
+            "--ffprobe-path",
+            "/opt/ffmpeg/bin/ffprobe",
+        ]);
+        assert_eq!(cli.ffmpeg_path, PathBuf::from("/opt/ffmpeg/bin/ffmpeg"));
+        assert_eq!(cli.ffprobe_path, PathBuf::from("/opt/ffmpeg/bin/ffprobe"));
+    }
+
+    #[test]
+    fn compare_keeps_separate_ascii_output() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--compare"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("input_ascii.mp4"));
+        assert_eq!(cli.compare_output_path(&cli.inputs[0]), Some(PathBuf::from("input_compare.mp4")));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--compare", "-o", "out/side.mp4"]);
+        assert_eq!(cli.output_path(&cli.inputs[0]), PathBuf::from("out/side_ascii.mp4"));
+        assert_eq!(cli.compare_output_path(&cli.inputs[0]), Some(PathBuf::from("out/side.mp4")));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.compare_output_path(&cli.inputs[0]), None);
+    }
+
+    #[test]
+    fn audio_copy_sits_next_to_the_transparent_output() {
+        let cli = Cli::parse_from(["video-ascii-cli", "clip.mp4", "--transparent", "--with-audio-copy"]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!(config.output, PathBuf::from("clip_ascii.webp"));
+        assert_eq!(config.audio_copy_output, Some(PathBuf::from("clip_ascii.mp4")));
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "clip.mp4", "--with-audio-copy"]).is_err());
+        assert!(
+            Cli::try_parse_from(["video-ascii-cli", "clip.mp4", "--transparent", "--flatten-bg", "0,0,0", "--with-audio-copy"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_compare_layout() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--compare"]);
+        assert_eq!(cli.compare_layout, CompareLayout::Vertical);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--compare", "--compare-layout", "horizontal"]);
+        assert_eq!(cli.compare_layout, CompareLayout::Horizontal);
+    }
+
+    #[test]
+    fn parses_time_range() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert!(cli.time_range().is_full());
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--start", "1.5", "--duration", "3"]);
+        assert_eq!(cli.start, Some(1.5));
+        assert_eq!(cli.duration, Some(3.0));
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--start", "-1"]).is_err());
+    }
+
+    #[test]
+    fn frames_out_gets_a_subdirectory_per_input() {
+        let cli = Cli::parse_from(["video-ascii-cli", "a.mp4", "--frames-out", "frames"]);
+        assert_eq!(cli.pipeline_config(Path::new("a.mp4")).frames_out, Some(PathBuf::from("frames")));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "a.mp4", "b.mp4", "--frames-out", "frames"]);
+        assert_eq!(cli.pipeline_config(Path::new("b.mp4")).frames_out, Some(PathBuf::from("frames/b")));
+        assert!(Cli::try_parse_from(["video-ascii-cli", "a.mp4", "--frames-out", "frames", "--compare"]).is_err());
+    }
+
+    #[test]
+    fn parses_key_color() {
+        let cli = Cli::parse_from(["video-ascii-cli", "in.mp4", "--transparent", "--key-color", "0,255,0"]);
+        assert_eq!(cli.key_color, Some(Rgb([0, 255, 0])));
+        assert_eq!(cli.key_tolerance, 0.2);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "in.mp4", "--transparent", "--key-color", "0,255,0", "--key-tolerance", "0.35"]);
+        assert_eq!(cli.key_tolerance, 0.35);
+        assert!(Cli::try_parse_from(["video-ascii-cli", "in.mp4", "--key-color", "0,255,0"]).is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "in.mp4", "--transparent", "--key-color", "0,255,0", "--key-tolerance", "2"]).is_err());
+    }
+
+    #[test]
+    fn parses_in_and_out_fps() {
+        let cli = Cli::parse_from(["video-ascii-cli", "in.mp4", "--in-fps", "24", "--out-fps", "12"]);
+        let config = cli.pipeline_config(Path::new("in.mp4"));
+        assert_eq!((config.fps, config.out_fps), (Some(24.0), Some(12.0)));
+        assert!(Cli::try_parse_from(["video-ascii-cli", "in.mp4", "--out-fps", "12", "--timing", "preserve"]).is_err());
+    }
+
+    #[test]
+    fn parses_temporal_smooth_alpha() {
+        let cli = Cli::parse_from(["video-ascii-cli", "in.mp4", "--temporal-smooth", "0.4"]);
+        assert_eq!(cli.pipeline_config(Path::new("in.mp4")).temporal_smooth, Some(0.4));
+        assert!(Cli::try_parse_from(["video-ascii-cli", "in.mp4", "--temporal-smooth", "0"]).is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "in.mp4", "--temporal-smooth", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn parses_crop() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--crop", "320x180+40+0"]);
+        assert_eq!(cli.crop, Some(Crop { width: 320, height: 180, x: 40, y: 0 }));
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--crop", "320:180"]).is_err());
+    }
+
+    #[test]
+    fn parses_luma_method() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.luma, LumaMethod::Rec709);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--luma", "average"]);
+        assert_eq!(cli.luma, LumaMethod::Average);
+    }
+
+    #[test]
+    fn parses_render_mode() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).mode, RenderMode::Brightness);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--mode", "edges", "--invert"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).mode, RenderMode::Edges);
+        assert!(cli.invert);
+    }
+
+    #[test]
+    fn parses_webp_options() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--transparent"]);
+        let encode = cli.pipeline_config(&cli.inputs[0]).encode;
+        assert_eq!(encode.webp_quality, DEFAULT_WEBP_QUALITY);
+        assert!(!encode.webp_lossless);
+
+        let cli = Cli::parse_from([
+            "video-ascii-cli",
+            "input.mp4",
+            "--transparent",
+            "--webp-quality",
+            "60",
+            "--webp-lossless",
+        ]);
+        let encode = cli.pipeline_config(&cli.inputs[0]).encode;
+        assert_eq!(encode.format, OutputFormat::Webp);
+        assert_eq!(encode.webp_quality, 60);
+        assert!(encode.webp_lossless);
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--webp-quality", "101"]).is_err());
+    }
+
+    #[test]
+    fn parses_audio_mode() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).encode.audio, AudioMode::Auto);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--audio", "none"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).encode.audio, AudioMode::None);
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--audio", "mp3"]).is_err());
+    }
+
+    #[test]
+    fn rows_and_columns_are_exclusive() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--rows", "30"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).rows, Some(30));
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--rows", "30", "--columns", "80"]).is_err());
+    }
+
+    #[test]
+    fn parses_dither() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).dither, Dither::None);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--dither", "floyd"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).dither, Dither::Floyd);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--dither", "atkinson"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).dither, Dither::Atkinson);
+    }
+
+    #[test]
+    fn counts_verbose_flags() {
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4"]).verbose, 0);
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-v"]).verbose, 1);
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-vv"]).verbose, 2);
+    }
+
+    #[test]
+    fn quiet_lowers_the_log_level_to_errors() {
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4"]).log_level(), log::LevelFilter::Warn);
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-q"]).log_level(), log::LevelFilter::Error);
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-v"]).log_level(), log::LevelFilter::Debug);
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "-q", "-v"]).is_err());
+    }
+
+    #[test]
+    fn builder_defaults_match_cli_defaults() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "out.mp4"]);
+        let from_cli = cli.pipeline_config(&cli.inputs[0]);
+        let built = PipelineConfig::builder("input.mp4", "out.mp4")
+            .ffmpeg(from_cli.ffmpeg.clone())
+            .ffprobe(from_cli.ffprobe.clone())
+            .progress(from_cli.progress)
+            .build();
+        assert_eq!(format!("{built:?}"), format!("{from_cli:?}"));
+
+        // Format follows the output path, or WebP for transparent output
+        let gif = PipelineConfig::builder("in.mp4", "out.gif").build();
+        assert_eq!(gif.encode.format, OutputFormat::Gif);
+        let transparent = PipelineConfig::builder("in.mp4", "out").transparent(true).build();
+        assert_eq!(transparent.encode.format, OutputFormat::Webp);
+        let forced = PipelineConfig::builder("in.mp4", "out.gif").format(OutputFormat::Apng).build();
+        assert_eq!(forced.encode.format, OutputFormat::Apng);
+
+        let config = PipelineConfig::builder("in.mp4", "out.mp4").columns(80).charset("#. ").rows(40).build();
+        assert_eq!((config.columns, config.charset.as_str(), config.rows), (80, "#. ", Some(40)));
+    }
+
+    #[test]
+    fn parses_flatten_bg_color() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--transparent", "--flatten-bg", "0, 32,255"]);
+        assert_eq!(cli.flatten_bg, Some(Rgb([0, 32, 255])));
+        // Flattened output is opaque, so it stays MP4 by default
+        assert_eq!(cli.output_format(), OutputFormat::Mp4);
+
+        assert_eq!(parse_rgb("1,2"), Err("`1,2` must have exactly three channels, e.g. 0,0,0".to_string()));
+        assert!(parse_rgb("0,0,256").is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--flatten-bg", "0,0,0"]).is_err());
+    }
+
+    #[test]
+    fn parses_timing() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).encode.timing, Timing::Cfr);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--timing", "preserve"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).encode.timing, Timing::Preserve);
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--timing", "preserve", "--fps", "10"]).is_err());
+    }
+
+    #[test]
+    fn gif_input_defaults_to_gif_output_with_source_timing() {
+        let cli = Cli::parse_from(["video-ascii-cli", "dir/anim.gif"]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!(config.output, PathBuf::from("dir/anim_ascii.gif"));
+        assert_eq!((config.encode.format, config.encode.timing), (OutputFormat::Gif, Timing::Preserve));
+
+        let cli = Cli::parse_from(["video-ascii-cli", "anim.gif", "--fps", "10", "--format", "mp4"]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!((config.encode.format, config.encode.timing), (OutputFormat::Mp4, Timing::Cfr));
+    }
+
+    #[test]
+    fn parses_font() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).font, GlyphFont::Regular);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--font", "builtin-bold"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).font, GlyphFont::BuiltinBold);
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--font", "8x8"]).is_ok());
+    }
+
+    #[test]
+    fn defaults_apply_without_a_look() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!(config.charset, STANDARD_CHARSET);
+        assert_eq!(config.shades, 1);
+        assert!(!config.invert);
+    }
+
+    #[test]
+    fn look_supplies_bundled_options() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--look", "newspaper"]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!(config.charset, "#+. ");
+        assert_eq!(config.shades, 1);
+        assert_eq!(config.dither, Dither::Floyd);
+        assert_eq!(config.mode, RenderMode::Brightness);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--look", "blocks"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).mode, RenderMode::Halfblock);
+    }
+
+    #[test]
+    fn named_ramps_resolve_to_charsets() {
+        let charset = |ramp: &str| {
+            let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--ramp", ramp]);
+            cli.pipeline_config(&cli.inputs[0]).charset
+        };
+        assert_eq!(charset("standard"), "@%#*+=-:. ");
+        assert_eq!(charset("blocks"), "█▓▒░ ");
+        assert_eq!(charset("minimal"), "@+. ");
+        assert_eq!(charset("dots"), "Oo°·. ");
+
+        let long = charset("long");
+        assert_eq!(long.chars().count(), 70);
+        assert!(long.starts_with("$@B%8&WM#*"));
+        assert!(long.ends_with("I;:,\"^`'. "));
+    }
+
+    #[test]
+    fn charset_file_loads_a_unicode_ramp() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("ramp.txt");
+        std::fs::write(&path, "█▓▒\n░ \n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--ramp", "minimal", "--charset-file", path]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).charset, "█▓▒░ ");
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--charset-file", path, "--charset", "# "]).is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--charset-file", "missing.txt"]).is_err());
+    }
+
+    #[test]
+    fn parses_palette_sizes_and_ansi16() {
+        let palette = |value: &str| {
+            Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--mode", "halfblock", "--palette", value]).map(|cli| cli.palette)
+        };
+        assert_eq!(palette("16").unwrap(), Some(Palette::MedianCut(16)));
+        assert_eq!(palette("ansi16").unwrap(), Some(Palette::Ansi16));
+        assert!(palette("1").is_err());
+        assert!(palette("257").is_err());
+        assert!(palette("vga").is_err());
+    }
+
+    #[test]
+    fn parses_tile_variants() {
+        let variant = parse_variant("ramp=minimal,shades=4,invert=true").unwrap();
+        assert_eq!(variant.charset.as_deref(), Some("@+. "));
+        assert_eq!((variant.shades, variant.invert), (Some(4), Some(true)));
+        assert_eq!(parse_variant("mode=edges,charset=#,. ").unwrap().charset.as_deref(), Some("#,. "));
+        assert_eq!(parse_variant("look=matrix,dither=floyd").unwrap().look, Some(Look::Matrix));
+
+        assert!(parse_variant("blocks").is_err());
+        assert!(parse_variant("shades=0").is_err());
+        assert!(parse_variant("font=bold").is_err());
+        assert!(parse_variant("mode=sparkly").is_err());
+    }
+
+    #[test]
+    fn tile_layers_each_variant_over_the_flags() {
+        let cli = Cli::parse_from([
+            "video-ascii-cli",
+            "input.mp4",
+            "--shades",
+            "2",
+            "--tile",
+            "--variant",
+            "charset=#. ",
+            "--variant",
+            "look=matrix,shades=3",
+        ]);
+        assert_eq!(cli.validate(), Ok(()));
+        let configs = cli.variant_configs(&cli.inputs[0]);
+        assert_eq!(configs.len(), 2);
+        assert_eq!((configs[0].charset.as_str(), configs[0].shades, configs[0].invert), ("#. ", 2, false));
+        assert_eq!((configs[1].charset.as_str(), configs[1].shades, configs[1].invert), ("@$0O1l;:,. ", 3, true));
+
+        let single = Cli::parse_from(["video-ascii-cli", "input.mp4", "--tile", "--variant", "shades=2"]);
+        assert!(single.validate().is_err());
+        let image = Cli::parse_from(["video-ascii-cli", "input.png", "--tile", "--variant", "shades=2", "--variant", "shades=4"]);
+        assert!(image.validate().is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--variant", "shades=2"]).is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--tile"]).is_err());
+    }
+
+    #[test]
+    fn parses_fit_sizes() {
+        let fit = |value: &str| Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--fit", value]).map(|cli| cli.fit);
+        assert_eq!(fit("1280x720").unwrap(), Some(Resolution { width: 1280, height: 720 }));
+        assert_eq!(fit("640X480").unwrap(), Some(Resolution { width: 640, height: 480 }));
+        assert!(fit("720p").is_err());
+        assert!(fit("4x4").is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--fit", "1280x720", "--rows", "30"]).is_err());
+    }
+
+    #[test]
+    fn parses_timeout_seconds() {
+        let timeout = |value: &str| Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--timeout", value]).map(|cli| cli.timeout);
+        assert_eq!(timeout("90").unwrap(), Some(Duration::from_secs(90)));
+        assert_eq!(timeout("0.5").unwrap(), Some(Duration::from_millis(500)));
+        assert!(timeout("0").is_err());
+        assert!(timeout("-3").is_err());
+        assert!(timeout("soon").is_err());
+    }
+
+    #[test]
+    fn framerates_must_be_positive() {
+        for flag in ["--fps", "--in-fps", "--out-fps"] {
+            let fps = |value: &str| Cli::try_parse_from(["video-ascii-cli", "input.mp4", flag, value]);
+            assert!(fps("12.5").is_ok());
+            for bad in ["0", "-24", "nan", "inf"] {
+                assert!(fps(bad).is_err(), "{flag} {bad}");
+            }
+        }
+    }
+
+    #[test]
+    fn concat_takes_one_output_for_several_video_inputs() {
+        let cli = Cli::parse_from(["video-ascii-cli", "a.mp4", "b.mov", "--concat", "-o", "joined.mp4"]);
+        assert_eq!(cli.validate(), Ok(()));
+        assert!(Cli::parse_from(["video-ascii-cli", "a.mp4", "b.mov", "-o", "joined.mp4"]).validate().is_err());
+        assert!(Cli::parse_from(["video-ascii-cli", "a.mp4", "--concat"]).validate().is_err());
+        assert!(Cli::parse_from(["video-ascii-cli", "a.mp4", "b.png", "--concat"]).validate().is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "a.mp4", "b.mp4", "--concat", "--tile"]).is_err());
+    }
+
+    #[test]
+    fn stdout_output_defaults_to_matroska_and_needs_a_video_input() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "-"]);
+        assert_eq!(cli.validate(), Ok(()));
+        assert_eq!(cli.output_format(), OutputFormat::Mkv);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).output, Path::new("-"));
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "-", "--format", "webm"]).output_format(), OutputFormat::Webm);
+        assert_eq!(Cli::parse_from(["video-ascii-cli", "input.gif", "-o", "-"]).output_format_for(Path::new("input.gif")), OutputFormat::Mkv);
+        assert!(Cli::parse_from(["video-ascii-cli", "input.png", "-o", "-"]).validate().is_err());
+        assert!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "-", "--compare"]).validate().is_err());
+        assert!(Cli::parse_from(["video-ascii-cli", "input.mp4", "-o", "-", "--stats-json"]).validate().is_err());
+    }
+
+    #[test]
+    fn fallback_char_must_be_one_drawable_character() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--charset", "@一 ", "--fallback-char", " "]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).fallback_char, Some(' '));
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--fallback-char", "ab"]).is_err());
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--fallback-char", "🙂"]).is_err());
+    }
+
+    #[test]
+    fn auto_order_sorts_the_charset_by_ink() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--charset", ". :@", "--auto-order"]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!(config.charset, ". :@");
+        assert_eq!(crate::pipeline::ascii_options(&config).charset, vec!['@', ':', '.', ' ']);
+    }
+
+    #[test]
+    fn charset_overrides_ramp_and_ramp_overrides_look() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--ramp", "blocks", "--charset", "# "]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).charset, "# ");
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--look", "newspaper", "--ramp", "minimal"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).charset, "@+. ");
+    }
+
+    #[test]
+    fn explicit_flags_override_look() {
+        let cli = Cli::parse_from([
+            "video-ascii-cli",
+            "input.mp4",
+            "--look",
+            "matrix",
+            "--shades",
+            "2",
+            "--charset",
+            "01 ",
+        ]);
+        let config = cli.pipeline_config(&cli.inputs[0]);
+        assert_eq!(config.shades, 2);
+        assert_eq!(config.charset, "01 ");
+        // Untouched look settings still apply
+        assert!(config.invert);
+
+        for look in ["matrix", "terminal"] {
+            let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--look", look, "--no-invert"]);
+            assert!(!cli.pipeline_config(&cli.inputs[0]).invert, "{look}");
+        }
+        // The last of --invert/--no-invert wins
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--no-invert", "--invert"]);
+        assert!(cli.pipeline_config(&cli.inputs[0]).invert);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--look", "newspaper", "--dither", "none"]);
+        assert_eq!(cli.pipeline_config(&cli.inputs[0]).dither, Dither::None);
+    }
+
+    #[test]
+    fn resize_filters_map_to_image_filters() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.resize_filter.filter_type(), FilterType::Triangle);
+
+        for (name, filter) in [
+            ("nearest", FilterType::Nearest),
+            ("triangle", FilterType::Triangle),
+            ("catmull", FilterType::CatmullRom),
+            ("lanczos", FilterType::Lanczos3),
+        ] {
+            let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--resize-filter", name]);
+            assert_eq!(cli.resize_filter.filter_type(), filter);
+        }
+    }
+
+    #[test]
+    fn parses_bg_sample() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4"]);
+        assert_eq!(cli.bg_sample, BackgroundSample::First);
+
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--transparent", "--bg-sample", "median"]);
+        assert_eq!(cli.bg_sample, BackgroundSample::Median);
+    }
+
+    #[test]
+    fn clean_requires_work_dir() {
+        let cli = Cli::parse_from(["video-ascii-cli", "input.mp4", "--work-dir", "cache", "--clean"]);
+        assert_eq!(cli.work_dir, Some(PathBuf::from("cache")));
+        assert!(cli.clean);
+
+        assert!(Cli::try_parse_from(["video-ascii-cli", "input.mp4", "--clean"]).is_err());
     }
 
     #[test]
@@ -0,0 +1,104 @@
+use image::{DynamicImage, GrayImage};
+
+use crate::ascii::{
+    AsciiOptions, DEFAULT_CHARSET, LumaMethod, convert_frame_to_ansi_text, convert_frame_to_ascii, convert_frame_to_text,
+};
+
+/// Converts frames that are already decoded in memory, for use as a library
+/// without the CLI or ffmpeg. Color frames are reduced to grayscale with
+/// `luma` first.
+#[derive(Debug, Clone)]
+pub struct Converter {
+    options: AsciiOptions,
+    luma: LumaMethod,
+}
+
+impl Converter {
+    pub fn new(options: AsciiOptions) -> Self {
+        Self {
+            options,
+            luma: LumaMethod::Rec709,
+        }
+    }
+
+    /// Use `luma` instead of Rec. 709 weighting for color frames.
+    pub fn with_luma(mut self, luma: LumaMethod) -> Self {
+        self.luma = luma;
+        self
+    }
+
+    pub fn options(&self) -> &AsciiOptions {
+        &self.options
+    }
+
+    /// Render `frame` as an ASCII-art image, 8x8 pixels per character.
+    pub fn convert_image(&self, frame: &DynamicImage) -> GrayImage {
+        convert_frame_to_ascii(&self.luma.to_gray(frame), &self.options)
+    }
+
+    /// The characters for `frame`, one line per row of cells.
+    pub fn convert_to_text(&self, frame: &DynamicImage) -> String {
+        convert_frame_to_text(&self.luma.to_gray(frame), &self.options)
+    }
+
+    /// `convert_to_text` wrapped in 24-bit ANSI color escapes taken from
+    /// each cell's source color, ready to print to a terminal.
+    pub fn convert_to_ansi_text(&self, frame: &DynamicImage) -> String {
+        convert_frame_to_ansi_text(&self.luma.to_gray(frame), &frame.to_rgb8(), &self.options)
+    }
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self::new(AsciiOptions::new(120, DEFAULT_CHARSET, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii::RenderMode;
+    use image::{Luma, Rgb, RgbImage};
+
+    #[test]
+    fn converts_image_and_text_from_memory() {
+        // Dark left half, bright right half: 4x2 cells
+        let frame = DynamicImage::ImageLuma8(GrayImage::from_fn(32, 16, |x, _| Luma([if x < 16 { 0 } else { 255 }])));
+        let converter = Converter::new(AsciiOptions::new(4, "# ", 1));
+
+        assert_eq!(converter.convert_image(&frame).dimensions(), (32, 16));
+        assert_eq!(converter.convert_to_text(&frame), "##  \n##  ");
+    }
+
+    #[test]
+    fn text_in_blocks_mode_uses_quadrant_characters() {
+        let frame = DynamicImage::ImageLuma8(GrayImage::from_fn(8, 8, |x, _| Luma([if x < 4 { 0 } else { 255 }])));
+        let options = AsciiOptions { mode: RenderMode::Blocks, ..AsciiOptions::new(1, "# ", 1) };
+        assert_eq!(Converter::new(options).convert_to_text(&frame), "▌");
+    }
+
+    #[test]
+    fn ansi_text_colors_runs_of_cells_once() {
+        // Two red cells, then a blue one
+        let frame = DynamicImage::ImageRgb8(RgbImage::from_fn(24, 8, |x, _| {
+            if x < 16 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) }
+        }));
+        let text = Converter::new(AsciiOptions::new(3, "# ", 1)).convert_to_ansi_text(&frame);
+
+        let red = "\x1b[38;2;255;0;0m";
+        assert!(text.starts_with(red), "{text:?}");
+        assert_eq!(text.matches(red).count(), 1);
+        assert!(text.contains("\x1b[38;2;0;0;255m"));
+        assert!(text.ends_with("\x1b[0m"));
+        assert_eq!(text.matches('#').count(), 3);
+    }
+
+    #[test]
+    fn luma_method_applies_to_color_frames() {
+        // Pure red is mid-dark under Rec. 709 but brighter under a plain average
+        let frame = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, Rgb([255, 0, 0])));
+        let rec709 = Converter::new(AsciiOptions::new(1, "@%#*+=-:. ", 1));
+        let average = rec709.clone().with_luma(LumaMethod::Average);
+        assert_ne!(rec709.convert_to_text(&frame), average.convert_to_text(&frame));
+    }
+}
@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use thiserror::Error;
 
+use crate::video::{Crop, OutputFormat};
+
 pub type Result<T> = std::result::Result<T, AppError>;
 
 #[derive(Debug, Error)]
@@ -9,6 +11,15 @@ pub enum AppError {
     #[error("input file does not exist: {0}")]
     InputNotFound(PathBuf),
 
+    #[error("`.{ext}` files aren't video or image input (pass --force to try anyway)")]
+    UnsupportedInput { ext: String },
+
+    #[error("output path is the same file as the input: {0}")]
+    OutputEqualsInput(PathBuf),
+
+    #[error("crop {crop} (WIDTH:HEIGHT:X:Y) does not fit inside the {width}x{height} input")]
+    CropOutOfBounds { crop: Crop, width: u32, height: u32 },
+
     #[error("ffmpeg and ffprobe must be installed and available on PATH")]
     MissingFfmpeg,
 
@@ -19,6 +30,9 @@ pub enum AppError {
         source: std::io::Error,
     },
 
+    #[error("command `{program}` was killed after running longer than {seconds}s")]
+    CommandTimeout { program: String, seconds: f64 },
+
     #[error("command `{program}` failed (exit code {code:?}): {stderr}")]
     CommandFailed {
         program: String,
@@ -29,9 +43,46 @@ pub enum AppError {
     #[error("failed to parse ffprobe output: {0}")]
     ProbeParse(String),
 
+    #[error("{0} output does not support transparency")]
+    TransparencyUnsupported(OutputFormat),
+
+    #[error("ffmpeg was built without the `{name}` encoder this output needs")]
+    MissingEncoder { name: String },
+
+    #[error("ffmpeg's `{encoder}` encoder can't write {pix_fmt} (10-bit output needs an x264 built with high bit depth support)")]
+    UnsupportedPixelFormat { encoder: String, pix_fmt: String },
+
+    #[error("ffmpeg was built without the `{name}` filter this output needs (drawtext requires libfreetype)")]
+    MissingFilter { name: String },
+
+    #[error("unknown x264 preset `{0}` (expected one of ultrafast, superfast, veryfast, faster, fast, medium, slow, slower, veryslow, placebo)")]
+    InvalidPreset(String),
+
+    #[error("charset contains only whitespace; it needs at least one visible character")]
+    BlankCharset,
+
+    #[error("charset character {ch:?} has no glyph in the built-in font and would render as '?' (pick a substitute with --fallback-char)")]
+    UnsupportedChar { ch: char },
+
+    #[error("fallback character {ch:?} has no glyph in the built-in font")]
+    UnsupportedFallback { ch: char },
+
+    #[error("interrupted")]
+    Interrupted,
+
+    #[error("failed to decode frame {path}: {source}")]
+    FrameDecode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+
     #[error("no frames were extracted from the input video")]
     NoFramesExtracted,
 
+    #[error("tiling needs at least two variants, got {0}")]
+    TooFewVariants(usize),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
@@ -1,5 +1,11 @@
 pub mod ascii;
+pub mod bench;
 pub mod cli;
+pub mod converter;
 pub mod error;
 pub mod pipeline;
+pub mod sidecar;
 pub mod video;
+pub mod workdir;
+
+pub use converter::Converter;
@@ -1,24 +1,217 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
 use clap::Parser;
-use video_ascii_cli::cli::Cli;
-use video_ascii_cli::pipeline::{PipelineConfig, run};
+use video_ascii_cli::bench::run_bench;
+use video_ascii_cli::cli::{Cli, Commands};
+use video_ascii_cli::error::AppError;
+use video_ascii_cli::pipeline::{self, InputInfo, PipelineStats, info, plan, run, run_concat, run_tiled};
+use video_ascii_cli::sidecar::write_sidecar;
+use video_ascii_cli::video::is_stdout;
 
 fn main() {
     let cli = Cli::parse();
-    let config = PipelineConfig {
-        input: cli.input.clone(),
-        output: cli.output_path(),
-        columns: cli.columns,
-        fps: cli.fps,
-        charset: cli.charset.clone(),
-        shades: cli.shades,
-        transparent: cli.transparent,
-        bg_color: cli.bg_color,
-        threshold: cli.threshold,
-        compare: cli.compare,
-    };
+    if let Err(message) = cli.validate() {
+        eprintln!("error: {message}");
+        std::process::exit(2);
+    }
+    init_logging(cli.log_level());
+
+    if let Some(Commands::Bench(args)) = &cli.command {
+        let options = pipeline::ascii_options(&cli.pipeline_config(Path::new("bench")));
+        println!("{}", run_bench(&options, args.width, args.height, args.iterations));
+        return;
+    }
+
+    if cli.info {
+        print_info(&cli);
+    }
+
+    install_interrupt_handler();
+
+    if cli.dry_run {
+        let mut failed = false;
+        for input in &cli.inputs {
+            match plan(&cli.pipeline_config(input)) {
+                Ok(plan) => println!("{}\n{plan}", input.display()),
+                Err(err) => {
+                    eprintln!("error: {}: {err}", input.display());
+                    failed = true;
+                }
+            }
+        }
+        std::process::exit(if failed { 1 } else { 0 });
+    }
+
+    // Keep going past individual failures so one bad file doesn't sink a batch
+    let mut results: Vec<(PathBuf, Result<PipelineStats, AppError>)> = Vec::new();
+    // --concat makes one output, named after the first input
+    let inputs = if cli.concat { &cli.inputs[..1] } else { &cli.inputs[..] };
+    for input in inputs {
+        let config = cli.pipeline_config(input);
+        let result = if cli.concat {
+            let parts: Vec<_> = cli.inputs.iter().map(|input| cli.pipeline_config(input)).collect();
+            run_concat(&config, &parts)
+        } else if cli.tile {
+            run_tiled(&config, &cli.variant_configs(input))
+        } else {
+            run(&config)
+        };
+        let result = result.and_then(|stats| {
+            if cli.sidecar {
+                write_sidecar(&config, &stats)?;
+            }
+            Ok(stats)
+        });
+        if let Err(AppError::Interrupted) = result {
+            // Returning from `run` already dropped the temp dir and any partial output
+            eprintln!("interrupted");
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        match &result {
+            // stdout carries the video itself
+            Ok(stats) if !cli.stats_json && !cli.quiet && !is_stdout(&stats.output) => print_summary(stats),
+            Ok(_) => {}
+            Err(err) if inputs.len() > 1 => eprintln!("error: {}: {err}", input.display()),
+            Err(err) => eprintln!("error: {err}"),
+        }
+        if let (true, Ok(stats)) = (cli.preview, &result) {
+            preview(&stats.output, cli.quiet);
+        }
+        results.push((input.clone(), result));
+    }
+
+    if cli.stats_json {
+        let stats: Vec<&PipelineStats> = results.iter().filter_map(|(_, r)| r.as_ref().ok()).collect();
+        let json = match stats.as_slice() {
+            [single] if inputs.len() == 1 => serde_json::to_string_pretty(single),
+            all => serde_json::to_string_pretty(all),
+        };
+        match json {
+            Ok(json) => println!("{json}"),
+            Err(err) => {
+                eprintln!("error: failed to serialize stats: {err}");
+                std::process::exit(1);
+            }
+        }
+    }
 
-    if let Err(err) = run(&config) {
-        eprintln!("error: {err}");
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    if inputs.len() > 1 && !cli.quiet {
+        eprintln!();
+        eprintln!("{} succeeded, {failed} failed", results.len() - failed);
+        for (input, result) in &results {
+            let status = if result.is_ok() { "ok" } else { "FAILED" };
+            eprintln!("  {status:<6} {}", input.display());
+        }
+    }
+
+    if failed > 0 {
         std::process::exit(1);
     }
 }
+
+/// Handle `--info`: report every input and exit, non-zero if any failed.
+fn print_info(cli: &Cli) -> ! {
+    let mut reports: Vec<InputInfo> = Vec::new();
+    let mut failed = false;
+    for input in &cli.inputs {
+        match info(&cli.pipeline_config(input)) {
+            Ok(report) if cli.stats_json => reports.push(report),
+            Ok(report) => println!("{}\n{report}", input.display()),
+            Err(err) => {
+                eprintln!("error: {}: {err}", input.display());
+                failed = true;
+            }
+        }
+    }
+
+    if cli.stats_json {
+        let json = match reports.as_slice() {
+            [single] if cli.inputs.len() == 1 => serde_json::to_string_pretty(single),
+            all => serde_json::to_string_pretty(all),
+        };
+        match json {
+            Ok(json) => println!("{json}"),
+            Err(err) => {
+                eprintln!("error: failed to serialize info: {err}");
+                failed = true;
+            }
+        }
+    }
+    std::process::exit(if failed { 1 } else { 0 });
+}
+
+/// Shell convention for a process stopped by SIGINT (128 + 2).
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// First Ctrl-C asks the pipeline to stop and tidy up; a second one exits
+/// on the spot for a run stuck somewhere that doesn't check.
+fn install_interrupt_handler() {
+    let installed = ctrlc::set_handler(|| {
+        if pipeline::interrupted() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        pipeline::interrupt();
+    });
+    if let Err(err) = installed {
+        log::warn!("could not install the Ctrl-C handler: {err}");
+    }
+}
+
+/// Route this crate's `log` output to stderr up to `level`. Warnings read
+/// `warning: ...` like the rest of the CLI's messages.
+fn init_logging(level: log::LevelFilter) {
+    env_logger::Builder::new()
+        .filter_module("video_ascii_cli", level)
+        .format(|buf, record| match record.level() {
+            log::Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            level => writeln!(buf, "[{level} {}] {}", record.target(), record.args()),
+        })
+        .init();
+}
+
+/// Hand `path` to the platform's default opener. Failing to launch one only
+/// prints the path (unless `quiet`); it never fails the run.
+fn preview(path: &Path, quiet: bool) {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        // `start` is a cmd builtin; its first quoted argument is the window title
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    let launched = command
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if launched.is_err() && !quiet {
+        eprintln!("preview: no viewer found; output is at {}", path.display());
+    }
+}
+
+fn print_summary(stats: &PipelineStats) {
+    println!(
+        "wrote {} ({} frames at {:.2} fps, {:.2}s, {}x{}) in {:.1}s",
+        stats.output.display(),
+        stats.frames_processed,
+        stats.output_fps,
+        stats.duration_seconds,
+        stats.output_resolution.width,
+        stats.output_resolution.height,
+        stats.elapsed_seconds,
+    );
+    if let Some(compare_output) = &stats.compare_output {
+        println!("wrote {}", compare_output.display());
+    }
+    if let Some(audio_copy_output) = &stats.audio_copy_output {
+        println!("wrote {} (opaque, with audio)", audio_copy_output.display());
+    }
+}
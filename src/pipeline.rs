@@ -1,92 +1,2011 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
+use clap::ValueEnum;
+use image::{DynamicImage, GrayImage, ImageDecoder, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use image::imageops::{self, FilterType};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
 use tempfile::TempDir;
 
-use crate::ascii::{AsciiOptions, convert_frame_to_ascii, detect_background_color, convert_to_transparent};
+use crate::ascii::{
+    AsciiOptions, CellHistory, DEFAULT_FALLBACK_CHAR, Dither, Focus, GlyphFont, LumaHistogram, LumaMethod, Palette, Prefilter, RenderMode,
+    STANDARD_CHARSET, apply_alpha_mask,
+    background_luma, colorize_glyphs, convert_color_to_transparent, convert_frame_to_ansi_text, convert_frame_to_ascii, convert_frame_to_ascii_smoothed,
+    convert_frame_to_halfblock, convert_to_transparent, detect_background_color, flatten_onto, key_by_color,
+    order_by_ink, sharpen, validate_charset,
+};
 use crate::error::{AppError, Result};
-use crate::video;
+use crate::video::{
+    self, AudioMode, CompareLayout, Crop, EncodeOptions, OutputFormat, TimeRange, Timing, VideoCodec, VideoMetadata,
+};
+use crate::workdir::WorkDir;
+
+/// Which frames are used to auto-detect the background color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackgroundSample {
+    /// Only the first frame (fastest)
+    First,
+    /// Most common gray across every frame
+    All,
+    /// Median of each frame's most common gray
+    Median,
+}
+
+/// Interpolation used when `rows`/`max_width` resize a frame before conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResizeFilter {
+    /// Nearest neighbour: blocky, keeps hard pixel edges
+    Nearest,
+    /// Bilinear: a balance of speed and smoothness
+    Triangle,
+    /// Catmull-Rom cubic: sharper than triangle
+    Catmull,
+    /// Lanczos with window 3: smoothest, slowest
+    Lanczos,
+}
+
+impl ResizeFilter {
+    pub fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::Catmull => FilterType::CatmullRom,
+            ResizeFilter::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Mirroring applied to each source frame before conversion, so glyphs
+/// themselves are never drawn backwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Flip {
+    /// Leave frames as they are
+    None,
+    /// Mirror left to right, like a selfie camera
+    Horizontal,
+    /// Turn upside down
+    Vertical,
+    /// Both, i.e. a 180 degree rotation
+    Both,
+}
+
+impl Flip {
+    pub fn apply(self, frame: &DynamicImage) -> DynamicImage {
+        match self {
+            Flip::None => frame.clone(),
+            Flip::Horizontal => frame.fliph(),
+            Flip::Vertical => frame.flipv(),
+            Flip::Both => frame.rotate180(),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct PipelineConfig {
     pub input: PathBuf,
     pub output: PathBuf,
+    pub encode: EncodeOptions,
     pub columns: u32,
+    /// Resize the source so the output has this many character rows
+    pub rows: Option<u32>,
+    /// Downscale frames wider than this before conversion, capping output width
+    pub max_width: Option<u32>,
+    /// Scale the source up or down so the output fits this canvas as closely
+    /// as the aspect ratio allows, for the most cells that fit
+    pub fit: Option<Resolution>,
+    /// Interpolation for that resize
+    pub resize_filter: ResizeFilter,
+    /// Mirror each frame before conversion
+    pub flip: Flip,
+    /// Draw each 8x8 glyph at this integer multiple, growing the output to match
+    pub font_scale: u32,
+    /// Background pixels inserted after every cell, horizontally and vertically
+    pub cell_padding: u32,
+    /// Plain or bold glyphs
+    pub font: GlyphFont,
+    /// Average every Nth pixel of a cell instead of all of them (faster on big frames)
+    pub sample_stride: u32,
+    /// Rate the source is sampled at (and played back at, unless `out_fps`)
     pub fps: Option<f64>,
+    /// Rate the output plays at. Below the sampling rate gives slow motion,
+    /// above it a time-lapse; the audio is dropped since it can't follow
+    pub out_fps: Option<f64>,
+    /// Only process this part of the source
+    pub range: TimeRange,
+    /// Only convert this region of each frame
+    pub crop: Option<Crop>,
     pub charset: String,
+    /// Sort `charset` dark to light by glyph ink coverage before rendering
+    pub auto_order: bool,
+    /// Drawn in place of `charset` characters the built-in font can't render;
+    /// without one those characters are an error
+    pub fallback_char: Option<char>,
     pub shades: u32,
+    /// Grayscale conversion applied to color frames
+    pub luma: LumaMethod,
+    /// Draw light glyphs on a black background
+    pub invert: bool,
+    /// Stretch cell brightness around mid-gray before picking characters
+    pub contrast: bool,
+    /// Smooth each frame before conversion to steady noisy footage
+    pub prefilter: Prefilter,
+    /// Strength of `prefilter` in pixels (Gaussian sigma or median radius)
+    pub blur_radius: f32,
+    /// Unsharp-mask strength applied after `prefilter`, to crisp up soft footage
+    pub sharpen: Option<f32>,
+    /// Blend each cell's luma with the previous frame's, weighting the new
+    /// frame by this alpha, so near-static scenes stop flickering
+    pub temporal_smooth: Option<f32>,
+    /// Spend finer cells in the middle of the frame than at its edges
+    pub focus: Focus,
+    /// Fraction of the frame, from the center out, kept at full detail with `focus`
+    pub focus_falloff: f32,
+    /// Error diffusion across cells in B/W mode
+    pub dither: Dither,
+    /// Dark/light cutoff for pure B/W, 2-shade and blocks rendering
+    pub bw_threshold: u8,
+    /// Drive characters by brightness or by edge strength
+    pub mode: RenderMode,
+    /// Quantize half-block cell colors to a fixed or per-video palette
+    pub palette: Option<Palette>,
+    /// Color glyphs from their cells' source colors: the average with 1, the
+    /// darkest and lightest with 2 (see [`colorize_glyphs`])
+    pub max_colors_per_cell: Option<u8>,
     pub transparent: bool,
     pub bg_color: Option<u8>,
+    /// Frames sampled when auto-detecting `bg_color`
+    pub bg_sample: BackgroundSample,
+    /// Detect the key color on every rendered frame instead of once up front
+    pub bg_per_frame: bool,
     /// Tolerance for background matching (0 = exact, 255 = everything).
     /// Pixels within ±threshold of the detected/specified bg_color are made transparent.
     pub threshold: u8,
-    /// Create a comparison video with original and ASCII versions stacked vertically
-    pub compare: bool,
+    /// Levels beyond `threshold` over which alpha ramps up to opaque (0 = hard edge)
+    pub feather: u8,
+    /// Key this color out of the source frame in HSV space instead of keying
+    /// the rendered ASCII by luma (`bg_color`/`threshold` are then unused)
+    pub key_color: Option<Rgb<u8>>,
+    /// HSV distance from `key_color` still treated as background (0-1)
+    pub key_tolerance: f32,
+    /// Paint the keyed background this color instead of making it
+    /// transparent, for opaque output such as MP4
+    pub flatten_bg: Option<Rgb<u8>>,
+    /// Write the converted frames here as numbered PNGs instead of encoding
+    /// `output`; RGBA when the output keeps transparency
+    pub frames_out: Option<PathBuf>,
+    /// Write `output` as 24-bit ANSI colored text instead of images or video:
+    /// one frame for an image, every frame of a video, each starting at the
+    /// top-left of the terminal so `cat` plays them back in place
+    pub text_ansi: bool,
+    /// Where to write a comparison video with the original and ASCII versions
+    /// stacked; the plain ASCII output at `output` is kept as well
+    pub compare_output: Option<PathBuf>,
+    /// Stack the comparison vertically or side by side
+    pub compare_layout: CompareLayout,
+    /// Also encode an MP4 here from the same converted frames, flattened onto
+    /// the canvas color, carrying the audio a transparent `output` can't hold
+    pub audio_copy_output: Option<PathBuf>,
+    /// ffmpeg binary to invoke (defaults to `ffmpeg` on PATH)
+    pub ffmpeg: PathBuf,
+    /// ffprobe binary to invoke (defaults to `ffprobe` on PATH)
+    pub ffprobe: PathBuf,
+    /// Keep extracted/converted frames here instead of a temp dir so an
+    /// interrupted run can resume
+    pub work_dir: Option<PathBuf>,
+    /// Discard anything cached in `work_dir` before starting
+    pub clean: bool,
+    /// Extract frames to PNG files on disk instead of streaming them from ffmpeg
+    pub disk_frames: bool,
+    /// Warn about and drop extracted frames that fail to decode instead of
+    /// aborting the run
+    pub skip_bad_frames: bool,
+    /// Stop after converting this many frames, for quick previews
+    pub max_frames: Option<u32>,
+    /// Convert every Nth frame and repeat it for the next N-1 ("on twos"
+    /// with 2); 1 converts every frame
+    pub hold: u32,
+    /// Copy the previous converted frame instead of converting a source
+    /// frame identical to the one before it (static screen recordings,
+    /// slideshows). Off with `temporal_smooth`, which changes repeats.
+    pub dedup: bool,
+    /// Hand inputs with an unrecognized extension to ffprobe anyway
+    pub force: bool,
+    /// Kill any ffmpeg extraction, encode, comparison or tiling command that
+    /// runs longer than this, so a hung ffmpeg can't block a batch forever
+    pub timeout: Option<Duration>,
+    /// With `disk_frames`, extract and convert this many frames at a time
+    /// instead of extracting the whole clip up front
+    pub batch_size: Option<u32>,
+    /// Draw progress spinners/bars on stderr while running
+    pub progress: bool,
+}
+
+impl PipelineConfig {
+    /// Whether the output carries transparency, which `flatten_bg` paints over.
+    pub fn keeps_alpha(&self) -> bool {
+        self.transparent && self.flatten_bg.is_none()
+    }
+
+    /// Start a config for `input` -> `output` with the same defaults as the
+    /// CLI, so library callers only set what they change.
+    pub fn builder(input: impl Into<PathBuf>, output: impl Into<PathBuf>) -> PipelineConfigBuilder {
+        PipelineConfigBuilder {
+            config: PipelineConfig {
+                input: input.into(),
+                output: output.into(),
+                encode: EncodeOptions::default(),
+                columns: 120,
+                rows: None,
+                max_width: None,
+                fit: None,
+                resize_filter: ResizeFilter::Triangle,
+                flip: Flip::None,
+                font_scale: 1,
+                cell_padding: 0,
+                font: GlyphFont::Regular,
+                sample_stride: 1,
+                fps: None,
+                out_fps: None,
+                range: TimeRange::default(),
+                crop: None,
+                charset: STANDARD_CHARSET.to_string(),
+                auto_order: false,
+                fallback_char: None,
+                shades: 1,
+                luma: LumaMethod::Rec709,
+                invert: false,
+                contrast: true,
+                prefilter: Prefilter::None,
+                blur_radius: 1.0,
+                sharpen: None,
+                temporal_smooth: None,
+                focus: Focus::None,
+                focus_falloff: 0.5,
+                dither: Dither::None,
+                bw_threshold: 128,
+                mode: RenderMode::Brightness,
+                palette: None,
+                max_colors_per_cell: None,
+                transparent: false,
+                bg_color: None,
+                bg_sample: BackgroundSample::First,
+                bg_per_frame: false,
+                threshold: 0,
+                feather: 0,
+                key_color: None,
+                key_tolerance: 0.2,
+                flatten_bg: None,
+                frames_out: None,
+                text_ansi: false,
+                compare_output: None,
+                compare_layout: CompareLayout::Vertical,
+                audio_copy_output: None,
+                ffmpeg: PathBuf::from(video::DEFAULT_FFMPEG),
+                ffprobe: PathBuf::from(video::DEFAULT_FFPROBE),
+                work_dir: None,
+                clean: false,
+                disk_frames: false,
+                skip_bad_frames: false,
+                max_frames: None,
+                hold: 1,
+                dedup: false,
+                force: false,
+                timeout: None,
+                batch_size: None,
+                progress: false,
+            },
+            format: None,
+        }
+    }
+}
+
+/// Builder returned by [`PipelineConfig::builder`]. Like the CLI, the output
+/// format follows the output extension (WebP for `transparent` otherwise)
+/// unless [`format`](Self::format) picks one.
+#[derive(Debug, Clone)]
+pub struct PipelineConfigBuilder {
+    config: PipelineConfig,
+    format: Option<OutputFormat>,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// One builder method per field. `into` fields (optional values, strings and
+/// paths) accept anything convertible, e.g. `rows(40)` or `charset("#. ")`.
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* ; into $($into_field:ident: $into_ty:ty),* $(,)?) => {
+        $(
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.config.$field = $field;
+                self
+            }
+        )*
+        $(
+            pub fn $into_field(mut self, $into_field: impl Into<$into_ty>) -> Self {
+                self.config.$into_field = $into_field.into();
+                self
+            }
+        )*
+    };
+}
+
+impl PipelineConfigBuilder {
+    setters! {
+        encode: EncodeOptions,
+        columns: u32,
+        resize_filter: ResizeFilter,
+        flip: Flip,
+        font_scale: u32,
+        cell_padding: u32,
+        font: GlyphFont,
+        sample_stride: u32,
+        range: TimeRange,
+        auto_order: bool,
+        shades: u32,
+        luma: LumaMethod,
+        invert: bool,
+        contrast: bool,
+        prefilter: Prefilter,
+        blur_radius: f32,
+        focus: Focus,
+        focus_falloff: f32,
+        dither: Dither,
+        bw_threshold: u8,
+        mode: RenderMode,
+        transparent: bool,
+        bg_sample: BackgroundSample,
+        bg_per_frame: bool,
+        threshold: u8,
+        feather: u8,
+        key_tolerance: f32,
+        compare_layout: CompareLayout,
+        clean: bool,
+        disk_frames: bool,
+        skip_bad_frames: bool,
+        hold: u32,
+        dedup: bool,
+        force: bool,
+        text_ansi: bool,
+        progress: bool;
+        into
+        rows: Option<u32>,
+        max_width: Option<u32>,
+        fit: Option<Resolution>,
+        fps: Option<f64>,
+        out_fps: Option<f64>,
+        timeout: Option<Duration>,
+        temporal_smooth: Option<f32>,
+        sharpen: Option<f32>,
+        palette: Option<Palette>,
+        max_colors_per_cell: Option<u8>,
+        fallback_char: Option<char>,
+        crop: Option<Crop>,
+        charset: String,
+        bg_color: Option<u8>,
+        flatten_bg: Option<Rgb<u8>>,
+        key_color: Option<Rgb<u8>>,
+        frames_out: Option<PathBuf>,
+        compare_output: Option<PathBuf>,
+        audio_copy_output: Option<PathBuf>,
+        ffmpeg: PathBuf,
+        ffprobe: PathBuf,
+        work_dir: Option<PathBuf>,
+        max_frames: Option<u32>,
+        batch_size: Option<u32>,
+    }
+
+    /// Output container/codec, overriding the one inferred from the output path.
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn build(self) -> PipelineConfig {
+        let mut config = self.config;
+        config.encode.format = self
+            .format
+            .or_else(|| OutputFormat::from_path(&config.output))
+            .unwrap_or(if config.keeps_alpha() { OutputFormat::Webp } else { OutputFormat::Mp4 });
+        config
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Step of a run reported through [`run_with_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Writing source frames to disk (`disk_frames` only)
+    Extracting,
+    /// Scanning frames for the background key (`bg_sample` other than `first`)
+    SamplingBackground,
+    /// Converting frames to ASCII, one unit per frame
+    Converting,
+    /// Encoding the converted frames into the output file
+    Encoding,
+    /// Writing the side-by-side comparison video
+    Comparing,
+    /// Encoding the opaque MP4 that carries the audio (`audio_copy_output`)
+    EncodingAudioCopy,
+}
+
+impl Stage {
+    fn label(self) -> &'static str {
+        match self {
+            Stage::Extracting => "Extracting frames",
+            Stage::SamplingBackground => "Sampling background",
+            Stage::Converting => "Converting frames",
+            Stage::Encoding => "Encoding output",
+            Stage::Comparing => "Writing comparison",
+            Stage::EncodingAudioCopy => "Encoding audio copy",
+        }
+    }
+}
+
+/// One progress update: `current` of `total` units of `stage` are done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineProgress {
+    pub stage: Stage,
+    pub current: u64,
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct PipelineStats {
     pub frames_processed: usize,
     pub output_fps: f64,
+    /// Playback length of the output (`frames_processed / output_fps`)
+    pub duration_seconds: f64,
+    pub input_resolution: Resolution,
+    pub output_resolution: Resolution,
+    /// Wall-clock time spent in `run`
+    pub elapsed_seconds: f64,
+    /// Path of the ASCII video that was written, or the `frames_out` directory
+    pub output: PathBuf,
+    /// Path of the comparison video, if one was requested
+    pub compare_output: Option<PathBuf>,
+    /// Path of the opaque MP4 with audio, if one was requested
+    pub audio_copy_output: Option<PathBuf>,
+    /// Frames copied from the one before instead of converted, with `dedup`
+    pub frames_reused: usize,
 }
 
+/// What `run` would do for one input, as reported by `--dry-run`.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub input_resolution: Resolution,
+    pub output_resolution: Resolution,
+    /// Output framerate; `None` for still images
+    pub fps: Option<f64>,
+    /// Frames that would be converted, when the container reports enough to tell
+    pub expected_frames: Option<u64>,
+    pub output: PathBuf,
+    pub compare_output: Option<PathBuf>,
+    pub audio_copy_output: Option<PathBuf>,
+    /// Shell-quoted ffmpeg invocations, in the order they would run
+    pub commands: Vec<String>,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Resolution { width, height } = self.input_resolution;
+        writeln!(f, "input:    {width}x{height}")?;
+        let Resolution { width, height } = self.output_resolution;
+        writeln!(f, "output:   {} ({width}x{height})", self.output.display())?;
+        if let Some(compare_output) = &self.compare_output {
+            writeln!(f, "compare:  {}", compare_output.display())?;
+        }
+        if let Some(audio_copy_output) = &self.audio_copy_output {
+            writeln!(f, "audio:    {}", audio_copy_output.display())?;
+        }
+        if let Some(fps) = self.fps {
+            writeln!(f, "fps:      {fps:.2}")?;
+        }
+        match self.expected_frames {
+            Some(frames) => writeln!(f, "frames:   ~{frames}")?,
+            None => writeln!(f, "frames:   unknown")?,
+        }
+        for command in &self.commands {
+            writeln!(f, "  {command}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Character grid a frame is rendered to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// What `--info` reports about one input: probed facts and the grid it would
+/// render to, without converting anything.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputInfo {
+    pub input_resolution: Resolution,
+    /// Source framerate; `None` for still images
+    pub fps: Option<f64>,
+    /// Stream duration in seconds, when the container reports it
+    pub duration_seconds: Option<f64>,
+    pub grid: Grid,
+    pub output_resolution: Resolution,
+}
+
+impl fmt::Display for InputInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Resolution { width, height } = self.input_resolution;
+        writeln!(f, "input:    {width}x{height}")?;
+        if let Some(fps) = self.fps {
+            writeln!(f, "fps:      {fps:.3}")?;
+        }
+        match self.duration_seconds {
+            Some(duration) => writeln!(f, "duration: {duration:.2}s")?,
+            None if self.fps.is_some() => writeln!(f, "duration: unknown")?,
+            None => {}
+        }
+        let Grid { columns, rows } = self.grid;
+        writeln!(f, "grid:     {columns}x{rows}")?;
+        let Resolution { width, height } = self.output_resolution;
+        writeln!(f, "output:   {width}x{height}")
+    }
+}
+
+/// Set by [`interrupt`]; checked between frames and after every ffmpeg run.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Ask the running pipeline to stop, e.g. from a Ctrl-C handler. It returns
+/// [`AppError::Interrupted`] at the next frame or once the current ffmpeg
+/// command exits, removing an output file it was partway through encoding
+/// (a pre-existing file at that path is left alone until encoding starts).
+/// Frames already written to `frames_out` are kept. The temp dir is removed
+/// as the pipeline unwinds; a `work_dir` is kept so the run can resume.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether [`interrupt`] has been called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+fn check_interrupted() -> Result<()> {
+    if interrupted() { Err(AppError::Interrupted) } else { Ok(()) }
+}
+
+/// On interrupt, delete the half-written `path` and report the interruption
+/// instead of whatever error the killed ffmpeg produced.
+fn finish_output<T>(path: &Path, result: Result<T>) -> Result<T> {
+    if !interrupted() {
+        return result;
+    }
+    match std::fs::remove_file(path) {
+        // stdout has no file to clean up
+        _ if video::is_stdout(path) => {}
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => log::warn!("could not remove {}: {err}", path.display()),
+        _ => log::debug!("removed partial output {}", path.display()),
+    }
+    Err(AppError::Interrupted)
+}
+
+/// Convert `config.input` into `config.output`. Progress goes to stderr as
+/// spinners and a frame bar when `config.progress` is set.
 pub fn run(config: &PipelineConfig) -> Result<PipelineStats> {
-    if !config.input.exists() {
+    if config.progress {
+        let mut terminal = TerminalProgress::default();
+        run_with_progress(config, &mut |update| terminal.update(update))
+    } else {
+        run_with_progress(config, &mut |_| {})
+    }
+}
+
+/// Like [`run`], but reports progress to `progress` instead of drawing
+/// anything, for embedding in other UIs.
+///
+/// The callback runs on the calling thread: once with `current == 0` when a
+/// stage starts, then after every finished unit of work. `Converting` counts
+/// frames against a best-effort `total` (`None` when the source doesn't
+/// report a frame count, and the count can run past an estimate); every
+/// other stage is a single unit with `total == Some(1)`. A stage ends when
+/// the next one starts or this function returns; stages that don't apply,
+/// like `Extracting` when frames are streamed, are skipped.
+pub fn run_with_progress(config: &PipelineConfig, progress: &mut dyn FnMut(PipelineProgress)) -> Result<PipelineStats> {
+    log::debug!("config: {config:?}");
+    check_input(config)?;
+
+    let started = Instant::now();
+    let result = video::with_command_timeout(config.timeout, || {
+        if config.text_ansi {
+            run_text(config, progress)
+        } else if video::is_image_path(&config.input) {
+            run_image(config, progress)
+        } else {
+            run_video(config, progress)
+        }
+    });
+    // ffmpeg gets the same Ctrl-C, so its failure is just the interruption
+    let mut stats = result.map_err(|err| if interrupted() { AppError::Interrupted } else { err })?;
+    stats.elapsed_seconds = started.elapsed().as_secs_f64();
+
+    Ok(stats)
+}
+
+/// Convert `config.input` once per entry of `variants` and stack the renders
+/// into one H.264 grid video at `config.output` (see [`video::tile_grid`]),
+/// to compare looks side by side. Each variant is rendered to an MP4 in a temp
+/// dir first; its own output paths are ignored. Stats are those of the first
+/// tile, with the grid's resolution.
+pub fn run_tiled(config: &PipelineConfig, variants: &[PipelineConfig]) -> Result<PipelineStats> {
+    if variants.len() < 2 {
+        return Err(AppError::TooFewVariants(variants.len()));
+    }
+    check_input(config)?;
+
+    let started = Instant::now();
+    let temp = TempDir::new()?;
+    let mut tiles = Vec::with_capacity(variants.len());
+    for (index, variant) in variants.iter().enumerate() {
+        let tile = PipelineConfig {
+            output: temp.path().join(format!("tile_{index}.mp4")),
+            encode: EncodeOptions {
+                format: OutputFormat::Mp4,
+                ..variant.encode.clone()
+            },
+            frames_out: None,
+            compare_output: None,
+            audio_copy_output: None,
+            ..variant.clone()
+        };
+        log::debug!("rendering tile {}/{}", index + 1, variants.len());
+        tiles.push(run(&tile)?);
+    }
+
+    let paths: Vec<PathBuf> = tiles.iter().map(|tile| tile.output.clone()).collect();
+    let created = video::with_command_timeout(config.timeout, || {
+        video::create_tile_video(&config.ffmpeg, &paths, &config.output)
+    });
+    finish_output(&config.output, created)?;
+
+    let (columns, rows) = video::tile_grid(tiles.len());
+    let first = &tiles[0];
+    Ok(PipelineStats {
+        output_resolution: Resolution {
+            width: first.output_resolution.width * columns,
+            height: first.output_resolution.height * rows,
+        },
+        elapsed_seconds: started.elapsed().as_secs_f64(),
+        output: config.output.clone(),
+        ..first.clone()
+    })
+}
+
+/// Convert the input of every entry of `parts` in order and encode all the
+/// frames once, as one video at `config.output` with `config`'s encode
+/// settings. Inputs are normalized to the first one: sampled at its frame
+/// rate and rendered as many rows tall, unless `config` sets `fps`/`rows`.
+/// Frames still smaller than the largest are centered on background, never
+/// rescaled. Source audio isn't joined, so the output is silent.
+pub fn run_concat(config: &PipelineConfig, parts: &[PipelineConfig]) -> Result<PipelineStats> {
+    let Some(first) = parts.first() else {
+        return Err(AppError::NoFramesExtracted);
+    };
+    for part in parts {
+        if video::same_file(&part.input, &config.output) {
+            return Err(AppError::OutputEqualsInput(config.output.clone()));
+        }
+    }
+
+    let started = Instant::now();
+    let reference = info(first)?;
+    // A still image has no rate of its own; without `fps` it shows for a second
+    let fps = config.fps.or(reference.fps).unwrap_or(1.0);
+    let rows = config.rows.or(Some(reference.grid.rows));
+
+    let temp = TempDir::new()?;
+    let mut rendered = Vec::with_capacity(parts.len());
+    for (index, part) in parts.iter().enumerate() {
+        let frames_dir = temp.path().join(format!("part_{index}"));
+        let part = PipelineConfig {
+            fps: Some(fps),
+            out_fps: None,
+            rows,
+            encode: EncodeOptions {
+                timing: Timing::Cfr,
+                ..part.encode.clone()
+            },
+            frames_out: Some(frames_dir.clone()),
+            compare_output: None,
+            audio_copy_output: None,
+            ..part.clone()
+        };
+        log::debug!("converting part {}/{}: {}", index + 1, parts.len(), part.input.display());
+        rendered.push((frames_dir, run(&part)?));
+    }
+
+    let width = rendered.iter().map(|(_, stats)| stats.output_resolution.width).max().unwrap_or(1);
+    let height = rendered.iter().map(|(_, stats)| stats.output_resolution.height).max().unwrap_or(1);
+    let fill = concat_fill(config);
+
+    // One running frame number across every part's own numbered sequence
+    let frames_dir = temp.path().join("frames");
+    std::fs::create_dir_all(&frames_dir)?;
+    let mut frame_count = 0;
+    for (part_dir, stats) in &rendered {
+        for number in 1..=stats.frames_processed {
+            check_interrupted()?;
+            frame_count += 1;
+            let (from, to) = (part_dir.join(frame_name(number)), frames_dir.join(frame_name(frame_count)));
+            if (stats.output_resolution.width, stats.output_resolution.height) == (width, height) {
+                std::fs::rename(&from, &to)?;
+            } else {
+                pad_frame(video::open_frame(&from)?, width, height, fill).save(&to)?;
+            }
+        }
+    }
+
+    let playback_fps = config.out_fps.unwrap_or(fps);
+    let encode = EncodeOptions {
+        audio: AudioMode::None,
+        timing: Timing::Cfr,
+        ..config.encode.clone()
+    };
+    let encoded = video::with_command_timeout(config.timeout, || {
+        video::encode_video(
+            &config.ffmpeg,
+            &frames_dir,
+            &first.input,
+            &TimeRange::default(),
+            playback_fps,
+            &config.output,
+            &encode,
+        )
+    });
+    finish_output(&config.output, encoded)?;
+
+    Ok(PipelineStats {
+        frames_processed: frame_count,
+        output_fps: playback_fps,
+        duration_seconds: frame_count as f64 / playback_fps,
+        input_resolution: reference.input_resolution,
+        output_resolution: Resolution { width, height },
+        elapsed_seconds: started.elapsed().as_secs_f64(),
+        output: config.output.clone(),
+        compare_output: None,
+        audio_copy_output: None,
+        frames_reused: rendered.iter().map(|(_, stats)| stats.frames_reused).sum(),
+    })
+}
+
+/// Background `run_concat` pads smaller frames with: the flatten color,
+/// transparent background gray, or opaque background gray.
+fn concat_fill(config: &PipelineConfig) -> Rgba<u8> {
+    let gray = background_luma(config.invert);
+    match config.flatten_bg {
+        Some(Rgb([r, g, b])) if config.transparent => Rgba([r, g, b, 255]),
+        _ if config.keeps_alpha() => Rgba([gray, gray, gray, 0]),
+        _ => Rgba([gray, gray, gray, 255]),
+    }
+}
+
+/// `frame` centered on a `width` x `height` canvas of `fill`, keeping its
+/// color type so every frame of a sequence decodes the same way.
+fn pad_frame(frame: DynamicImage, width: u32, height: u32, fill: Rgba<u8>) -> DynamicImage {
+    let x = (width.saturating_sub(frame.width()) / 2) as i64;
+    let y = (height.saturating_sub(frame.height()) / 2) as i64;
+    let [r, g, b, _] = fill.0;
+    match frame {
+        DynamicImage::ImageLuma8(frame) => {
+            let mut canvas = GrayImage::from_pixel(width, height, Luma([r]));
+            imageops::replace(&mut canvas, &frame, x, y);
+            canvas.into()
+        }
+        DynamicImage::ImageRgb8(frame) => {
+            let mut canvas = RgbImage::from_pixel(width, height, Rgb([r, g, b]));
+            imageops::replace(&mut canvas, &frame, x, y);
+            canvas.into()
+        }
+        frame => {
+            let mut canvas = RgbaImage::from_pixel(width, height, fill);
+            imageops::replace(&mut canvas, &frame.to_rgba8(), x, y);
+            canvas.into()
+        }
+    }
+}
+
+/// Work out what `run` would do without writing anything: probe the input,
+/// resolve sizes and paths, and build the ffmpeg command lines.
+pub fn plan(config: &PipelineConfig) -> Result<Plan> {
+    check_input(config)?;
+
+    if video::is_image_path(&config.input) {
+        let (width, height) = image::image_dimensions(&config.input)?;
+        return Ok(Plan {
+            input_resolution: Resolution { width, height },
+            output_resolution: output_resolution(config, width, height),
+            fps: None,
+            expected_frames: Some(1),
+            output: config.frames_out.as_deref().map_or_else(|| config.output.clone(), first_frame_path),
+            compare_output: None,
+            audio_copy_output: None,
+            commands: Vec::new(),
+        });
+    }
+
+    check_video_tools(config)?;
+    let metadata = video::probe_video(&config.ffprobe, &config.input)?;
+    let fps = playback_fps(config, &metadata);
+    let encode_options = retimed_encode(config, &metadata);
+    let extract_fps = extraction_fps(config, &metadata);
+    let input_resolution = input_resolution(config, &metadata)?;
+    let output_resolution = output_resolution(config, input_resolution.width, input_resolution.height);
+
+    // Nothing is created, so a temp dir stands in as a placeholder
+    let root = config.work_dir.clone().unwrap_or_else(|| PathBuf::from("<temp-dir>"));
+    let work = WorkDir::unopened(&root);
+
+    let decode = if config.disk_frames {
+        let limit = [config.batch_size, config.max_frames].into_iter().flatten().min();
+        video::extract_args(&config.input, &config.range, config.crop, extract_fps, limit, &work.extracted_dir())
+    } else {
+        video::stream_args(&config.input, &config.range, config.crop, extract_fps)
+    };
+    let mut commands = vec![video::shell_command(&config.ffmpeg, &decode)];
+    if config.frames_out.is_none() {
+        let encode = video::encode_args(
+            &work.ascii_dir(),
+            &config.input,
+            &config.range,
+            fps,
+            &config.output,
+            &encode_options,
+            encode_options.audio,
+        );
+        commands.push(video::shell_command(&config.ffmpeg, &encode));
+    }
+    if let Some(audio_copy_output) = &config.audio_copy_output {
+        let audio_copy = audio_copy_encode(&encode_options);
+        let encode = video::encode_args(
+            &work.opaque_dir(),
+            &config.input,
+            &config.range,
+            fps,
+            audio_copy_output,
+            &audio_copy,
+            audio_copy.audio,
+        );
+        commands.push(video::shell_command(&config.ffmpeg, &encode));
+    }
+    if let Some(compare_output) = &config.compare_output {
+        let compare = video::comparison_args(
+            &config.input,
+            &config.output,
+            &config.range,
+            compare_output,
+            config.compare_layout,
+            (metadata.display_size(), (output_resolution.width, output_resolution.height)),
+        );
+        commands.push(video::shell_command(&config.ffmpeg, &compare));
+    }
+
+    Ok(Plan {
+        input_resolution,
+        output_resolution,
+        fps: Some(fps),
+        expected_frames: cap_frames(expected_frames(&metadata, &config.range, extract_fps), config.max_frames),
+        output: config.frames_out.clone().unwrap_or_else(|| config.output.clone()),
+        compare_output: config.compare_output.clone(),
+        audio_copy_output: config.audio_copy_output.clone(),
+        commands,
+    })
+}
+
+/// Probe `config.input` and work out the grid it renders to. Only ffprobe is
+/// run (nothing for still images); ffmpeg and the output aren't checked.
+pub fn info(config: &PipelineConfig) -> Result<InputInfo> {
+    find_input(config)?;
+
+    let (input_resolution, fps, duration_seconds) = if video::is_image_path(&config.input) {
+        let (width, height) = image::image_dimensions(&config.input)?;
+        (Resolution { width, height }, None, None)
+    } else {
+        let metadata = video::probe_video(&config.ffprobe, &config.input)?;
+        (input_resolution(config, &metadata)?, Some(metadata.effective_fps()), metadata.duration)
+    };
+    let (width, height) = source_size(config, input_resolution.width, input_resolution.height);
+
+    Ok(InputInfo {
+        input_resolution,
+        fps,
+        duration_seconds,
+        grid: Grid {
+            columns: width.div_ceil(8),
+            rows: height.div_ceil(8),
+        },
+        output_resolution: output_resolution(config, input_resolution.width, input_resolution.height),
+    })
+}
+
+/// The input exists and, unless `force` is set, doesn't carry the extension
+/// of something that clearly isn't media, which ffprobe would only reject
+/// with a confusing error.
+fn find_input(config: &PipelineConfig) -> Result<()> {
+    if !video::is_url(&config.input) && !config.input.exists() {
         return Err(AppError::InputNotFound(config.input.clone()));
     }
+    match video::unsupported_extension(&config.input) {
+        Some(ext) if !config.force => Err(AppError::UnsupportedInput { ext }),
+        _ => Ok(()),
+    }
+}
+
+/// Checks shared by `run` and `plan` before any input is read.
+fn check_input(config: &PipelineConfig) -> Result<()> {
+    find_input(config)?;
+
+    // Writing over the input would truncate it while ffmpeg is still reading
+    for output in std::iter::once(&config.output).chain(&config.compare_output).chain(&config.audio_copy_output) {
+        if video::same_file(&config.input, output) {
+            return Err(AppError::OutputEqualsInput(output.clone()));
+        }
+    }
+
+    for warning in validate_charset(&config.charset, config.shades, config.fallback_char)? {
+        log::warn!("{warning}");
+    }
+    if config.palette.is_some() && config.mode != RenderMode::Halfblock {
+        log::warn!("--palette only applies to --mode halfblock and is ignored");
+    }
+    if config.max_colors_per_cell.is_some() && config.mode == RenderMode::Halfblock {
+        log::warn!("--max-colors-per-cell doesn't apply to --mode halfblock, which is already in full color");
+    }
+    Ok(())
+}
+
+/// Settings and tool checks for video input, so a bad combination fails
+/// before any frame is decoded.
+fn check_video_tools(config: &PipelineConfig) -> Result<()> {
+    if config.frames_out.is_some() {
+        // Frames are only decoded: nothing is encoded and PNGs hold alpha
+        if config.encode.label.is_some() {
+            log::warn!("--label is drawn by ffmpeg while encoding and is not applied to --frames-out");
+        }
+        if !video::tools_available(&config.ffmpeg, &config.ffprobe) {
+            return Err(AppError::MissingFfmpeg);
+        }
+        return Ok(());
+    }
 
-    if !video::tools_available() {
+    if config.keeps_alpha() && !config.encode.format.supports_alpha() {
+        return Err(AppError::TransparencyUnsupported(config.encode.format));
+    }
+
+    video::validate_preset(&config.encode.preset)?;
+
+    let wants_audio = matches!(config.encode.audio, AudioMode::Copy | AudioMode::Aac);
+    if wants_audio && !config.encode.format.uses_codec() {
+        log::warn!(
+            "{} output carries no audio; the source audio is dropped",
+            config.encode.format
+        );
+    }
+
+    if !video::tools_available(&config.ffmpeg, &config.ffprobe) {
         return Err(AppError::MissingFfmpeg);
     }
 
-    let metadata = video::probe_video(&config.input)?;
-    let fps = config.fps.unwrap_or(metadata.fps);
+    if config.encode.format == OutputFormat::Mp4 && !config.encode.browser_compatible() {
+        if config.encode.codec == VideoCodec::X265 {
+            log::warn!("HEVC MP4 output may not play in common browsers; use --codec x264 or av1 for compatibility");
+        } else {
+            log::warn!(
+                "{} MP4 output may not play in common browsers; use 8-bit yuv420p for compatibility",
+                config.encode.mp4_pixel_format()
+            );
+        }
+    }
 
-    let temp_dir = TempDir::new()?;
-    let extracted_dir = temp_dir.path().join("extracted");
-    let ascii_dir = temp_dir.path().join("ascii");
+    let h264 = config.compare_output.is_some() || config.audio_copy_output.is_some();
+    video::verify_encoders(&config.ffmpeg, &config.encode, h264)?;
+    video::verify_pixel_format(&config.ffmpeg, &config.encode)?;
+    video::verify_filters(&config.ffmpeg, &config.encode)
+}
 
-    let frames = video::extract_frames(&config.input, &extracted_dir)?;
-    std::fs::create_dir_all(&ascii_dir)?;
+fn run_video(config: &PipelineConfig, progress: &mut dyn FnMut(PipelineProgress)) -> Result<PipelineStats> {
+    check_video_tools(config)?;
 
-    let options = AsciiOptions::new(config.columns, &config.charset, config.shades);
+    let metadata = video::probe_video(&config.ffprobe, &config.input)?;
+    let input_resolution = input_resolution(config, &metadata)?;
+    if metadata.is_variable_rate() && config.encode.timing == Timing::Cfr {
+        log::warn!(
+            "variable frame rate source ({:.3} fps base, {:.3} fps average); resampling to a constant {:.3} fps",
+            metadata.fps,
+            metadata.effective_fps(),
+            config.fps.unwrap_or(metadata.effective_fps())
+        );
+    }
+    let fps = playback_fps(config, &metadata);
 
-    // Detect background color from first frame if not specified
-    let bg_color = if config.transparent {
-        match config.bg_color {
-            Some(color) => color,
-            None => {
-                let first_frame = image::open(&frames[0])?.to_luma8();
-                detect_background_color(&first_frame)
-            }
+    // Frames live in a throwaway temp dir unless --work-dir asks to keep them
+    let temp_dir;
+    let root = match &config.work_dir {
+        Some(dir) => dir.as_path(),
+        None => {
+            temp_dir = TempDir::new()?;
+            temp_dir.path()
+        }
+    };
+    let extraction_key = format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}",
+        config.input,
+        config.range,
+        config.crop,
+        extraction_fps(config, &metadata),
+        config.max_frames
+    );
+    let mut work = WorkDir::open(root, extraction_key, config.clean)?;
+    let ascii_dir = work.ascii_dir();
+
+    let source = if !config.disk_frames {
+        FrameSource::Stream
+    } else if work.frames_extracted() {
+        FrameSource::Disk(video::list_frames(&work.extracted_dir())?)
+    } else if let Some(size) = config.batch_size {
+        FrameSource::Batched {
+            size,
+            dir: work.extracted_dir(),
         }
     } else {
-        255 // Not used in non-transparent mode
+        let started = Instant::now();
+        let (input, range, crop, fps, dir) =
+            (&config.input, &config.range, config.crop, extraction_fps(config, &metadata), &work.extracted_dir());
+        let paths = single_step(progress, Stage::Extracting, || match config.max_frames {
+            Some(count) => video::extract_frame_batch(&config.ffmpeg, input, range, crop, fps, count, dir),
+            None => video::extract_frames(&config.ffmpeg, input, range, crop, fps, dir),
+        })?;
+        log::debug!("extracted {} frames in {:.2?}", paths.len(), started.elapsed());
+        work.mark_extracted()?;
+        FrameSource::Disk(paths)
     };
 
-    for (index, frame_path) in frames.iter().enumerate() {
-        let image = image::open(frame_path)?.to_luma8();
-        let ascii = convert_frame_to_ascii(&image, &options);
+    let mut options = ascii_options(config);
+
+    let mut frames = source.frames(config, &metadata)?;
+    let first_frame = frames.next().ok_or(AppError::NoFramesExtracted)??;
+    options.palette = palette_colors(config, &options, &first_frame);
 
-        let output_frame = ascii_dir.join(format!("frame_{:08}.png", index));
+    // Detect background color if not specified
+    let bg_color = background_key(config, &options, || match config.bg_sample {
+        BackgroundSample::First => Ok(detect_background_color(&config.luma.to_gray(&first_frame))),
+        sample => single_step(progress, Stage::SamplingBackground, || {
+            sample_background(sample, config.luma, source.frames(config, &metadata)?)
+        }),
+    })?;
+
+    work.set_render_key(render_key(config, &options, bg_color))?;
+
+    let total = source.total(config, &metadata);
+    let report = |current: usize| PipelineProgress {
+        stage: Stage::Converting,
+        current: current as u64,
+        total,
+    };
+    progress(report(0));
+    let started = Instant::now();
+    let mut frames_processed = 0;
+    let mut output_resolution = Resolution { width: 0, height: 0 };
+    let limit = config.max_frames.map_or(usize::MAX, |count| count as usize);
+    // --frames-out numbers from 1 like ffmpeg's image muxer; the user's
+    // directory is never treated as a resume cache
+    let (frames_dir, first_number) = match &config.frames_out {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            (dir.clone(), 1)
+        }
+        None => (ascii_dir.clone(), 0),
+    };
+    let mut history = config.temporal_smooth.map(CellHistory::new);
+    if config.dedup && history.is_some() {
+        log::warn!("--dedup has no effect with --temporal-smooth; repeated frames are still converted");
+    }
+    // Hash of the source frame behind the last converted (or resumed) frame
+    let dedup = config.dedup && history.is_none();
+    let mut last_converted = None;
+    let mut frames_reused = 0;
+    // The audio copy is encoded from flattened duplicates of every frame
+    let opaque_dir = match (&config.audio_copy_output, &config.frames_out) {
+        (Some(_), None) => {
+            std::fs::create_dir_all(work.opaque_dir())?;
+            Some(work.opaque_dir())
+        }
+        _ => None,
+    };
+    let canvas = Rgb([background_luma(config.invert); 3]);
+    for (index, frame) in std::iter::once(Ok(first_frame)).chain(frames).take(limit).enumerate() {
+        check_interrupted()?;
+        let frame = frame?;
+        let hash = dedup.then(|| frame_hash(&frame));
+        let output_frame = frames_dir.join(frame_name(index + first_number));
+        let opaque_frame = opaque_dir.as_ref().map(|dir| dir.join(frame_name(index)));
 
-        if config.transparent {
-            // Convert to transparent RGBA
-            let rgba = convert_to_transparent(&ascii, bg_color, config.threshold);
-            rgba.save(output_frame)?;
+        // Smoothed frames depend on every frame before them, so a resumed run
+        // has to render them again to rebuild the cell history
+        let (width, height) = if config.frames_out.is_none() && history.is_none() && output_frame.exists() {
+            // Already converted by an earlier, interrupted run
+            if let Some(opaque_frame) = opaque_frame.filter(|path| !path.exists()) {
+                save_frame(&flatten_frame(&image::open(&output_frame)?, canvas), &opaque_frame)?;
+            }
+            last_converted = hash;
+            image::image_dimensions(&output_frame)?
+        } else if index % config.hold.max(1) as usize != 0 || (hash.is_some() && hash == last_converted) {
+            // --hold repeats the last converted frame, keeping the frame count and
+            // duration; --dedup does the same when the source frame didn't change
+            copy_frame(&frames_dir.join(frame_name(index - 1 + first_number)), &output_frame)?;
+            if let (Some(dir), Some(opaque_frame)) = (&opaque_dir, &opaque_frame) {
+                copy_frame(&dir.join(frame_name(index - 1)), opaque_frame)?;
+            }
+            if hash.is_some() && hash == last_converted {
+                frames_reused += 1;
+            }
+            (output_resolution.width, output_resolution.height)
         } else {
-            ascii.save(output_frame)?;
+            last_converted = hash;
+            let ascii = render_frame(config, &options, bg_color, &frame, history.as_mut());
+            save_frame(&ascii, &output_frame)?;
+            if let Some(opaque_frame) = &opaque_frame {
+                save_frame(&flatten_frame(&ascii, canvas), opaque_frame)?;
+            }
+            (ascii.width(), ascii.height())
+        };
+        output_resolution = Resolution { width, height };
+        frames_processed += 1;
+        progress(report(frames_processed));
+    }
+    log::debug!("converted {frames_processed} frames in {:.2?}", started.elapsed());
+    if dedup {
+        log::debug!("reused {frames_reused} unchanged frames");
+    }
+    // A killed ffmpeg can end the frame stream early without an error
+    check_interrupted()?;
+
+    if let Some(frames_out) = &config.frames_out {
+        return Ok(PipelineStats {
+            frames_processed,
+            output_fps: fps,
+            duration_seconds: if fps > 0.0 { frames_processed as f64 / fps } else { 0.0 },
+            input_resolution,
+            output_resolution,
+            elapsed_seconds: 0.0,
+            output: frames_out.clone(),
+            compare_output: None,
+            audio_copy_output: None,
+            frames_reused,
+        });
+    }
+
+    if config.encode.timing == Timing::Preserve {
+        let timestamps = video::probe_frame_timestamps(&config.ffprobe, &config.input, &config.range)?;
+        if timestamps.len() != frames_processed {
+            log::warn!(
+                "{} source timestamps for {frames_processed} converted frames; unmatched frames use {fps:.3} fps",
+                timestamps.len()
+            );
+        }
+        for dir in std::iter::once(&ascii_dir).chain(&opaque_dir) {
+            video::write_concat_list(dir, frames_processed, &timestamps, fps)?;
         }
     }
 
-    video::encode_video(&ascii_dir, &config.input, fps, &config.output, config.transparent)?;
+    let started = Instant::now();
+    let encode_options = retimed_encode(config, &metadata);
+    let encoded = single_step(progress, Stage::Encoding, || {
+        video::encode_video(&config.ffmpeg, &ascii_dir, &config.input, &config.range, fps, &config.output, &encode_options)
+    });
+    finish_output(&config.output, encoded)?;
+    log::debug!("encoded {} in {:.2?}", config.output.display(), started.elapsed());
+
+    if let (Some(audio_copy_output), Some(opaque_dir)) = (&config.audio_copy_output, &opaque_dir) {
+        let audio_copy = audio_copy_encode(&encode_options);
+        let encoded = single_step(progress, Stage::EncodingAudioCopy, || {
+            video::encode_video(&config.ffmpeg, opaque_dir, &config.input, &config.range, fps, audio_copy_output, &audio_copy)
+        });
+        finish_output(audio_copy_output, encoded)?;
+        log::debug!("wrote audio copy {}", audio_copy_output.display());
+    }
 
     // Create comparison video if requested
-    if config.compare {
-        video::create_comparison_video(&config.input, &config.output)?;
+    if let Some(compare_output) = &config.compare_output {
+        let compared = single_step(progress, Stage::Comparing, || {
+            video::create_comparison_video(
+                &config.ffmpeg,
+                &config.input,
+                &config.output,
+                &config.range,
+                compare_output,
+                config.compare_layout,
+                // The original is stacked uncropped, at its full size
+                (metadata.display_size(), (output_resolution.width, output_resolution.height)),
+            )
+        });
+        finish_output(compare_output, compared)?;
+        log::debug!("wrote comparison {}", compare_output.display());
     }
 
     Ok(PipelineStats {
-        frames_processed: frames.len(),
+        frames_processed,
         output_fps: fps,
+        duration_seconds: if fps > 0.0 { frames_processed as f64 / fps } else { 0.0 },
+        input_resolution,
+        output_resolution,
+        elapsed_seconds: 0.0,
+        output: config.output.clone(),
+        compare_output: config.compare_output.clone(),
+        audio_copy_output: config.audio_copy_output.clone(),
+        frames_reused,
     })
 }
+
+/// Identifies a decoded frame for `dedup`: equal frames hash equally.
+fn frame_hash(frame: &DynamicImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (frame.width(), frame.height()).hash(&mut hasher);
+    frame.as_bytes().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Write `frame` under a temporary name first, so a killed run never leaves
+/// a truncated frame behind for a resumed run to pick up.
+fn save_frame(frame: &DynamicImage, path: &Path) -> Result<()> {
+    let partial = path.with_extension("partial.png");
+    frame.save(&partial)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+fn copy_frame(from: &Path, to: &Path) -> Result<()> {
+    let partial = to.with_extension("partial.png");
+    std::fs::copy(from, &partial)?;
+    std::fs::rename(&partial, to)?;
+    Ok(())
+}
+
+/// A converted frame with any transparency painted over with `canvas`.
+fn flatten_frame(frame: &DynamicImage, canvas: Rgb<u8>) -> DynamicImage {
+    DynamicImage::ImageRgba8(flatten_onto(frame.to_rgba8(), canvas))
+}
+
+/// The main encode settings, as an H.264 MP4 that keeps the source audio.
+fn audio_copy_encode(encode: &EncodeOptions) -> EncodeOptions {
+    EncodeOptions {
+        format: OutputFormat::Mp4,
+        codec: VideoCodec::X264,
+        ..encode.clone()
+    }
+}
+
+/// Convert a still image straight to a single ASCII image; ffmpeg is not needed.
+fn run_image(config: &PipelineConfig, progress: &mut dyn FnMut(PipelineProgress)) -> Result<PipelineStats> {
+    if config.encode.label.is_some() {
+        log::warn!("--label is drawn by ffmpeg and only applies to video output");
+    }
+    if config.audio_copy_output.is_some() {
+        log::warn!("--with-audio-copy only applies to video input; no audio copy is written");
+    }
+
+    let mut source = open_oriented(&config.input)?;
+    if let Some(crop) = config.crop {
+        crop.check_fits(source.width(), source.height())?;
+        source = source.crop_imm(crop.x, crop.y, crop.width, crop.height);
+    }
+    let mut options = ascii_options(config);
+    options.palette = palette_colors(config, &options, &source);
+    let bg_color = background_key(config, &options, || Ok(detect_background_color(&config.luma.to_gray(&source))))?;
+
+    let output = config.frames_out.as_deref().map_or_else(|| config.output.clone(), first_frame_path);
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let report = |current| PipelineProgress {
+        stage: Stage::Converting,
+        current,
+        total: Some(1),
+    };
+    progress(report(0));
+    let ascii = render_frame(config, &options, bg_color, &source, None);
+    ascii.save(&output)?;
+    progress(report(1));
+
+    Ok(PipelineStats {
+        frames_processed: 1,
+        output_fps: 0.0,
+        duration_seconds: 0.0,
+        input_resolution: Resolution {
+            width: source.width(),
+            height: source.height(),
+        },
+        output_resolution: Resolution {
+            width: ascii.width(),
+            height: ascii.height(),
+        },
+        elapsed_seconds: 0.0,
+        output,
+        compare_output: None,
+        audio_copy_output: None,
+        frames_reused: 0,
+    })
+}
+
+/// Write `config.input` to `config.output` as ANSI colored text (see
+/// [`PipelineConfig::text_ansi`]). Nothing is encoded, so video input only
+/// needs ffmpeg to decode.
+fn run_text(config: &PipelineConfig, progress: &mut dyn FnMut(PipelineProgress)) -> Result<PipelineStats> {
+    let options = ascii_options(config);
+    let is_video = !video::is_image_path(&config.input);
+    let (frames, total, fps, input_resolution): (Frames, _, _, _) = if is_video {
+        if !video::tools_available(&config.ffmpeg, &config.ffprobe) {
+            return Err(AppError::MissingFfmpeg);
+        }
+        let metadata = video::probe_video(&config.ffprobe, &config.input)?;
+        let source = FrameSource::Stream;
+        let frames = source.frames(config, &metadata)?;
+        (frames, source.total(config, &metadata), playback_fps(config, &metadata), input_resolution(config, &metadata)?)
+    } else {
+        let mut source = open_oriented(&config.input)?;
+        if let Some(crop) = config.crop {
+            crop.check_fits(source.width(), source.height())?;
+            source = source.crop_imm(crop.x, crop.y, crop.width, crop.height);
+        }
+        let resolution = Resolution {
+            width: source.width(),
+            height: source.height(),
+        };
+        (Box::new(std::iter::once(Ok(source))), Some(1), 0.0, resolution)
+    };
+
+    let mut output: Box<dyn Write> = if video::is_stdout(&config.output) {
+        Box::new(std::io::stdout().lock())
+    } else {
+        if let Some(parent) = config.output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Box::new(BufWriter::new(File::create(&config.output)?))
+    };
+    let report = |current: usize| PipelineProgress {
+        stage: Stage::Converting,
+        current: current as u64,
+        total,
+    };
+    progress(report(0));
+
+    let mut frames_processed = 0;
+    let mut output_resolution = Resolution { width: 0, height: 0 };
+    let limit = config.max_frames.map_or(usize::MAX, |count| count as usize);
+    let written = (|| {
+        for frame in frames.take(limit) {
+            check_interrupted()?;
+            let frame = prepare_frame(config, &frame?).into_owned();
+            // Video frames clear the screen once, then redraw from the top-left
+            // corner; a lone image prints where the cursor is
+            let home = if !is_video {
+                ""
+            } else if frames_processed == 0 {
+                "\x1b[2J\x1b[H"
+            } else {
+                "\x1b[H"
+            };
+            let text = convert_frame_to_ansi_text(&config.luma.to_gray(&frame), &frame.to_rgb8(), &options);
+            writeln!(output, "{home}{text}")?;
+
+            frames_processed += 1;
+            // The text's size is its grid of characters
+            output_resolution = Resolution {
+                width: frame.width().div_ceil(8),
+                height: frame.height().div_ceil(8),
+            };
+            progress(report(frames_processed));
+        }
+        output.flush()?;
+        Ok(())
+    })();
+    finish_output(&config.output, written)?;
+    if frames_processed == 0 {
+        return Err(AppError::NoFramesExtracted);
+    }
+
+    Ok(PipelineStats {
+        frames_processed,
+        output_fps: fps,
+        duration_seconds: if is_video { frames_processed as f64 / fps } else { 0.0 },
+        input_resolution,
+        output_resolution,
+        elapsed_seconds: 0.0,
+        output: config.output.clone(),
+        compare_output: None,
+        audio_copy_output: None,
+        frames_reused: 0,
+    })
+}
+
+/// File name of the `number`th converted frame.
+fn frame_name(number: usize) -> String {
+    format!("frame_{number:08}.png")
+}
+
+/// Where a still image lands under `--frames-out`: the first numbered frame.
+fn first_frame_path(dir: &Path) -> PathBuf {
+    dir.join(frame_name(1))
+}
+
+/// Open a still image upright, applying its EXIF orientation the way photo
+/// viewers do (phone photos are often stored sideways).
+fn open_oriented(path: &Path) -> Result<DynamicImage> {
+    let mut decoder = image::ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// The glyph settings frames are converted with under `config`.
+pub fn ascii_options(config: &PipelineConfig) -> AsciiOptions {
+    let fallback_char = config.fallback_char.unwrap_or(DEFAULT_FALLBACK_CHAR);
+    let charset = if config.auto_order { order_by_ink(&config.charset, fallback_char) } else { config.charset.clone() };
+    AsciiOptions {
+        fallback_char,
+        invert: config.invert,
+        contrast: config.contrast,
+        focus: config.focus,
+        focus_falloff: config.focus_falloff,
+        dither: config.dither,
+        mode: config.mode,
+        font_scale: config.font_scale,
+        cell_padding: config.cell_padding,
+        max_colors_per_cell: config.max_colors_per_cell,
+        font: config.font,
+        bw_threshold: config.bw_threshold,
+        sample_stride: config.sample_stride,
+        ..AsciiOptions::new(config.columns, &charset, config.shades)
+    }
+}
+
+/// Colors for `config.palette`, with median-cut palettes built from `frame`
+/// scaled to the size it is rendered at. A whole video shares one palette so
+/// cell colors don't flicker between frames.
+fn palette_colors(config: &PipelineConfig, options: &AsciiOptions, frame: &DynamicImage) -> Option<Vec<Rgb<u8>>> {
+    let palette = config.palette.filter(|_| config.mode == RenderMode::Halfblock)?;
+    let (width, height) = source_size(config, frame.width(), frame.height());
+    let sample = imageops::resize(&frame.to_rgb8(), width, height, config.resize_filter.filter_type());
+    Some(palette.colors(&sample, options))
+}
+
+/// Gray value keyed out in transparent mode: `bg_color` if given, otherwise
+/// the result of `detect`. Inverted rendering swaps glyph and background luma,
+/// so the detected value is flipped to match.
+fn background_key(
+    config: &PipelineConfig,
+    options: &AsciiOptions,
+    detect: impl FnOnce() -> Result<u8>,
+) -> Result<u8> {
+    if !config.transparent || config.key_color.is_some() {
+        return Ok(255); // Not used without luma keying
+    }
+
+    match config.bg_color {
+        Some(color) => Ok(color),
+        None => {
+            let detected = detect()?;
+            Ok(if options.invert { 255 - detected } else { detected })
+        }
+    }
+}
+
+/// Detect the background over every frame: the global histogram mode for
+/// `All`, or the median of the per-frame modes for `Median`.
+fn sample_background(sample: BackgroundSample, luma: LumaMethod, mut frames: Frames) -> Result<u8> {
+    match sample {
+        BackgroundSample::First => match frames.next() {
+            Some(frame) => Ok(detect_background_color(&luma.to_gray(&frame?))),
+            None => Err(AppError::NoFramesExtracted),
+        },
+        BackgroundSample::All => {
+            let mut histogram = LumaHistogram::default();
+            for frame in frames {
+                histogram.add(&luma.to_gray(&frame?));
+            }
+            Ok(histogram.mode())
+        }
+        BackgroundSample::Median => {
+            let mut modes = frames
+                .map(|frame| frame.map(|frame| detect_background_color(&luma.to_gray(&frame))))
+                .collect::<Result<Vec<u8>>>()?;
+            modes.sort_unstable();
+            modes.get(modes.len() / 2).copied().ok_or(AppError::NoFramesExtracted)
+        }
+    }
+}
+
+/// Convert one source frame to ASCII, keying out the background when
+/// rendering transparent output. `history` carries cell luma between frames
+/// with `temporal_smooth`.
+fn render_frame(
+    config: &PipelineConfig,
+    options: &AsciiOptions,
+    bg_color: u8,
+    frame: &DynamicImage,
+    history: Option<&mut CellHistory>,
+) -> DynamicImage {
+    let to_ascii = |gray: &GrayImage| match history {
+        Some(history) => convert_frame_to_ascii_smoothed(gray, options, history),
+        None => convert_frame_to_ascii(gray, options),
+    };
+    let prepared = prepare_frame(config, frame);
+    let frame = &*prepared;
+    // --max-colors-per-cell paints the glyphs with their cells' source colors
+    let colored = options.max_colors_per_cell.is_some() && options.mode != RenderMode::Halfblock;
+    let glyphs = |ascii: GrayImage| {
+        if colored {
+            DynamicImage::ImageRgb8(colorize_glyphs(&ascii, &frame.to_rgb8(), options))
+        } else {
+            DynamicImage::ImageLuma8(ascii)
+        }
+    };
+
+    // --key-color keys the color source frame, then cuts the same shape out
+    // of whatever was rendered from it
+    if let Some(key) = config.key_color.filter(|_| config.transparent) {
+        let mask = key_by_color(&frame.to_rgba8(), key, config.key_tolerance);
+        let rendered = match options.mode {
+            RenderMode::Halfblock => DynamicImage::ImageRgb8(convert_frame_to_halfblock(&frame.to_rgb8(), options)),
+            _ => glyphs(to_ascii(&config.luma.to_gray(frame))),
+        };
+        let keyed = apply_alpha_mask(rendered.to_rgba8(), &mask);
+        return DynamicImage::ImageRgba8(match config.flatten_bg {
+            Some(fill) => flatten_onto(keyed, fill),
+            None => keyed,
+        });
+    }
+
+    if options.mode == RenderMode::Halfblock {
+        let blocks = convert_frame_to_halfblock(&frame.to_rgb8(), options);
+        return if config.transparent {
+            let bg_color = if config.bg_per_frame {
+                detect_background_color(&imageops::grayscale(&blocks))
+            } else {
+                bg_color
+            };
+            let keyed = convert_color_to_transparent(&blocks, bg_color, config.threshold, config.feather);
+            match config.flatten_bg {
+                Some(fill) => DynamicImage::ImageRgba8(flatten_onto(keyed, fill)),
+                None => DynamicImage::ImageRgba8(keyed),
+            }
+        } else {
+            DynamicImage::ImageRgb8(blocks)
+        };
+    }
+
+    let ascii = to_ascii(&config.luma.to_gray(frame));
+
+    if config.transparent {
+        // --bg-per-frame keys each rendered frame on its own dominant luma
+        let bg_color = if config.bg_per_frame { detect_background_color(&ascii) } else { bg_color };
+        // Convert to transparent RGBA, or paint the key over with --flatten-bg
+        let keyed = convert_to_transparent(&ascii, bg_color, config.threshold, config.feather);
+        let keyed = if colored { apply_alpha_mask(glyphs(ascii).to_rgba8(), &keyed) } else { keyed };
+        DynamicImage::ImageRgba8(match config.flatten_bg {
+            Some(fill) => flatten_onto(keyed, fill),
+            None => keyed,
+        })
+    } else {
+        glyphs(ascii)
+    }
+}
+
+/// `frame` resized, flipped, prefiltered and sharpened as `config` asks,
+/// ready to be split into cells.
+fn prepare_frame<'a>(config: &PipelineConfig, frame: &'a DynamicImage) -> Cow<'a, DynamicImage> {
+    let mut frame = Cow::Borrowed(frame);
+    let (width, height) = source_size(config, frame.width(), frame.height());
+    if (width, height) != (frame.width(), frame.height()) {
+        let scaled = imageops::resize(&frame.to_rgba8(), width, height, config.resize_filter.filter_type());
+        frame = Cow::Owned(DynamicImage::from(scaled));
+    }
+    if config.flip != Flip::None {
+        frame = Cow::Owned(config.flip.apply(&frame));
+    }
+    if config.prefilter != Prefilter::None {
+        frame = Cow::Owned(config.prefilter.apply(&frame, config.blur_radius));
+    }
+    if let Some(amount) = config.sharpen {
+        frame = Cow::Owned(sharpen(&frame, amount));
+    }
+    frame
+}
+
+/// Everything the converted frames in a work dir depend on: the glyph
+/// `options` plus every setting `render_frame` and `source_size` read, the
+/// resolved key color, and the frame cap, or a longer earlier run would leak
+/// into the encode. A field missing here lets stale frames be reused.
+fn render_key(config: &PipelineConfig, options: &AsciiOptions, bg_color: u8) -> String {
+    format!(
+        "{options:?}|{}|{bg_color}|{}|{}|{}|{:?}|{:?}|{:?}|{}|{}|{:?}|{}|{:?}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+        config.transparent,
+        config.threshold,
+        config.feather,
+        config.bg_per_frame,
+        config.max_frames,
+        config.flatten_bg,
+        config.key_color,
+        config.key_tolerance,
+        config.hold,
+        config.prefilter,
+        config.blur_radius,
+        config.sharpen,
+        config.skip_bad_frames,
+        config.temporal_smooth,
+        config.resize_filter,
+        config.flip,
+        config.luma,
+        config.rows,
+        config.max_width,
+        config.fit
+    )
+}
+
+/// Widest source `render_frame` converts without downscaling. `max_width`
+/// caps the output, which glyph scaling enlarges.
+fn source_width_cap(config: &PipelineConfig) -> Option<u32> {
+    config.max_width.map(|width| (width / config.font_scale.max(1)).max(8))
+}
+
+/// Size a `width` x `height` source is resized to before conversion: `rows`
+/// cells tall or fitted to `fit` when given, then capped by `max_width`,
+/// keeping aspect ratio.
+fn source_size(config: &PipelineConfig, width: u32, height: u32) -> (u32, u32) {
+    let scale_to = |from: u32, to: u32, other: u32| (other as u64 * to as u64 / from.max(1) as u64).max(1) as u32;
+
+    let (mut width, mut height) = (width, height);
+    if let Some(rows) = config.rows {
+        let target = rows * 8;
+        width = scale_to(height, target, width);
+        height = target;
+    }
+    if let Some(fit) = config.fit {
+        // Every 8 source pixels grow into one scaled, padded output cell
+        let pitch = (8 * config.font_scale.max(1) + config.cell_padding) as u64;
+        let box_width = (fit.width as u64 * 8 / pitch).max(1) as u32;
+        let box_height = (fit.height as u64 * 8 / pitch).max(1) as u32;
+        if width as u64 * box_height as u64 > height as u64 * box_width as u64 {
+            height = scale_to(width, box_width, height);
+            width = box_width;
+        } else {
+            width = scale_to(height, box_height, width);
+            height = box_height;
+        }
+    }
+    if let Some(max_width) = source_width_cap(config).filter(|&max_width| width > max_width) {
+        height = scale_to(width, max_width, height);
+        width = max_width;
+    }
+    (width, height)
+}
+
+/// Size of the frames `render_frame` produces for a `width` x `height` source.
+fn output_resolution(config: &PipelineConfig, width: u32, height: u32) -> Resolution {
+    let (width, height) = source_size(config, width, height);
+    let scale = config.font_scale.max(1);
+    // Every cell, partial edge cells included, is followed by its gutter
+    let padded = |extent: u32| extent * scale + extent.div_ceil(8) * config.cell_padding;
+    Resolution {
+        width: padded(width),
+        height: padded(height),
+    }
+}
+
+type Frames = Box<dyn Iterator<Item = Result<DynamicImage>>>;
+
+/// Where decoded frames come from. Both variants can be iterated more than
+/// once, which background sampling relies on.
+enum FrameSource {
+    /// PNGs already extracted to the temp dir (`--disk-frames`)
+    Disk(Vec<PathBuf>),
+    /// A fresh ffmpeg rawvideo pipe per iteration
+    Stream,
+    /// PNGs extracted `size` at a time into `dir` as iteration proceeds
+    /// (`--batch-size`)
+    Batched { size: u32, dir: PathBuf },
+}
+
+impl FrameSource {
+    fn frames(&self, config: &PipelineConfig, metadata: &VideoMetadata) -> Result<Frames> {
+        let frames = self.decoded_frames(config, metadata)?;
+        if !config.skip_bad_frames {
+            return Ok(frames);
+        }
+        // Dropped before numbering so the converted sequence has no gaps
+        Ok(Box::new(frames.filter(|frame| match frame {
+            Err(err @ AppError::FrameDecode { .. }) => {
+                log::warn!("skipping {err}");
+                false
+            }
+            _ => true,
+        })))
+    }
+
+    fn decoded_frames(&self, config: &PipelineConfig, metadata: &VideoMetadata) -> Result<Frames> {
+        match self {
+            FrameSource::Disk(paths) => Ok(Box::new(
+                paths
+                    .clone()
+                    .into_iter()
+                    .map(|path| video::open_frame(&path)),
+            )),
+            FrameSource::Stream => {
+                let Resolution { width, height } = input_resolution(config, metadata)?;
+                let stream = video::stream_frames(
+                    &config.ffmpeg,
+                    &config.input,
+                    &config.range,
+                    config.crop,
+                    extraction_fps(config, metadata),
+                    width,
+                    height,
+                )?;
+                Ok(Box::new(stream.map(|frame| frame.map(DynamicImage::ImageRgb8))))
+            }
+            FrameSource::Batched { size, dir } => {
+                let fps = extraction_fps(config, metadata);
+                Ok(Box::new(BatchedFrames {
+                    ffmpeg: config.ffmpeg.clone(),
+                    input: config.input.clone(),
+                    range: config.range,
+                    crop: config.crop,
+                    fps,
+                    frame_duration: 1.0 / fps.unwrap_or(metadata.effective_fps()),
+                    size: *size,
+                    dir: dir.clone(),
+                    batch: 0,
+                    pending: Vec::new().into_iter(),
+                    done: false,
+                }))
+            }
+        }
+    }
+
+    fn total(&self, config: &PipelineConfig, metadata: &VideoMetadata) -> Option<u64> {
+        let total = match self {
+            FrameSource::Disk(paths) => Some(paths.len() as u64),
+            FrameSource::Stream | FrameSource::Batched { .. } => expected_frames(metadata, &config.range, extraction_fps(config, metadata)),
+        };
+        cap_frames(total, config.max_frames)
+    }
+}
+
+/// Extracts frames in windows of `size`, deleting each PNG once it has been
+/// read, so disk usage stays bounded by one batch. Every batch is a separate
+/// ffmpeg run that has to seek into the input, so smaller batches cost time.
+struct BatchedFrames {
+    ffmpeg: PathBuf,
+    input: PathBuf,
+    range: TimeRange,
+    crop: Option<Crop>,
+    fps: Option<f64>,
+    frame_duration: f64,
+    size: u32,
+    dir: PathBuf,
+    batch: u64,
+    pending: std::vec::IntoIter<PathBuf>,
+    done: bool,
+}
+
+impl BatchedFrames {
+    /// The slice of the input holding the next batch. It starts half a frame
+    /// early so float rounding can't skip the first frame; `-frames:v` then
+    /// stops it at exactly `size` frames.
+    fn next_window(&self) -> Option<TimeRange> {
+        let offset = (self.batch * self.size as u64) as f64 * self.frame_duration;
+        let lead = if self.batch == 0 { 0.0 } else { self.frame_duration / 2.0 };
+        let start = self.range.start.unwrap_or(0.0) + offset - lead;
+        let duration = match self.range.duration {
+            Some(duration) if offset >= duration => return None,
+            Some(duration) => Some(duration - offset + lead),
+            None => None,
+        };
+        Some(TimeRange {
+            start: Some(start),
+            duration,
+            ..self.range
+        })
+    }
+}
+
+impl Iterator for BatchedFrames {
+    type Item = Result<DynamicImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(path) = self.pending.next() {
+                let frame = video::open_frame(&path);
+                let _ = std::fs::remove_file(&path);
+                return Some(frame);
+            }
+            if self.done {
+                return None;
+            }
+
+            let Some(window) = self.next_window() else {
+                self.done = true;
+                return None;
+            };
+            self.batch += 1;
+            match video::extract_frame_batch(&self.ffmpeg, &self.input, &window, self.crop, self.fps, self.size, &self.dir) {
+                Ok(paths) => {
+                    self.done = paths.len() < self.size as usize;
+                    self.pending = paths.into_iter();
+                }
+                // An empty window means the previous batch ended exactly at the end of the input
+                Err(AppError::NoFramesExtracted) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// Size of the decoded frames: ffmpeg autorotates, so phone footage
+/// recorded sideways comes out with width and height swapped, and `--crop`
+/// (checked against that upright size) shrinks them to the cropped region.
+fn input_resolution(config: &PipelineConfig, metadata: &VideoMetadata) -> Result<Resolution> {
+    let (width, height) = metadata.display_size();
+    match config.crop {
+        Some(crop) => {
+            crop.check_fits(width, height)?;
+            Ok(Resolution {
+                width: crop.width,
+                height: crop.height,
+            })
+        }
+        None => Ok(Resolution { width, height }),
+    }
+}
+
+/// Frame total once `--max-frames` is applied; the cap alone when the total is unknown.
+fn cap_frames(total: Option<u64>, max_frames: Option<u32>) -> Option<u64> {
+    match (total, max_frames) {
+        (Some(total), Some(max)) => Some(total.min(max as u64)),
+        (total, max) => total.or(max.map(u64::from)),
+    }
+}
+
+/// Best-effort frame total for the progress bar when streaming.
+fn expected_frames(metadata: &VideoMetadata, range: &TimeRange, extract_fps: Option<f64>) -> Option<u64> {
+    let fps = extract_fps.unwrap_or(metadata.effective_fps());
+    if range.is_full() {
+        return metadata
+            .frame_count
+            .map(|count| (count as f64 * fps / metadata.effective_fps()).round() as u64);
+    }
+
+    range.duration.map(|duration| (duration * fps).round() as u64)
+}
+
+/// Rate the output plays at: `out_fps`, else the sampling rate.
+fn playback_fps(config: &PipelineConfig, metadata: &VideoMetadata) -> f64 {
+    config.out_fps.unwrap_or(config.fps.unwrap_or(metadata.effective_fps()))
+}
+
+/// `config.encode`, minus the audio when `out_fps` speeds up or slows down
+/// the video and the source audio would drift out of sync.
+fn retimed_encode(config: &PipelineConfig, metadata: &VideoMetadata) -> EncodeOptions {
+    let sample_fps = config.fps.unwrap_or(metadata.effective_fps());
+    let retimed = (playback_fps(config, metadata) - sample_fps).abs() > 1e-6;
+    if !retimed || config.encode.audio == AudioMode::None {
+        return config.encode.clone();
+    }
+    log::warn!("--out-fps changes the playback speed; the source audio is dropped");
+    EncodeOptions {
+        audio: AudioMode::None,
+        ..config.encode.clone()
+    }
+}
+
+/// Rate to decode at when `--fps` is below the source rate, so surplus frames
+/// are dropped by ffmpeg instead of being converted and re-encoded. Variable
+/// frame rate sources are always resampled to a constant rate, since the
+/// frames are re-encoded evenly spaced.
+fn extraction_fps(config: &PipelineConfig, metadata: &VideoMetadata) -> Option<f64> {
+    // Preserved timing needs every source frame, unevenly spaced or not
+    if config.encode.timing == Timing::Preserve {
+        return None;
+    }
+    let requested = config.fps.filter(|&fps| fps > 0.0);
+    if metadata.is_variable_rate() {
+        return Some(requested.unwrap_or(metadata.effective_fps()));
+    }
+    requested.filter(|&fps| fps < metadata.fps)
+}
+
+/// Run a single-unit stage, reporting its start and end to `progress`.
+fn single_step<T>(
+    progress: &mut dyn FnMut(PipelineProgress),
+    stage: Stage,
+    work: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let report = |current| PipelineProgress {
+        stage,
+        current,
+        total: Some(1),
+    };
+    progress(report(0));
+    let result = work()?;
+    progress(report(1));
+    Ok(result)
+}
+
+/// The spinners and frame bar `run` draws on stderr, fed by the same
+/// progress hook library callers get.
+#[derive(Default)]
+struct TerminalProgress {
+    bar: Option<ProgressBar>,
+}
+
+impl TerminalProgress {
+    fn update(&mut self, update: PipelineProgress) {
+        if update.current == 0 {
+            self.finish();
+            self.bar = Some(match update.stage {
+                Stage::Converting => frame_bar(update.total),
+                stage => start_spinner(stage.label()),
+            });
+        } else if update.stage != Stage::Converting && update.total == Some(update.current) {
+            self.finish();
+        } else if let Some(bar) = &self.bar {
+            bar.set_position(update.current);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl Drop for TerminalProgress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn start_spinner(message: &'static str) -> ProgressBar {
+    let spinner = ProgressBar::new_spinner().with_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+fn frame_bar(total: Option<u64>) -> ProgressBar {
+    // Streamed sources don't always report a frame count; fall back to a counter
+    let (bar, template) = match total {
+        Some(total) => (
+            ProgressBar::new(total),
+            "Converting {bar:40} {pos}/{len} frames (ETA {eta})",
+        ),
+        None => (ProgressBar::no_length(), "{spinner} Converting {pos} frames"),
+    };
+    if let Ok(style) = ProgressStyle::with_template(template) {
+        bar.set_style(style);
+    }
+    bar
+}
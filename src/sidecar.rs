@@ -0,0 +1,93 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, Result};
+use crate::pipeline::{PipelineConfig, PipelineStats};
+use crate::video;
+
+/// Record of how an output was produced, written next to it by `--sidecar`
+/// so a later run can check it reproduces the same file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sidecar<'a> {
+    pub input: &'a Path,
+    /// SHA-256 of the input file; `None` for URL inputs, which aren't downloaded
+    pub input_sha256: Option<String>,
+    pub charset: &'a str,
+    pub shades: u32,
+    pub columns: u32,
+    pub contrast: bool,
+    pub invert: bool,
+    /// Version of this crate that wrote the output
+    pub version: &'static str,
+    /// Seconds since the Unix epoch when the sidecar was written
+    pub created_unix: u64,
+    #[serde(flatten)]
+    pub stats: &'a PipelineStats,
+}
+
+/// `<output>.json`, e.g. `clip_ascii.mp4.json`.
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut path = OsString::from(output.as_os_str());
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+/// Hash the input and write the sidecar for a finished run, returning its path.
+pub fn write_sidecar(config: &PipelineConfig, stats: &PipelineStats) -> Result<PathBuf> {
+    let input_sha256 = if video::is_url(&config.input) {
+        None
+    } else {
+        Some(sha256_file(&config.input)?)
+    };
+    let sidecar = Sidecar {
+        input: &config.input,
+        input_sha256,
+        charset: &config.charset,
+        shades: config.shades,
+        columns: config.columns,
+        contrast: config.contrast,
+        invert: config.invert,
+        version: env!("CARGO_PKG_VERSION"),
+        created_unix: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+        stats,
+    };
+
+    let json = serde_json::to_string_pretty(&sidecar).map_err(|err| AppError::Io(io::Error::other(err)))?;
+    let path = sidecar_path(&stats.output);
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_sits_next_to_the_output() {
+        assert_eq!(sidecar_path(Path::new("out/clip_ascii.mp4")), Path::new("out/clip_ascii.mp4.json"));
+    }
+
+    #[test]
+    fn hashes_input_contents() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("input.bin");
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            sha256_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
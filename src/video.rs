@@ -1,230 +1,1491 @@
-use std::ffi::OsStr;
+use std::cell::Cell;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
+use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use image::{DynamicImage, RgbImage};
 
 use crate::error::{AppError, Result};
 
+/// Container/codec combination used when encoding the ASCII frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// H.264 in an MP4 container (opaque, keeps source audio)
+    Mp4,
+    /// Animated WebP (supports transparency)
+    Webp,
+    /// Animated GIF with a generated palette (1-bit transparency)
+    Gif,
+    /// VP9 in a WebM container (supports transparency)
+    Webm,
+    /// Animated PNG (lossless, full alpha)
+    Apng,
+    /// The MP4 encode in a Matroska container (opaque, keeps source audio)
+    Mkv,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Mp4 => "mp4",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Webm => "webm",
+            OutputFormat::Apng => "apng",
+            OutputFormat::Mkv => "mkv",
+        }
+    }
+
+    /// Infer the format from a path's extension, if it is a known one.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "mp4" => Some(OutputFormat::Mp4),
+            "webp" => Some(OutputFormat::Webp),
+            "gif" => Some(OutputFormat::Gif),
+            "webm" => Some(OutputFormat::Webm),
+            "apng" => Some(OutputFormat::Apng),
+            "mkv" => Some(OutputFormat::Mkv),
+            _ => None,
+        }
+    }
+
+    pub fn supports_alpha(self) -> bool {
+        !self.uses_codec()
+    }
+
+    /// Whether the format is encoded with the `--codec` video encoder and
+    /// keeps the source audio, as MP4 and MKV are.
+    pub fn uses_codec(self) -> bool {
+        matches!(self, OutputFormat::Mp4 | OutputFormat::Mkv)
+    }
+
+    /// The ffmpeg muxer (`-f`) that writes this format.
+    pub fn muxer(self) -> &'static str {
+        match self {
+            OutputFormat::Mkv => "matroska",
+            format => format.extension(),
+        }
+    }
+
+    /// The ffmpeg encoder `encode_video` uses for this format.
+    pub fn encoder(self) -> &'static str {
+        match self {
+            OutputFormat::Mp4 | OutputFormat::Mkv => "libx264",
+            OutputFormat::Webp => "libwebp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Webm => "libvpx-vp9",
+            OutputFormat::Apng => "apng",
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct VideoMetadata {
     pub width: u32,
     pub height: u32,
+    /// `r_frame_rate`: the base rate, exact for constant frame rate sources
     pub fps: f64,
+    /// `avg_frame_rate`: frames over duration, when known
+    pub avg_fps: Option<f64>,
+    /// Frame count reported by the container, when known
+    pub frame_count: Option<u64>,
+    /// Stream duration in seconds, when known
+    pub duration: Option<f64>,
+    /// Clockwise rotation (0, 90, 180 or 270) players apply from the
+    /// `rotate` tag or display matrix, as phones record it
+    pub rotation: u32,
+}
+
+impl VideoMetadata {
+    /// Frame size after rotation, which is what ffmpeg decodes since it
+    /// autorotates; `width`/`height` are the coded size.
+    pub fn display_size(&self) -> (u32, u32) {
+        match self.rotation {
+            90 | 270 => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
+
+    /// Whether the base and average rates disagree by more than 1%, which
+    /// means frames are not evenly spaced (phone footage, screen recordings).
+    pub fn is_variable_rate(&self) -> bool {
+        self.avg_fps
+            .is_some_and(|avg| (avg - self.fps).abs() > self.fps * 0.01)
+    }
+
+    /// Rate the output should play at: the average rate for variable frame
+    /// rate sources, so playback keeps its length and stays in sync with audio.
+    pub fn effective_fps(&self) -> f64 {
+        match self.avg_fps {
+            Some(avg) if self.is_variable_rate() => avg,
+            _ => self.fps,
+        }
+    }
+}
+
+/// How the original and ASCII videos are arranged in a comparison video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompareLayout {
+    /// Original on top, ASCII below
+    Vertical,
+    /// Original on the left, ASCII on the right
+    Horizontal,
+}
+
+/// How converted frames are timed in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Timing {
+    /// Evenly spaced frames at a constant rate
+    Cfr,
+    /// Each frame keeps the source's presentation timestamp
+    Preserve,
+}
+
+/// Name of the concat list `write_concat_list` puts next to the converted
+/// frames for `Timing::Preserve`.
+pub const CONCAT_LIST: &str = "frames.ffconcat";
+
+/// Corner of the frame a `--label` caption is drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LabelPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl LabelPosition {
+    /// drawtext `x`/`y` expressions placing the text box in this corner.
+    fn coordinates(self) -> (&'static str, &'static str) {
+        let (left, right) = ("16", "w-tw-16");
+        let (top, bottom) = ("16", "h-th-16");
+        match self {
+            LabelPosition::TopLeft => (left, top),
+            LabelPosition::TopRight => (right, top),
+            LabelPosition::BottomLeft => (left, bottom),
+            LabelPosition::BottomRight => (right, bottom),
+        }
+    }
+}
+
+/// Pixel format of MP4 output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PixelFormat {
+    /// 4:2:0 chroma subsampling; plays everywhere
+    Yuv420p,
+    /// Full chroma resolution; crisper colored glyphs, but not playable in most browsers
+    Yuv444p,
+    /// Lossless-friendly RGB via libx264rgb; not playable in most browsers
+    Rgb24,
+}
+
+impl PixelFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            PixelFormat::Yuv420p => "yuv420p",
+            PixelFormat::Yuv444p => "yuv444p",
+            PixelFormat::Rgb24 => "rgb24",
+        }
+    }
+
+    /// Whether common browsers can play H.264 in this format.
+    pub fn browser_compatible(self) -> bool {
+        self == PixelFormat::Yuv420p
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Bits per color channel of MP4 output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BitDepth {
+    /// Standard 8-bit video; plays everywhere
+    #[value(name = "8")]
+    Eight,
+    /// 10-bit (High 10 profile); removes banding in smooth gradients, but
+    /// not playable in most browsers
+    #[value(name = "10")]
+    Ten,
+}
+
+/// Video codec of MP4 output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VideoCodec {
+    /// H.264 via libx264; fast and plays everywhere
+    X264,
+    /// H.265/HEVC via libx265; smaller files, limited browser support
+    X265,
+    /// AV1 via libsvtav1; smallest files at equal quality, but slow to encode
+    Av1,
+}
+
+impl VideoCodec {
+    /// `-preset` value for this codec. x265 shares x264's preset names;
+    /// SVT-AV1 takes a number from 0 (slowest) to 13, so names are mapped.
+    fn preset(self, name: &str) -> &str {
+        if self != VideoCodec::Av1 {
+            return name;
+        }
+        match name {
+            "ultrafast" => "12",
+            "superfast" => "11",
+            "veryfast" => "10",
+            "faster" => "9",
+            "fast" => "8",
+            "medium" => "6",
+            "slow" => "5",
+            "slower" => "4",
+            "veryslow" => "2",
+            _ => "0",
+        }
+    }
+}
+
+/// What happens to the source audio track in MP4 output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AudioMode {
+    /// Copy the track, re-encoding to AAC if the codec doesn't fit in MP4
+    Auto,
+    /// Copy the track as-is
+    Copy,
+    /// Re-encode the track to AAC
+    Aac,
+    /// Drop the audio
+    None,
+}
+
+impl AudioMode {
+    fn codec_args(self) -> &'static [&'static str] {
+        match self {
+            AudioMode::Auto | AudioMode::Copy => &["-c:a", "copy"],
+            AudioMode::Aac => &["-c:a", "aac"],
+            AudioMode::None => &["-an"],
+        }
+    }
+}
+
+/// Portion of the source to process. `start` is passed to ffmpeg as an input
+/// option (`-ss` before `-i`) for fast keyframe seeking, so the first frame may
+/// land slightly off the requested timestamp unless `accurate` is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeRange {
+    /// Seconds to skip from the beginning of the source
+    pub start: Option<f64>,
+    /// Seconds of source to process after `start`
+    pub duration: Option<f64>,
+    /// Seek frame-exactly when decoding frames, by passing `-ss` after `-i`:
+    /// ffmpeg then decodes and discards everything before `start`, which is
+    /// slower the further in it starts
+    pub accurate: bool,
+}
+
+impl TimeRange {
+    pub fn is_full(&self) -> bool {
+        self.start.is_none() && self.duration.is_none()
+    }
+
+    /// ffmpeg input options that must precede the corresponding `-i`.
+    fn input_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(start) = self.start {
+            args.extend(["-ss".to_string(), format!("{start}")]);
+        }
+        if let Some(duration) = self.duration {
+            args.extend(["-t".to_string(), format!("{duration}")]);
+        }
+        args
+    }
+
+    /// Decoder arguments for `input` limited to this range: seek options
+    /// before `-i` for fast seeking, or after it when `accurate`.
+    fn decode_input_args(&self, input: &Path) -> Vec<OsString> {
+        let (before, after) = if self.accurate { (Vec::new(), self.input_args()) } else { (self.input_args(), Vec::new()) };
+        let mut args = os_args(before);
+        args.push("-i".into());
+        args.push(input.into());
+        args.extend(os_args(after));
+        args
+    }
+}
+
+/// Region of the source frame to keep, in pixels of the upright (autorotated)
+/// frame. Parsed from `WIDTH:HEIGHT:X:Y` or `WIDTHxHEIGHT+X+Y`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Crop {
+    /// Check the region lies inside a `width`x`height` frame.
+    pub fn check_fits(&self, width: u32, height: u32) -> Result<()> {
+        let fits = self.x.checked_add(self.width).is_some_and(|right| right <= width)
+            && self.y.checked_add(self.height).is_some_and(|bottom| bottom <= height);
+        if fits {
+            Ok(())
+        } else {
+            Err(AppError::CropOutOfBounds {
+                crop: *self,
+                width,
+                height,
+            })
+        }
+    }
+
+    fn filter(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl std::str::FromStr for Crop {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let parts: Vec<&str> = match value.split_once('x') {
+            Some((width, rest)) => {
+                let mut parts = vec![width];
+                parts.extend(rest.split('+'));
+                parts
+            }
+            None => value.split(':').collect(),
+        };
+        let numbers: Vec<u32> = parts
+            .iter()
+            .map(|part| part.trim().parse::<u32>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| format!("`{value}` is not a crop like WIDTH:HEIGHT:X:Y or WIDTHxHEIGHT+X+Y"))?;
+        match numbers[..] {
+            [width, height, x, y] if width > 0 && height > 0 => Ok(Crop { width, height, x, y }),
+            [_, _, _, _] => Err(format!("`{value}` must have a non-zero width and height")),
+            _ => Err(format!("`{value}` must have four values: WIDTH:HEIGHT:X:Y")),
+        }
+    }
+}
+
+/// Encoder settings applied when writing the output file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub format: OutputFormat,
+    /// Handling of the source audio track (MP4 only)
+    pub audio: AudioMode,
+    /// H.264 constant rate factor (0-51, lower = higher quality)
+    pub crf: u8,
+    /// x264 speed/compression preset
+    pub preset: String,
+    /// MP4 pixel format
+    pub pix_fmt: PixelFormat,
+    /// MP4 bits per channel
+    pub bit_depth: BitDepth,
+    /// MP4 video codec
+    pub codec: VideoCodec,
+    /// libwebp quality (0-100); with `webp_lossless` it trades speed for size
+    pub webp_quality: u8,
+    /// Encode WebP losslessly, often smaller for flat ASCII frames
+    pub webp_lossless: bool,
+    /// Times an animated GIF/WebP/APNG plays; 0 loops forever
+    pub loop_count: u32,
+    /// Caption burned into every frame
+    pub label: Option<String>,
+    /// Corner the caption is drawn in
+    pub label_position: LabelPosition,
+    /// Constant rate, or the source's own frame timestamps
+    pub timing: Timing,
+    /// Encode on one thread without muxer timestamps or metadata, so equal
+    /// frames always give a byte-identical file (slower)
+    pub deterministic: bool,
+}
+
+impl EncodeOptions {
+    /// The ffmpeg encoder `encode_video` uses. RGB H.264 needs x264's RGB build.
+    pub fn encoder(&self) -> &'static str {
+        if !self.format.uses_codec() {
+            return self.format.encoder();
+        }
+        match (self.codec, self.pix_fmt) {
+            (VideoCodec::X264, PixelFormat::Rgb24) => "libx264rgb",
+            (VideoCodec::X264, _) => "libx264",
+            (VideoCodec::X265, _) => "libx265",
+            (VideoCodec::Av1, _) => "libsvtav1",
+        }
+    }
+
+    /// The `-pix_fmt` MP4 output is encoded in, widened by `bit_depth`.
+    pub fn mp4_pixel_format(&self) -> &'static str {
+        match (self.pix_fmt, self.bit_depth) {
+            (pix_fmt, BitDepth::Eight) => pix_fmt.as_str(),
+            (PixelFormat::Yuv420p, BitDepth::Ten) => "yuv420p10le",
+            (PixelFormat::Yuv444p, BitDepth::Ten) => "yuv444p10le",
+            (PixelFormat::Rgb24, BitDepth::Ten) => "gbrp10le",
+        }
+    }
+
+    /// Whether common browsers can play the MP4 this produces.
+    pub fn browser_compatible(&self) -> bool {
+        self.pix_fmt.browser_compatible() && self.bit_depth == BitDepth::Eight && self.codec != VideoCodec::X265
+    }
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Mp4,
+            audio: AudioMode::Auto,
+            crf: DEFAULT_CRF,
+            preset: DEFAULT_PRESET.to_string(),
+            pix_fmt: PixelFormat::Yuv420p,
+            bit_depth: BitDepth::Eight,
+            codec: VideoCodec::X264,
+            webp_quality: DEFAULT_WEBP_QUALITY,
+            webp_lossless: false,
+            loop_count: 0,
+            label: None,
+            label_position: LabelPosition::BottomLeft,
+            timing: Timing::Cfr,
+            deterministic: false,
+        }
+    }
 }
 
-pub fn tools_available() -> bool {
-    command_success("ffmpeg", &["-version"]) && command_success("ffprobe", &["-version"])
+pub const DEFAULT_CRF: u8 = 18;
+pub const DEFAULT_PRESET: &str = "veryfast";
+pub const DEFAULT_WEBP_QUALITY: u8 = 95;
+
+/// Preset names accepted by libx264, fastest to slowest.
+pub const X264_PRESETS: &[&str] = &[
+    "ultrafast",
+    "superfast",
+    "veryfast",
+    "faster",
+    "fast",
+    "medium",
+    "slow",
+    "slower",
+    "veryslow",
+    "placebo",
+];
+
+pub fn validate_preset(preset: &str) -> Result<()> {
+    if X264_PRESETS.contains(&preset) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidPreset(preset.to_string()))
+    }
+}
+
+/// Still-image extensions that are converted directly, without ffmpeg.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "tif", "tiff"];
+
+/// Containers accepted as video input without `force`, besides images and GIF.
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "mpg", "mpeg", "ts", "mts", "m2ts", "3gp", "ogv", "apng",
+];
+
+/// The extension of `path` when it is neither a still image nor a known
+/// video container (e.g. `txt`, `zip`). URLs and paths without an extension
+/// are left for ffprobe to judge.
+pub fn unsupported_extension(path: &Path) -> Option<String> {
+    let ext = path.extension().filter(|_| !is_url(path))?.to_string_lossy().to_ascii_lowercase();
+    let known = IMAGE_EXTENSIONS.contains(&ext.as_str()) || VIDEO_EXTENSIONS.contains(&ext.as_str()) || ext == "gif";
+    (!known).then_some(ext)
+}
+
+/// Whether `output` is `-`, which writes the encoded video to stdout instead
+/// of a file.
+pub fn is_stdout(output: &Path) -> bool {
+    output == Path::new("-")
+}
+
+/// Whether `input` is an http(s) URL that ffmpeg should open directly.
+pub fn is_url(input: &Path) -> bool {
+    input.to_str().is_some_and(|s| {
+        let lower = s.to_ascii_lowercase();
+        lower.starts_with("http://") || lower.starts_with("https://")
+    })
+}
+
+/// Whether `path` looks like a still image rather than a video. URLs are
+/// always treated as video since they're opened by ffmpeg.
+pub fn is_image_path(path: &Path) -> bool {
+    !is_url(path)
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Whether `path` is an animated GIF, which ffmpeg decodes like a video
+/// whose frames each carry their own delay.
+pub fn is_gif_path(path: &Path) -> bool {
+    !is_url(path)
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"))
+}
+
+/// Program names used when no explicit ffmpeg/ffprobe path is configured.
+pub const DEFAULT_FFMPEG: &str = "ffmpeg";
+pub const DEFAULT_FFPROBE: &str = "ffprobe";
+
+pub fn tools_available(ffmpeg: &Path, ffprobe: &Path) -> bool {
+    command_success(ffmpeg, &["-version"]) && command_success(ffprobe, &["-version"])
+}
+
+/// Check that ffmpeg was built with the encoders this run needs, so a stripped
+/// distro build fails up front instead of after every frame is converted.
+/// Comparison videos and audio copies are always H.264, requested by `h264`.
+pub fn verify_encoders(ffmpeg: &Path, options: &EncodeOptions, h264: bool) -> Result<()> {
+    log::trace!("checking encoders with {} -hide_banner -encoders", ffmpeg.display());
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-encoders"])
+        .output()
+        .map_err(|source| AppError::CommandSpawn {
+            program: ffmpeg.display().to_string(),
+            source,
+        })?;
+
+    ensure_command_success(ffmpeg, &output)?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let available = parse_encoder_names(&stdout);
+    let mut required = vec![options.encoder()];
+    if h264 {
+        required.push(OutputFormat::Mp4.encoder());
+    }
+
+    match required.into_iter().find(|name| !available.contains(name)) {
+        Some(name) => Err(AppError::MissingEncoder {
+            name: name.to_string(),
+        }),
+        None => Ok(()),
+    }
 }
 
-pub fn probe_video(input: &Path) -> Result<VideoMetadata> {
-    let output = Command::new("ffprobe")
+/// Presentation timestamps (seconds) of the video frames inside `range`, in
+/// display order. Read from packets, so nothing is decoded.
+pub fn probe_frame_timestamps(ffprobe: &Path, input: &Path, range: &TimeRange) -> Result<Vec<f64>> {
+    let output = Command::new(ffprobe)
         .args([
             "-v",
             "error",
             "-select_streams",
             "v:0",
             "-show_entries",
-            "stream=width,height,r_frame_rate",
+            "packet=pts_time",
             "-of",
-            "default=noprint_wrappers=1:nokey=1",
+            "csv=p=0",
         ])
         .arg(input)
         .output()
         .map_err(|source| AppError::CommandSpawn {
-            program: "ffprobe".to_string(),
+            program: ffprobe.display().to_string(),
             source,
         })?;
 
-    ensure_command_success("ffprobe", &output)?;
+    ensure_command_success(ffprobe, &output)?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut lines = stdout.lines();
+    Ok(parse_timestamps(&String::from_utf8_lossy(&output.stdout), range))
+}
+
+/// Parse one `pts_time` per line, sort packets from decode into display
+/// order and keep those in `range`, rebased so the first starts at zero.
+fn parse_timestamps(stdout: &str, range: &TimeRange) -> Vec<f64> {
+    let start = range.start.unwrap_or(0.0);
+    let end = range.duration.map_or(f64::INFINITY, |duration| start + duration);
+
+    let mut timestamps: Vec<f64> = stdout
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse().ok())
+        .filter(|&time| time >= start && time < end)
+        .collect();
+    timestamps.sort_by(f64::total_cmp);
+
+    let first = timestamps.first().copied().unwrap_or(0.0);
+    timestamps.iter().map(|time| time - first).collect()
+}
+
+/// Write an ffconcat list into `frames_dir` that shows each of the
+/// `frame_count` converted frames for the gap to the next source timestamp.
+/// Frames without a timestamp, and the last one, last `1 / fps`.
+pub fn write_concat_list(frames_dir: &Path, frame_count: usize, timestamps: &[f64], fps: f64) -> Result<PathBuf> {
+    let path = frames_dir.join(CONCAT_LIST);
+    fs::write(&path, concat_list(frame_count, timestamps, fps))?;
+    Ok(path)
+}
+
+fn concat_list(frame_count: usize, timestamps: &[f64], fps: f64) -> String {
+    let fallback = if fps > 0.0 { 1.0 / fps } else { 0.04 };
+    let mut list = String::from("ffconcat version 1.0\n");
+    for index in 0..frame_count {
+        let duration = match (timestamps.get(index), timestamps.get(index + 1)) {
+            (Some(this), Some(next)) if next > this => next - this,
+            _ => fallback,
+        };
+        list.push_str(&format!("file frame_{index:08}.png\nduration {duration:.6}\n"));
+    }
+    // The concat demuxer ignores the last entry's duration unless it is repeated
+    if frame_count > 0 {
+        list.push_str(&format!("file frame_{:08}.png\n", frame_count - 1));
+    }
+    list
+}
+
+/// Check that ffmpeg's MP4 encoder can write the requested pixel format. x264
+/// only gained 10-bit support in the same library as 8-bit in 2017, so older
+/// and stripped builds still lack it; x265 and SVT-AV1 support fewer formats.
+pub fn verify_pixel_format(ffmpeg: &Path, options: &EncodeOptions) -> Result<()> {
+    let default_h264 = options.codec == VideoCodec::X264 && options.bit_depth == BitDepth::Eight;
+    if !options.format.uses_codec() || default_h264 {
+        return Ok(());
+    }
+
+    let pix_fmt = options.mp4_pixel_format();
+    if encoder_pixel_formats(ffmpeg, options.encoder())?.iter().any(|name| name == pix_fmt) {
+        Ok(())
+    } else {
+        Err(AppError::UnsupportedPixelFormat {
+            encoder: options.encoder().to_string(),
+            pix_fmt: pix_fmt.to_string(),
+        })
+    }
+}
+
+/// Pixel formats `encoder` accepts, from `ffmpeg -h encoder=<name>`.
+pub fn encoder_pixel_formats(ffmpeg: &Path, encoder: &str) -> Result<Vec<String>> {
+    log::trace!("checking pixel formats with {} -hide_banner -h encoder={encoder}", ffmpeg.display());
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-h", &format!("encoder={encoder}")])
+        .output()
+        .map_err(|source| AppError::CommandSpawn {
+            program: ffmpeg.display().to_string(),
+            source,
+        })?;
+
+    ensure_command_success(ffmpeg, &output)?;
+
+    Ok(parse_pixel_formats(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .map(str::to_string)
+        .collect())
+}
 
-    let width = lines
-        .next()
+/// The names after `Supported pixel formats:` in an encoder's help text.
+fn parse_pixel_formats(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Supported pixel formats:"))
+        .map(|formats| formats.split_whitespace().collect())
+        .unwrap_or_default()
+}
+
+/// Check that ffmpeg has the filters this run needs. `drawtext` is missing
+/// from builds without libfreetype, which would otherwise surface as a terse
+/// "No such filter" after every frame is converted.
+pub fn verify_filters(ffmpeg: &Path, options: &EncodeOptions) -> Result<()> {
+    if options.label.is_none() {
+        return Ok(());
+    }
+
+    log::trace!("checking filters with {} -hide_banner -filters", ffmpeg.display());
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-filters"])
+        .output()
+        .map_err(|source| AppError::CommandSpawn {
+            program: ffmpeg.display().to_string(),
+            source,
+        })?;
+
+    ensure_command_success(ffmpeg, &output)?;
+
+    if parse_filter_names(&String::from_utf8_lossy(&output.stdout)).contains(&"drawtext") {
+        Ok(())
+    } else {
+        Err(AppError::MissingFilter {
+            name: "drawtext".to_string(),
+        })
+    }
+}
+
+/// Filter names from `ffmpeg -filters`, listed as `<flags> <name> <io> <description>`
+/// after a legend whose lines read `<flags> = <meaning>`.
+fn parse_filter_names(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_flags, name, io) = (fields.next()?, fields.next()?, fields.next()?);
+            io.contains("->").then_some(name)
+        })
+        .collect()
+}
+
+/// Encoder names from `ffmpeg -encoders`, which lists them after a ` ------`
+/// separator as `<flags> <name> <description>`.
+fn parse_encoder_names(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .collect()
+}
+
+pub fn probe_video(ffprobe: &Path, input: &Path) -> Result<VideoMetadata> {
+    let mut args = os_args([
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-show_entries",
+        "stream=width,height,r_frame_rate,avg_frame_rate,nb_frames,duration:stream_tags=rotate:stream_side_data=rotation",
+        "-of",
+        "default=noprint_wrappers=1",
+    ]);
+    args.push(input.into());
+    let output = run_command(ffprobe, &args)?;
+
+    ensure_command_success(ffprobe, &output)?;
+
+    let metadata = parse_probe_output(&String::from_utf8_lossy(&output.stdout))?;
+    log::debug!("probed {}: {metadata:?}", input.display());
+    Ok(metadata)
+}
+
+/// Parse ffprobe `key=value` stream entries into metadata.
+/// `nb_frames` and `duration` are optional since many containers report `N/A`.
+fn parse_probe_output(stdout: &str) -> Result<VideoMetadata> {
+    let field = |key: &str| {
+        stdout
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim())
+    };
+
+    let width = field("width")
         .ok_or_else(|| AppError::ProbeParse("missing width".to_string()))?
-        .trim()
         .parse::<u32>()
         .map_err(|_| AppError::ProbeParse("invalid width".to_string()))?;
 
-    let height = lines
-        .next()
+    let height = field("height")
         .ok_or_else(|| AppError::ProbeParse("missing height".to_string()))?
-        .trim()
         .parse::<u32>()
         .map_err(|_| AppError::ProbeParse("invalid height".to_string()))?;
 
-    let frame_rate = lines
-        .next()
-        .ok_or_else(|| AppError::ProbeParse("missing frame rate".to_string()))?
-        .trim();
+    let frame_rate = field("r_frame_rate")
+        .ok_or_else(|| AppError::ProbeParse("missing frame rate".to_string()))?;
     let fps = parse_rational(frame_rate)
         .ok_or_else(|| AppError::ProbeParse(format!("invalid frame rate: {frame_rate}")))?;
 
-    Ok(VideoMetadata { width, height, fps })
+    // "0/0" when ffprobe can't tell
+    let avg_fps = field("avg_frame_rate").and_then(parse_rational);
+    let frame_count = field("nb_frames").and_then(|v| v.parse::<u64>().ok());
+    let duration = field("duration").and_then(|v| v.parse::<f64>().ok());
+
+    // The legacy tag is clockwise; the display matrix angle is counterclockwise
+    let rotation = field("TAG:rotate")
+        .and_then(|v| v.parse::<f64>().ok())
+        .or_else(|| field("rotation").and_then(|v| v.parse::<f64>().ok()).map(|angle| -angle))
+        .map_or(0, |angle| ((angle / 90.0).round() as i64 * 90).rem_euclid(360) as u32);
+
+    Ok(VideoMetadata {
+        width,
+        height,
+        fps,
+        avg_fps,
+        frame_count,
+        duration,
+        rotation,
+    })
 }
 
-pub fn extract_frames(input: &Path, output_dir: &Path) -> Result<Vec<PathBuf>> {
-    fs::create_dir_all(output_dir)?;
-    let frame_pattern = output_dir.join("frame_%08d.png");
+/// Decoded frames read straight from an ffmpeg `rawvideo` pipe, one RGB image
+/// at a time, without writing anything to disk. A reader thread pulls frames
+/// off the pipe, so a decode that stops producing them can be timed out.
+pub struct FrameStream {
+    program: PathBuf,
+    child: Child,
+    frames: Receiver<std::io::Result<Option<Vec<u8>>>>,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    width: u32,
+    height: u32,
+    /// Longest to wait for each frame (see [`with_command_timeout`])
+    timeout: Option<Duration>,
+    finished: bool,
+}
 
-    let output = Command::new("ffmpeg")
-        .args(["-y", "-v", "error", "-i"])
-        .arg(input)
-        .args(["-vsync", "0"])
-        .arg(&frame_pattern)
-        .output()
+impl FrameStream {
+    fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+
+        let status = wait_for(&mut self.child, &self.program, self.timeout.map(|timeout| (Instant::now() + timeout, timeout)))?;
+        if status.success() {
+            return Ok(());
+        }
+
+        let stderr = self.stderr.take().and_then(|stderr| stderr.join().ok()).unwrap_or_default();
+        Err(AppError::CommandFailed {
+            program: self.program.display().to_string(),
+            code: status.code(),
+            stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+        })
+    }
+
+    fn timed_out(&mut self, timeout: Duration) -> AppError {
+        self.finished = true;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        AppError::CommandTimeout {
+            program: self.program.display().to_string(),
+            seconds: timeout.as_secs_f64(),
+        }
+    }
+}
+
+impl Iterator for FrameStream {
+    type Item = Result<RgbImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let frame = match self.timeout {
+            Some(timeout) => match self.frames.recv_timeout(timeout) {
+                Err(RecvTimeoutError::Timeout) => return Some(Err(self.timed_out(timeout))),
+                received => received.ok(),
+            },
+            None => self.frames.recv().ok(),
+        };
+        match frame.unwrap_or(Ok(None)) {
+            Ok(Some(buffer)) => RgbImage::from_raw(self.width, self.height, buffer).map(Ok),
+            Ok(None) => self.finish().err().map(Err),
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Fill `buffer` with one frame. Returns `false` at end of stream; a trailing
+/// partial frame is discarded.
+fn read_frame(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+/// Decode `input` and stream its frames as `width`x`height` RGB images.
+/// With `crop`, `width`/`height` must be the cropped size.
+pub fn stream_frames(
+    ffmpeg: &Path,
+    input: &Path,
+    range: &TimeRange,
+    crop: Option<Crop>,
+    fps: Option<f64>,
+    width: u32,
+    height: u32,
+) -> Result<FrameStream> {
+    let args = stream_args(input, range, crop, fps);
+    log::debug!("streaming frames: {}", shell_command(ffmpeg, &args));
+    let mut child = Command::new(ffmpeg)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
         .map_err(|source| AppError::CommandSpawn {
-            program: "ffmpeg".to_string(),
+            program: ffmpeg.display().to_string(),
             source,
         })?;
 
-    ensure_command_success("ffmpeg", &output)?;
+    let stdout = child.stdout.take().ok_or_else(|| AppError::CommandSpawn {
+        program: ffmpeg.display().to_string(),
+        source: std::io::Error::other("stdout was not captured"),
+    })?;
+
+    // One frame in flight keeps the decoder just ahead of conversion; the
+    // thread ends when the pipe closes or the stream is dropped
+    let (sender, frames) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(stdout);
+        loop {
+            let mut buffer = vec![0u8; width as usize * height as usize * 3];
+            let frame = read_frame(&mut reader, &mut buffer).map(|full| full.then_some(buffer));
+            let more = matches!(frame, Ok(Some(_)));
+            if sender.send(frame).is_err() || !more {
+                break;
+            }
+        }
+    });
+
+    Ok(FrameStream {
+        program: ffmpeg.to_path_buf(),
+        stderr: Some(drain(child.stderr.take())),
+        child,
+        frames,
+        width,
+        height,
+        timeout: COMMAND_TIMEOUT.get(),
+        finished: false,
+    })
+}
+
+/// ffmpeg arguments for `stream_frames`: raw RGB24 frames on stdout.
+pub fn stream_args(input: &Path, range: &TimeRange, crop: Option<Crop>, fps: Option<f64>) -> Vec<OsString> {
+    let mut args = os_args(["-v", "error"]);
+    args.extend(range.decode_input_args(input));
+    args.extend(os_args(decode_filter_args(crop, fps)));
+    args.extend(os_args(["-vsync", "0", "-f", "rawvideo", "-pix_fmt", "rgb24", "-"]));
+    args
+}
+
+/// `-vf crop=...,fps=N` so ffmpeg crops and drops frames while decoding;
+/// nothing when neither is requested.
+fn decode_filter_args(crop: Option<Crop>, fps: Option<f64>) -> Vec<String> {
+    let filters: Vec<String> = crop
+        .map(|crop| crop.filter())
+        .into_iter()
+        .chain(fps.map(|fps| format!("fps={fps:.6}")))
+        .collect();
+    if filters.is_empty() {
+        Vec::new()
+    } else {
+        vec!["-vf".to_string(), filters.join(",")]
+    }
+}
+
+pub fn extract_frames(
+    ffmpeg: &Path,
+    input: &Path,
+    range: &TimeRange,
+    crop: Option<Crop>,
+    fps: Option<f64>,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    extract_frames_limited(ffmpeg, input, range, crop, fps, None, output_dir)
+}
+
+/// Like `extract_frames`, but stop after at most `count` frames.
+pub fn extract_frame_batch(
+    ffmpeg: &Path,
+    input: &Path,
+    range: &TimeRange,
+    crop: Option<Crop>,
+    fps: Option<f64>,
+    count: u32,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    extract_frames_limited(ffmpeg, input, range, crop, fps, Some(count), output_dir)
+}
+
+fn extract_frames_limited(
+    ffmpeg: &Path,
+    input: &Path,
+    range: &TimeRange,
+    crop: Option<Crop>,
+    fps: Option<f64>,
+    limit: Option<u32>,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(output_dir)?;
+    let output = run_command(ffmpeg, &extract_args(input, range, crop, fps, limit, output_dir))?;
+    ensure_command_success(ffmpeg, &output)?;
+
+    list_frames(output_dir)
+}
+
+/// ffmpeg arguments for `extract_frames` (`limit: None`) and
+/// `extract_frame_batch`.
+pub fn extract_args(
+    input: &Path,
+    range: &TimeRange,
+    crop: Option<Crop>,
+    fps: Option<f64>,
+    limit: Option<u32>,
+    output_dir: &Path,
+) -> Vec<OsString> {
+    let mut args = os_args(["-y", "-v", "error"]);
+    args.extend(range.decode_input_args(input));
+    args.extend(os_args(decode_filter_args(crop, fps)));
+    args.extend(os_args(["-vsync", "0"]));
+    if let Some(count) = limit {
+        args.extend(os_args(["-frames:v".to_string(), count.to_string()]));
+    }
+    args.push(output_dir.join("frame_%08d.png").into());
+    args
+}
 
-    let mut files: Vec<PathBuf> = fs::read_dir(output_dir)?
+/// Sorted PNG frames previously written to `dir`.
+pub fn list_frames(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
         .filter_map(|entry| entry.ok())
         .map(|entry| entry.path())
         .filter(|path| path.extension() == Some(OsStr::new("png")))
         .collect();
     files.sort();
 
-    if files.is_empty() {
-        return Err(AppError::NoFramesExtracted);
+    if files.is_empty() {
+        return Err(AppError::NoFramesExtracted);
+    }
+
+    Ok(files)
+}
+
+/// Decode one extracted frame, naming the file if it is truncated or corrupt.
+pub fn open_frame(path: &Path) -> Result<DynamicImage> {
+    image::open(path).map_err(|source| AppError::FrameDecode {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub fn encode_video(
+    ffmpeg: &Path,
+    ascii_frames_dir: &Path,
+    source_video: &Path,
+    range: &TimeRange,
+    fps: f64,
+    output: &Path,
+    options: &EncodeOptions,
+) -> Result<()> {
+    if let Some(parent) = output.parent().filter(|_| !is_stdout(output)) {
+        fs::create_dir_all(parent)?;
+    }
+
+    if is_stdout(output) {
+        // ffmpeg writes straight to our stdout as it encodes. Bytes already
+        // sent can't be retried, so `auto` audio transcodes up front
+        let audio = if options.audio == AudioMode::Auto { AudioMode::Aac } else { options.audio };
+        let args = encode_args(ascii_frames_dir, source_video, range, fps, output, options, audio);
+        let output_cmd = run_command_with_stdout(ffmpeg, &args, Stdio::inherit())?;
+        return ensure_command_success(ffmpeg, &output_cmd);
+    }
+
+    let output_cmd = encode_with_audio_fallback(options, |audio| {
+        run_command(
+            ffmpeg,
+            &encode_args(ascii_frames_dir, source_video, range, fps, output, options, audio),
+        )
+    })?;
+
+    ensure_command_success(ffmpeg, &output_cmd)
+}
+
+/// Run `encode` with the configured audio mode, retrying once with AAC when
+/// an `auto` MP4 encode fails on the audio stream copy. Stream copy fails for
+/// codecs MP4 can't hold (e.g. PCM from a MOV); transcoding fixes that
+/// without throwing away the converted frames. Other failures are returned
+/// as they are.
+fn encode_with_audio_fallback(
+    options: &EncodeOptions,
+    mut encode: impl FnMut(AudioMode) -> Result<std::process::Output>,
+) -> Result<std::process::Output> {
+    let attempt = encode(options.audio)?;
+    let stderr = String::from_utf8_lossy(&attempt.stderr);
+    if options.format.uses_codec()
+        && options.audio == AudioMode::Auto
+        && !attempt.status.success()
+        && is_audio_copy_failure(&stderr)
+    {
+        log::debug!("audio stream copy failed, retrying with AAC: {}", stderr.trim());
+        return encode(AudioMode::Aac);
     }
+    Ok(attempt)
+}
 
-    Ok(files)
+/// Whether ffmpeg's stderr blames the copied audio, which `encode_args` maps
+/// to output stream 1 after the video.
+fn is_audio_copy_failure(stderr: &str) -> bool {
+    const MARKERS: &[&str] = &[
+        "could not find tag for codec",
+        "not currently supported in container",
+        "output stream 0:1",
+        "stream #1",
+    ];
+    let stderr = stderr.to_ascii_lowercase();
+    MARKERS.iter().any(|marker| stderr.contains(marker))
 }
 
-pub fn encode_video(
+/// ffmpeg arguments for `encode_video`. `audio` is passed separately from
+/// `options.audio` so the AAC fallback can build its retry.
+pub fn encode_args(
     ascii_frames_dir: &Path,
     source_video: &Path,
+    range: &TimeRange,
     fps: f64,
     output: &Path,
-    transparent: bool,
-) -> Result<()> {
-    if let Some(parent) = output.parent() {
-        fs::create_dir_all(parent)?;
-    }
+    options: &EncodeOptions,
+    audio: AudioMode,
+) -> Vec<OsString> {
+    let mut args = match options.timing {
+        Timing::Cfr => {
+            let mut args = os_args(["-y", "-v", "error", "-framerate", &format!("{fps:.6}"), "-i"]);
+            args.push(ascii_frames_dir.join("frame_%08d.png").into());
+            args
+        }
+        Timing::Preserve => {
+            let mut args = os_args(["-y", "-v", "error", "-f", "concat", "-safe", "0", "-i"]);
+            args.push(ascii_frames_dir.join(CONCAT_LIST).into());
+            // Keep the listed durations instead of duplicating frames to a constant rate
+            args.extend(os_args(["-vsync", "vfr"]));
+            args
+        }
+    };
 
-    let frame_pattern = ascii_frames_dir.join("frame_%08d.png");
-    let fps_string = format!("{fps:.6}");
+    let label = options.label.as_deref().map(|text| drawtext_filter(text, options.label_position));
+    // GIF already runs a filter graph, so the caption joins it there
+    if let (Some(filter), false) = (&label, options.format == OutputFormat::Gif) {
+        args.extend(os_args(["-vf", filter]));
+    }
 
-    let output_cmd = if transparent {
-        // WebP with transparency
-        Command::new("ffmpeg")
-            .args(["-y", "-v", "error", "-framerate"])
-            .arg(&fps_string)
-            .arg("-i")
-            .arg(&frame_pattern)
-            .args([
+    match options.format {
+        OutputFormat::Webp => {
+            // WebP with transparency
+            let quality = options.webp_quality.to_string();
+            let lossless = if options.webp_lossless { "1" } else { "0" };
+            args.extend(os_args([
                 "-c:v",
                 "libwebp",
                 "-pix_fmt",
                 "yuva420p", // Include alpha channel
                 "-quality",
-                "95",
+                &quality,
+                "-lossless",
+                lossless,
                 "-loop",
-                "0", // Loop infinitely
-            ])
-            .arg(output)
-            .output()
-            .map_err(|source| AppError::CommandSpawn {
-                program: "ffmpeg".to_string(),
-                source,
-            })?
-    } else {
-        // MP4 with H.264 (original behavior)
-        Command::new("ffmpeg")
-            .args(["-y", "-v", "error", "-framerate"])
-            .arg(&fps_string)
-            .arg("-i")
-            .arg(&frame_pattern)
-            .arg("-i")
-            .arg(source_video)
-            .args([
+                &loop_arg(options.format, options.loop_count),
+            ]));
+        }
+        OutputFormat::Webm => {
+            // VP9 keeps alpha as a side channel when encoded from yuva420p
+            args.extend(os_args([
+                "-c:v",
+                "libvpx-vp9",
+                "-pix_fmt",
+                "yuva420p", // Include alpha channel
+                "-crf",
+                "30",
+                "-b:v",
+                "0", // Constant quality mode
+            ]));
+        }
+        OutputFormat::Apng => {
+            // Lossless RGBA frames; -plays counts full plays like WebP's -loop
+            args.extend(os_args([
+                "-f",
+                "apng",
+                "-pix_fmt",
+                "rgba",
+                "-plays",
+                &loop_arg(options.format, options.loop_count),
+            ]));
+        }
+        OutputFormat::Gif => {
+            // Two-pass palette: generate an optimal 256-colour palette, then map onto it
+            args.extend(os_args([
+                "-filter_complex",
+                &gif_filter(label.as_deref()),
+                "-loop",
+                &loop_arg(options.format, options.loop_count),
+            ]));
+        }
+        OutputFormat::Mp4 | OutputFormat::Mkv => {
+            // MP4 or MKV with H.264 (original behavior), H.265 or AV1
+            args.extend(os_args(range.input_args()));
+            args.push("-i".into());
+            args.push(source_video.into());
+            args.extend(os_args([
                 "-map",
                 "0:v:0",
                 "-map",
                 "1:a?",
                 "-c:v",
-                "libx264",
+                options.encoder(),
                 "-preset",
-                "veryfast",
+                options.codec.preset(&options.preset),
                 "-crf",
-                "18",
+                &options.crf.to_string(),
                 "-pix_fmt",
-                "yuv420p",
-                "-tune",
-                "stillimage",
-                "-c:a",
-                "copy",
-                "-shortest",
-            ])
-            .arg(output)
-            .output()
-            .map_err(|source| AppError::CommandSpawn {
-                program: "ffmpeg".to_string(),
-                source,
-            })?
-    };
+                options.mp4_pixel_format(),
+            ]));
+            match options.codec {
+                // stillimage is an x264-only tune
+                VideoCodec::X264 => args.extend(os_args(["-tune", "stillimage"])),
+                // Apple players only accept HEVC in MP4 tagged hvc1
+                VideoCodec::X265 if options.format == OutputFormat::Mp4 => args.extend(os_args(["-tag:v", "hvc1"])),
+                VideoCodec::X265 | VideoCodec::Av1 => {}
+            }
+            args.extend(os_args(audio.codec_args()));
+            args.push("-shortest".into());
+            if is_stdout(output) && options.format == OutputFormat::Mp4 {
+                // A pipe can't seek back to put the index in front
+                args.extend(os_args(["-movflags", "frag_keyframe+empty_moov"]));
+            }
+        }
+    }
+
+    // Name the muxer when the extension can't: --format mkv may write to any
+    // extension, and stdout has none (APNG always names its own)
+    if (options.format == OutputFormat::Mkv || is_stdout(output)) && options.format != OutputFormat::Apng {
+        args.extend(os_args(["-f", options.format.muxer()]));
+    }
 
-    ensure_command_success("ffmpeg", &output_cmd)
+    if options.deterministic {
+        // Threaded encoders split work by timing, and muxers stamp the
+        // encoder version and creation time
+        args.extend(os_args([
+            "-threads",
+            "1",
+            "-map_metadata",
+            "-1",
+            "-fflags",
+            "+bitexact",
+            "-flags:v",
+            "+bitexact",
+            "-flags:a",
+            "+bitexact",
+        ]));
+    }
+
+    args.push(if is_stdout(output) { "pipe:1".into() } else { output.into() });
+    args
+}
+
+/// palettegen/paletteuse in a single filter graph; `reserve_transparent` keeps
+/// a palette slot for fully transparent pixels coming from RGBA frames.
+const GIF_PALETTE_FILTER: &str =
+    "split[a][b];[a]palettegen=reserve_transparent=1[p];[b][p]paletteuse=alpha_threshold=128";
+
+/// The GIF palette graph, with `prefix` filters (e.g. a caption) applied first.
+fn gif_filter(prefix: Option<&str>) -> String {
+    match prefix {
+        Some(prefix) => format!("[0:v]{prefix},{GIF_PALETTE_FILTER}"),
+        None => format!("[0:v]{GIF_PALETTE_FILTER}"),
+    }
+}
+
+/// `drawtext` filter writing `text` verbatim in a half-transparent box at
+/// `position`. The text is escaped twice: once as a filter option value and
+/// once for the filter graph, and `%{...}` expansion is turned off.
+fn drawtext_filter(text: &str, position: LabelPosition) -> String {
+    let (x, y) = position.coordinates();
+    let value = escape_filter(&escape_filter(text, "\\':"), "\\'[],;");
+    format!(
+        "drawtext=text={value}:expansion=none:fontsize=24:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=8:x={x}:y={y}"
+    )
+}
+
+/// Backslash-escape every character of `text` that appears in `special`.
+fn escape_filter(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if special.contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Build the `-filter_complex` graph stacking the original (input 0) and the
+/// ASCII render (input 1), each given as `(width, height)`. `vstack` needs
+/// equal widths and `hstack` equal heights, so both sides are padded to the
+/// larger of the two rather than scaled, keeping their aspect ratios. The
+/// widths differ whenever the ASCII render was resized or cropped.
+fn comparison_filter(layout: CompareLayout, original: (u32, u32), ascii: (u32, u32)) -> String {
+    match layout {
+        CompareLayout::Vertical => {
+            let width = original.0.max(ascii.0);
+            format!("[0:v]pad={width}:ih[a];[1:v]pad={width}:ih[b];[a][b]vstack")
+        }
+        CompareLayout::Horizontal => {
+            let height = original.1.max(ascii.1);
+            format!("[0:v]pad=iw:{height}[a];[1:v]pad=iw:{height}[b];[a][b]hstack")
+        }
+    }
 }
 
 pub fn create_comparison_video(
+    ffmpeg: &Path,
     original: &Path,
     ascii_video: &Path,
+    range: &TimeRange,
+    output: &Path,
+    layout: CompareLayout,
+    sizes: ((u32, u32), (u32, u32)),
 ) -> Result<()> {
-    // Determine output path (original + ASCII, stacked)
-    let output = original.with_file_name(
-        original
-            .file_stem()
-            .unwrap()
-            .to_string_lossy()
-            .to_string() + "_compare." + ascii_video.extension().unwrap().to_string_lossy().as_ref()
-    );
+    // ffmpeg would truncate the source before reading it
+    if same_file(original, output) {
+        return Err(AppError::OutputEqualsInput(output.to_path_buf()));
+    }
 
     if let Some(parent) = output.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    // Use ffmpeg's vstack filter to stack videos vertically
-    let output_cmd = Command::new("ffmpeg")
-        .args(["-y", "-v", "error"])
-        .arg("-i")
-        .arg(original)
-        .arg("-i")
-        .arg(ascii_video)
-        .args([
-            "-filter_complex",
-            "[0:v][1:v]vstack",
-            "-c:v",
-            "libx264",
-            "-preset",
-            "veryfast",
-            "-crf",
-            "18",
-            "-pix_fmt",
-            "yuv420p",
-            "-tune",
-            "stillimage",
-        ])
-        .arg(&output)
-        .output()
-        .map_err(|source| AppError::CommandSpawn {
-            program: "ffmpeg".to_string(),
-            source,
-        })?;
+    let output_cmd = run_command(ffmpeg, &comparison_args(original, ascii_video, range, output, layout, sizes))?;
+    ensure_command_success(ffmpeg, &output_cmd)
+}
+
+/// ffmpeg `-loop`/`-plays` value for playing an animation `count` times
+/// (0 = forever). The WebP and APNG muxers take the play count directly, while the GIF muxer counts
+/// repeats after the first play and uses -1 for "play once".
+fn loop_arg(format: OutputFormat, count: u32) -> String {
+    match (format, count) {
+        (_, 0) => "0".to_string(),
+        (OutputFormat::Gif, 1) => "-1".to_string(),
+        (OutputFormat::Gif, count) => (count - 1).to_string(),
+        (_, count) => count.to_string(),
+    }
+}
+
+/// ffmpeg arguments for `create_comparison_video`; `sizes` holds the
+/// `(width, height)` of the original and then the ASCII video.
+pub fn comparison_args(
+    original: &Path,
+    ascii_video: &Path,
+    range: &TimeRange,
+    output: &Path,
+    layout: CompareLayout,
+    (original_size, ascii_size): ((u32, u32), (u32, u32)),
+) -> Vec<OsString> {
+    // Use ffmpeg's vstack/hstack filter to place the videos next to each other
+    let filter = comparison_filter(layout, original_size, ascii_size);
+    let mut args = os_args(["-y", "-v", "error"]);
+    args.extend(os_args(range.input_args()));
+    args.push("-i".into());
+    args.push(original.into());
+    args.push("-i".into());
+    args.push(ascii_video.into());
+    args.extend(os_args([
+        "-filter_complex",
+        &filter,
+        "-c:v",
+        "libx264",
+        "-preset",
+        "veryfast",
+        "-crf",
+        "18",
+        "-pix_fmt",
+        "yuv420p",
+        "-tune",
+        "stillimage",
+    ]));
+    args.push(output.into());
+    args
+}
+
+/// Columns and rows of the near-square grid `count` tiles are stacked into:
+/// `ceil(sqrt(count))` tiles to a row, filled left to right, top to bottom.
+pub fn tile_grid(count: usize) -> (u32, u32) {
+    let columns = (1..).find(|columns| columns * columns >= count).unwrap_or(1).max(1);
+    (columns as u32, count.div_ceil(columns).max(1) as u32)
+}
 
-    ensure_command_success("ffmpeg", &output_cmd)?;
+/// `xstack` layout placing `count` equally sized tiles on `tile_grid`, with
+/// offsets written in multiples of the first tile's width and height.
+fn xstack_layout(count: usize) -> String {
+    let (columns, _) = tile_grid(count);
+    let offset = |steps: u32, unit: &str| match steps {
+        0 => "0".to_string(),
+        steps => vec![unit; steps as usize].join("+"),
+    };
+    (0..count as u32)
+        .map(|index| format!("{}_{}", offset(index % columns, "w0"), offset(index / columns, "h0")))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Stack the `tiles` videos into one grid video at `output`, keeping the
+/// first tile's audio. Unused cells of a partial last row are black.
+pub fn create_tile_video(ffmpeg: &Path, tiles: &[PathBuf], output: &Path) -> Result<()> {
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
 
-    // Replace the ASCII video with the comparison video
-    fs::rename(&output, ascii_video)?;
+    let output_cmd = run_command(ffmpeg, &tile_args(tiles, output))?;
+    ensure_command_success(ffmpeg, &output_cmd)
+}
 
-    Ok(())
+/// ffmpeg arguments for `create_tile_video`.
+pub fn tile_args(tiles: &[PathBuf], output: &Path) -> Vec<OsString> {
+    let inputs: String = (0..tiles.len()).map(|index| format!("[{index}:v]")).collect();
+    let filter = format!("{inputs}xstack=inputs={}:layout={}:fill=black[v]", tiles.len(), xstack_layout(tiles.len()));
+    let mut args = os_args(["-y", "-v", "error"]);
+    for tile in tiles {
+        args.push("-i".into());
+        args.push(tile.into());
+    }
+    args.extend(os_args([
+        "-filter_complex",
+        &filter,
+        "-map",
+        "[v]",
+        "-map",
+        "0:a?",
+        "-c:v",
+        "libx264",
+        "-preset",
+        "veryfast",
+        "-crf",
+        "18",
+        "-pix_fmt",
+        "yuv420p",
+        "-tune",
+        "stillimage",
+        "-c:a",
+        "copy",
+    ]));
+    args.push(output.into());
+    args
 }
 
 pub fn create_test_video(
+    ffmpeg: &Path,
     output: &Path,
     width: u32,
     height: u32,
@@ -235,7 +1496,7 @@ pub fn create_test_video(
     let rate = fps.to_string();
     let duration = format!("{duration_seconds}");
 
-    let output_cmd = Command::new("ffmpeg")
+    let output_cmd = Command::new(ffmpeg)
         .args(["-y", "-v", "error", "-f", "lavfi", "-i"])
         .arg(format!(
             "testsrc=size={size}:rate={rate}:duration={duration}"
@@ -243,11 +1504,11 @@ pub fn create_test_video(
         .arg(output)
         .output()
         .map_err(|source| AppError::CommandSpawn {
-            program: "ffmpeg".to_string(),
+            program: ffmpeg.display().to_string(),
             source,
         })?;
 
-    ensure_command_success("ffmpeg", &output_cmd)
+    ensure_command_success(ffmpeg, &output_cmd)
 }
 
 fn parse_rational(value: &str) -> Option<f64> {
@@ -264,20 +1525,137 @@ fn parse_rational(value: &str) -> Option<f64> {
     }
 }
 
-fn ensure_command_success(program: &str, output: &std::process::Output) -> Result<()> {
+fn ensure_command_success(program: &Path, output: &std::process::Output) -> Result<()> {
     if output.status.success() {
         return Ok(());
     }
 
     let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
     Err(AppError::CommandFailed {
-        program: program.to_string(),
+        program: program.display().to_string(),
         code: output.status.code(),
         stderr,
     })
 }
 
-fn command_success(program: &str, args: &[&str]) -> bool {
+/// Whether both paths name the same existing file, after resolving symlinks
+/// and relative components. A path that doesn't exist yet never matches.
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+thread_local! {
+    /// Longest a `run_command` on this thread may take, see [`with_command_timeout`]
+    static COMMAND_TIMEOUT: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Run `body` with every ffmpeg and ffprobe command it runs on this thread
+/// (probing, extraction, encoding, comparison and tiling) killed once it has
+/// run for `timeout`, failing with [`AppError::CommandTimeout`]. A
+/// [`FrameStream`] decodes alongside conversion, so it is killed when
+/// `timeout` passes without a new frame instead. `None` waits indefinitely.
+pub fn with_command_timeout<T>(timeout: Option<Duration>, body: impl FnOnce() -> T) -> T {
+    let previous = COMMAND_TIMEOUT.replace(timeout);
+    let result = body();
+    COMMAND_TIMEOUT.set(previous);
+    result
+}
+
+fn run_command(program: &Path, args: &[OsString]) -> Result<std::process::Output> {
+    run_command_with_stdout(program, args, Stdio::piped())
+}
+
+/// `run_command` with the child's stdout going to `stdout`; it is only
+/// captured in the returned output when piped.
+fn run_command_with_stdout(program: &Path, args: &[OsString], stdout: Stdio) -> Result<std::process::Output> {
+    log::debug!("running: {}", shell_command(program, args));
+    let spawn_error = |source| AppError::CommandSpawn {
+        program: program.display().to_string(),
+        source,
+    };
+    let Some(timeout) = COMMAND_TIMEOUT.get() else {
+        return Command::new(program).args(args).stdout(stdout).output().map_err(spawn_error);
+    };
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    // Drain both pipes while waiting, or a chatty ffmpeg blocks on a full one
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = wait_for(&mut child, program, Some((Instant::now() + timeout, timeout)))?;
+    Ok(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// Read `pipe` to the end on its own thread.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Wait for `child` to exit, killing it once `deadline` passes; the `Duration`
+/// is the timeout reported in the error.
+fn wait_for(child: &mut Child, program: &Path, deadline: Option<(Instant, Duration)>) -> Result<ExitStatus> {
+    let Some((deadline, timeout)) = deadline else {
+        return Ok(child.wait()?);
+    };
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(AppError::CommandTimeout {
+                program: program.display().to_string(),
+                seconds: timeout.as_secs_f64(),
+            });
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn os_args<S: AsRef<OsStr>>(args: impl IntoIterator<Item = S>) -> Vec<OsString> {
+    args.into_iter().map(|arg| arg.as_ref().to_os_string()).collect()
+}
+
+/// Render a command line for display, quoting arguments the shell would split
+/// or expand.
+pub fn shell_command(program: &Path, args: &[OsString]) -> String {
+    std::iter::once(program.as_os_str())
+        .chain(args.iter().map(OsString::as_os_str))
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=%,+@".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+fn command_success(program: &Path, args: &[&str]) -> bool {
     Command::new(program)
         .args(args)
         .output()
@@ -289,6 +1667,20 @@ fn command_success(program: &str, args: &[&str]) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn truncated_frame_error_names_the_file() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("frame_00000001.png");
+        RgbImage::new(16, 16).save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+        match open_frame(&path) {
+            Err(AppError::FrameDecode { path: bad, .. }) => assert_eq!(bad, path),
+            other => panic!("expected FrameDecode, got {other:?}"),
+        }
+    }
+
     #[test]
     fn parses_rational_frame_rate() {
         assert_eq!(parse_rational("30000/1001").unwrap().round(), 30.0);
@@ -296,4 +1688,570 @@ mod tests {
         assert!(parse_rational("1/0").is_none());
         assert!(parse_rational("abc").is_none());
     }
+
+    #[test]
+    fn shell_command_quotes_only_when_needed() {
+        let args = os_args(["-i", "my clip.mp4", "-vf", "fps=10", "it's"]);
+        assert_eq!(
+            shell_command(Path::new("ffmpeg"), &args),
+            "ffmpeg -i 'my clip.mp4' -vf fps=10 'it'\\''s'"
+        );
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn encode_args_retry_uses_aac() {
+        let options = EncodeOptions::default();
+        let range = TimeRange::default();
+        let args = |audio| {
+            encode_args(Path::new("f"), Path::new("in.mp4"), &range, 10.0, Path::new("out.mp4"), &options, audio)
+        };
+        let copy = args(AudioMode::Auto);
+        assert!(copy.windows(2).any(|pair| pair == ["-c:a", "copy"]));
+        assert!(args(AudioMode::Aac).windows(2).any(|pair| pair == ["-c:a", "aac"]));
+        assert_eq!(copy.last().map(OsString::as_os_str), Some(OsStr::new("out.mp4")));
+    }
+
+    #[test]
+    fn deterministic_encodes_pin_threads_and_strip_metadata() {
+        let args = |deterministic| {
+            let options = EncodeOptions { deterministic, ..EncodeOptions::default() };
+            encode_args(Path::new("f"), Path::new("in.mp4"), &TimeRange::default(), 10.0, Path::new("out.mp4"), &options, AudioMode::Auto)
+        };
+        let pinned = args(true);
+        for pair in [["-threads", "1"], ["-map_metadata", "-1"], ["-fflags", "+bitexact"], ["-flags:v", "+bitexact"]] {
+            assert!(pinned.windows(2).any(|window| window == pair), "missing {pair:?}");
+        }
+        assert_eq!(pinned.last().map(OsString::as_os_str), Some(OsStr::new("out.mp4")));
+        assert!(!args(false).iter().any(|arg| arg == "-threads"));
+    }
+
+    #[test]
+    fn stdout_output_names_the_muxer_and_writes_to_the_pipe() {
+        let args = |format| {
+            let options = EncodeOptions { format, ..EncodeOptions::default() };
+            encode_args(Path::new("f"), Path::new("in.mp4"), &TimeRange::default(), 10.0, Path::new("-"), &options, AudioMode::Auto)
+        };
+        let mkv = args(OutputFormat::Mkv);
+        assert!(mkv.windows(2).any(|window| window == ["-f", "matroska"]));
+        assert_eq!(mkv.last().map(OsString::as_os_str), Some(OsStr::new("pipe:1")));
+
+        let mp4 = args(OutputFormat::Mp4);
+        assert!(mp4.windows(2).any(|window| window == ["-f", "mp4"]));
+        assert!(mp4.windows(2).any(|window| window == ["-movflags", "frag_keyframe+empty_moov"]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_timeout_kills_a_slow_command() {
+        let limit = Some(Duration::from_millis(50));
+        let started = Instant::now();
+        let slow = with_command_timeout(limit, || run_command(Path::new("sleep"), &os_args(["5"])));
+        assert!(matches!(slow, Err(AppError::CommandTimeout { .. })), "{slow:?}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let quick = with_command_timeout(limit, || run_command(Path::new("echo"), &os_args(["done"]))).expect("run echo");
+        assert_eq!(String::from_utf8_lossy(&quick.stdout).trim(), "done");
+        assert_eq!(COMMAND_TIMEOUT.get(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdout_left_unpiped_is_not_collected() {
+        for timeout in [None, Some(Duration::from_secs(5))] {
+            let output = with_command_timeout(timeout, || {
+                run_command_with_stdout(Path::new("echo"), &os_args(["frame bytes"]), Stdio::null())
+            })
+            .unwrap();
+            assert!(output.status.success());
+            assert!(output.stdout.is_empty(), "{timeout:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn frame_stream_is_killed_when_no_frame_arrives_in_time() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stands in for an ffmpeg that hangs before writing a frame
+        let temp = tempfile::TempDir::new().unwrap();
+        let stalled = temp.path().join("stalled");
+        fs::write(&stalled, "#!/bin/sh\nexec sleep 5\n").unwrap();
+        fs::set_permissions(&stalled, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let started = Instant::now();
+        let limit = Some(Duration::from_millis(100));
+        let mut stream = with_command_timeout(limit, || {
+            stream_frames(&stalled, Path::new("in.mp4"), &TimeRange::default(), None, None, 4, 4)
+        })
+        .unwrap();
+        assert!(matches!(stream.next(), Some(Err(AppError::CommandTimeout { .. }))));
+        assert!(stream.next().is_none());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // A decoder that keeps up isn't affected: two 4x4 frames and a partial one
+        fs::write(&stalled, "#!/bin/sh\nhead -c 100 /dev/zero\n").unwrap();
+        let stream = with_command_timeout(limit, || {
+            stream_frames(&stalled, Path::new("in.mp4"), &TimeRange::default(), None, None, 4, 4)
+        })
+        .unwrap();
+        let frames: Vec<RgbImage> = stream.collect::<Result<_>>().unwrap();
+        assert_eq!(frames.len(), 2);
+    }
+
+    // Regression: an MP4 run used to abort after converting every frame when
+    // the source audio (e.g. PCM in a MOV) couldn't be stream-copied
+    #[cfg(unix)]
+    #[test]
+    fn audio_copy_failure_retries_once_with_aac() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = |code: i32, stderr: &str| std::process::Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        let pcm = "[mp4 @ 0x1] Could not find tag for codec pcm_s16le in stream #1, codec not currently supported in container";
+        let run = |options: &EncodeOptions, first: std::process::Output| {
+            let mut results = vec![output(0, ""), first];
+            let mut calls = Vec::new();
+            let result = encode_with_audio_fallback(options, |audio| {
+                calls.push(audio);
+                Ok(results.pop().expect("unexpected extra encode"))
+            })
+            .unwrap();
+            (calls, result.status.success())
+        };
+        let options = EncodeOptions::default();
+
+        assert_eq!(run(&options, output(1, pcm)), (vec![AudioMode::Auto, AudioMode::Aac], true));
+        assert_eq!(run(&options, output(0, "")), (vec![AudioMode::Auto], true));
+
+        // Unrelated failures and explicit audio modes are not retried
+        assert_eq!(run(&options, output(1, "Unknown encoder 'libx264'")), (vec![AudioMode::Auto], false));
+        let copy = EncodeOptions { audio: AudioMode::Copy, ..EncodeOptions::default() };
+        assert_eq!(run(&copy, output(1, pcm)), (vec![AudioMode::Copy], false));
+    }
+
+    #[test]
+    fn rgb_mp4_uses_rgb_encoder() {
+        let options = EncodeOptions {
+            pix_fmt: PixelFormat::Rgb24,
+            ..EncodeOptions::default()
+        };
+        assert_eq!(options.encoder(), "libx264rgb");
+        let args = encode_args(
+            Path::new("f"),
+            Path::new("in.mp4"),
+            &TimeRange::default(),
+            10.0,
+            Path::new("out.mp4"),
+            &options,
+            AudioMode::Auto,
+        );
+        assert!(args.windows(2).any(|pair| pair == ["-pix_fmt", "rgb24"]));
+
+        let webp = EncodeOptions { format: OutputFormat::Webp, ..options };
+        assert_eq!(webp.encoder(), "libwebp");
+    }
+
+    #[test]
+    fn loop_count_maps_per_container() {
+        assert_eq!(loop_arg(OutputFormat::Webp, 0), "0");
+        assert_eq!(loop_arg(OutputFormat::Gif, 0), "0");
+        assert_eq!(loop_arg(OutputFormat::Webp, 1), "1");
+        assert_eq!(loop_arg(OutputFormat::Gif, 1), "-1");
+        assert_eq!(loop_arg(OutputFormat::Webp, 3), "3");
+        assert_eq!(loop_arg(OutputFormat::Gif, 3), "2");
+        assert_eq!(loop_arg(OutputFormat::Apng, 3), "3");
+    }
+
+    #[test]
+    fn timestamps_are_sorted_and_trimmed_to_range() {
+        let stdout = "0.000000\n0.100000\n0.066667\n0.250000,\nN/A\n0.300000\n";
+        assert_eq!(parse_timestamps(stdout, &TimeRange::default()).len(), 5);
+
+        let range = TimeRange {
+            start: Some(0.05),
+            duration: Some(0.22),
+            ..TimeRange::default()
+        };
+        let kept = parse_timestamps(stdout, &range);
+        let expected = [0.0, 0.1 - 0.066667, 0.25 - 0.066667];
+        assert_eq!(kept.len(), expected.len());
+        for (time, expected) in kept.iter().zip(expected) {
+            assert!((time - expected).abs() < 1e-9, "{kept:?}");
+        }
+    }
+
+    #[test]
+    fn concat_list_follows_source_timing() {
+        let list = concat_list(3, &[0.0, 0.04, 0.2], 25.0);
+        assert_eq!(
+            list,
+            "ffconcat version 1.0\n\
+             file frame_00000000.png\nduration 0.040000\n\
+             file frame_00000001.png\nduration 0.160000\n\
+             file frame_00000002.png\nduration 0.040000\n\
+             file frame_00000002.png\n"
+        );
+
+        // Missing timestamps fall back to the average rate
+        assert!(concat_list(2, &[], 10.0).contains("duration 0.100000"));
+        assert_eq!(concat_list(0, &[], 10.0), "ffconcat version 1.0\n");
+    }
+
+    #[test]
+    fn preserve_timing_encodes_from_concat_list() {
+        let options = EncodeOptions {
+            timing: Timing::Preserve,
+            ..EncodeOptions::default()
+        };
+        let args = encode_args(
+            Path::new("f"),
+            Path::new("in.mp4"),
+            &TimeRange::default(),
+            10.0,
+            Path::new("out.mp4"),
+            &options,
+            AudioMode::Auto,
+        );
+        assert!(args.windows(2).any(|pair| pair == ["-f", "concat"]));
+        assert!(args.windows(2).any(|pair| pair[0] == "-i" && Path::new(&pair[1]) == Path::new("f").join(CONCAT_LIST)));
+        assert!(!args.iter().any(|arg| arg == "-framerate"));
+    }
+
+    #[test]
+    fn label_adds_escaped_drawtext() {
+        let options = EncodeOptions {
+            label: Some("it's 10:30".to_string()),
+            ..EncodeOptions::default()
+        };
+        let range = TimeRange::default();
+        let args = |options: &EncodeOptions, output: &str| {
+            encode_args(Path::new("f"), Path::new("in.mp4"), &range, 10.0, Path::new(output), options, AudioMode::Auto)
+        };
+        let value_of = |args: &[OsString], flag: &str| {
+            args.windows(2).find(|pair| pair[0] == flag).map(|pair| pair[1].to_string_lossy().into_owned())
+        };
+
+        let filter = value_of(&args(&options, "out.mp4"), "-vf").expect("-vf drawtext");
+        assert!(filter.starts_with("drawtext=text=it\\\\\\'s 10\\\\:30:"), "{filter}");
+        assert!(filter.ends_with(":x=16:y=h-th-16"), "{filter}");
+
+        // GIF folds the caption into its palette graph instead of adding -vf
+        let gif = EncodeOptions {
+            format: OutputFormat::Gif,
+            label_position: LabelPosition::TopRight,
+            ..options
+        };
+        let gif_args = args(&gif, "out.gif");
+        assert!(value_of(&gif_args, "-vf").is_none());
+        let graph = value_of(&gif_args, "-filter_complex").expect("palette graph");
+        assert!(graph.starts_with("[0:v]drawtext="), "{graph}");
+        assert!(graph.contains(":x=w-tw-16:y=16,split[a][b]"), "{graph}");
+    }
+
+    #[test]
+    fn parses_filter_list() {
+        let stdout = "Filters:\n  T.. = Timeline support\n  ... = Other\n \
+                      TSC drawtext          V->V       Draw text on top of video frames.\n \
+                      ... split             V->N       Pass on the input to N video outputs.\n";
+        let names = parse_filter_names(stdout);
+        assert_eq!(names, ["drawtext", "split"]);
+        assert!(parse_filter_names("Filters:\n  ... = Other\n").is_empty());
+    }
+
+    #[test]
+    fn ten_bit_widens_the_mp4_pixel_format() {
+        let options = EncodeOptions {
+            bit_depth: BitDepth::Ten,
+            ..EncodeOptions::default()
+        };
+        assert_eq!(options.mp4_pixel_format(), "yuv420p10le");
+        assert!(!options.browser_compatible());
+        assert_eq!(EncodeOptions::default().mp4_pixel_format(), "yuv420p");
+
+        let args = encode_args(
+            Path::new("f"),
+            Path::new("in.mp4"),
+            &TimeRange::default(),
+            10.0,
+            Path::new("out.mp4"),
+            &options,
+            AudioMode::Auto,
+        );
+        assert!(args.windows(2).any(|pair| pair == ["-pix_fmt", "yuv420p10le"]));
+    }
+
+    #[test]
+    fn tiles_fill_a_near_square_grid_row_by_row() {
+        assert_eq!(tile_grid(2), (2, 1));
+        assert_eq!(tile_grid(3), (2, 2));
+        assert_eq!(tile_grid(4), (2, 2));
+        assert_eq!(tile_grid(5), (3, 2));
+        assert_eq!(xstack_layout(3), "0_0|w0_0|0_h0");
+        assert_eq!(xstack_layout(5), "0_0|w0_0|w0+w0_0|0_h0|w0_h0");
+
+        let tiles = [PathBuf::from("a.mp4"), PathBuf::from("b.mp4")];
+        let args = tile_args(&tiles, Path::new("out.mp4"));
+        assert!(args.windows(2).any(|pair| pair == ["-filter_complex", "[0:v][1:v]xstack=inputs=2:layout=0_0|w0_0:fill=black[v]"]));
+        assert_eq!(args.last().unwrap(), "out.mp4");
+    }
+
+    #[test]
+    fn codec_switches_the_mp4_encoder_and_its_flags() {
+        let args = |codec| {
+            let options = EncodeOptions {
+                codec,
+                ..EncodeOptions::default()
+            };
+            let args = encode_args(
+                Path::new("f"),
+                Path::new("in.mp4"),
+                &TimeRange::default(),
+                10.0,
+                Path::new("out.mp4"),
+                &options,
+                AudioMode::Auto,
+            );
+            args.into_iter().map(|arg| arg.to_string_lossy().into_owned()).collect::<Vec<_>>()
+        };
+        let has = |args: &[String], pair: [&str; 2]| args.windows(2).any(|window| window == pair);
+
+        let h264 = args(VideoCodec::X264);
+        assert!(has(&h264, ["-c:v", "libx264"]) && has(&h264, ["-tune", "stillimage"]));
+
+        let hevc = args(VideoCodec::X265);
+        assert!(has(&hevc, ["-c:v", "libx265"]) && has(&hevc, ["-preset", "veryfast"]) && has(&hevc, ["-tag:v", "hvc1"]));
+        assert!(!hevc.iter().any(|arg| arg == "-tune"));
+
+        let av1 = args(VideoCodec::Av1);
+        assert!(has(&av1, ["-c:v", "libsvtav1"]) && has(&av1, ["-preset", "10"]) && has(&av1, ["-crf", "18"]));
+        assert!(!av1.iter().any(|arg| arg == "-tune"));
+
+        let rgb_av1 = EncodeOptions { codec: VideoCodec::Av1, pix_fmt: PixelFormat::Rgb24, ..EncodeOptions::default() };
+        assert_eq!(rgb_av1.encoder(), "libsvtav1");
+    }
+
+    #[test]
+    fn parses_encoder_pixel_formats() {
+        let stdout = "Encoder libx264 [libx264 H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10]:\n    \
+                      General capabilities: dr1 delay threads\n    \
+                      Supported pixel formats: yuv420p yuvj420p yuv422p yuv420p10le yuv444p10le nv12\n\
+                      libx264 AVOptions:\n";
+        let formats = parse_pixel_formats(stdout);
+        assert!(formats.contains(&"yuv420p10le"));
+        assert_eq!(formats.len(), 6);
+        assert!(parse_pixel_formats("Encoder libx264:\n").is_empty());
+    }
+
+    #[test]
+    fn parses_encoder_list() {
+        let stdout = "Encoders:\n V..... = Video\n A..... = Audio\n ------\n \
+                      V....D libx264              libx264 H.264 / AVC\n \
+                      V....D libwebp              libwebp WebP image (codec webp)\n \
+                      A....D aac                  AAC (Advanced Audio Coding)\n";
+        let names = parse_encoder_names(stdout);
+        assert_eq!(names, ["libx264", "libwebp", "aac"]);
+        assert!(!names.contains(&"Video"));
+        assert!(parse_encoder_names("no separator here\n").is_empty());
+    }
+
+    #[test]
+    fn parses_probe_key_values() {
+        let meta = parse_probe_output(
+            "width=640\nheight=360\nr_frame_rate=30000/1001\nduration=2.002000\nnb_frames=60\n",
+        )
+        .unwrap();
+        assert_eq!((meta.width, meta.height), (640, 360));
+        assert_eq!(meta.fps.round(), 30.0);
+        assert_eq!(meta.frame_count, Some(60));
+        assert_eq!(meta.duration, Some(2.002));
+
+        let meta = parse_probe_output("width=64\nheight=48\nr_frame_rate=5/1\nduration=N/A\nnb_frames=N/A\n")
+            .unwrap();
+        assert_eq!(meta.frame_count, None);
+        assert_eq!(meta.duration, None);
+
+        assert!(parse_probe_output("height=48\nr_frame_rate=5/1\n").is_err());
+    }
+
+    #[test]
+    fn parses_rotation_from_tag_or_display_matrix() {
+        let base = "width=1920\nheight=1080\nr_frame_rate=30/1\n";
+        let meta = parse_probe_output(&format!("{base}TAG:rotate=90\n")).unwrap();
+        assert_eq!(meta.rotation, 90);
+        assert_eq!(meta.display_size(), (1080, 1920));
+
+        // Side data reports counterclockwise degrees
+        let meta = parse_probe_output(&format!("{base}side_data_type=Display Matrix\nrotation=-90\n")).unwrap();
+        assert_eq!(meta.rotation, 90);
+        assert_eq!(parse_probe_output(&format!("{base}rotation=90\n")).unwrap().rotation, 270);
+        assert_eq!(parse_probe_output(&format!("{base}TAG:rotate=180\n")).unwrap().display_size(), (1920, 1080));
+
+        let meta = parse_probe_output(base).unwrap();
+        assert_eq!((meta.rotation, meta.display_size()), (0, (1920, 1080)));
+    }
+
+    #[test]
+    fn parses_average_rate_and_detects_vfr() {
+        let meta = parse_probe_output(
+            "width=64\nheight=48\nr_frame_rate=30/1\navg_frame_rate=24000/1001\nnb_frames=N/A\n",
+        )
+        .unwrap();
+        assert_eq!(meta.fps, 30.0);
+        assert!((meta.avg_fps.unwrap() - 23.976).abs() < 0.001);
+        assert!(meta.is_variable_rate());
+        assert_eq!(meta.effective_fps(), meta.avg_fps.unwrap());
+
+        let meta = parse_probe_output("width=64\nheight=48\nr_frame_rate=25/1\navg_frame_rate=25/1\n").unwrap();
+        assert!(!meta.is_variable_rate());
+        assert_eq!(meta.effective_fps(), 25.0);
+
+        let meta = parse_probe_output("width=64\nheight=48\nr_frame_rate=25/1\navg_frame_rate=0/0\n").unwrap();
+        assert_eq!(meta.avg_fps, None);
+        assert_eq!(meta.effective_fps(), 25.0);
+    }
+
+    #[test]
+    fn reads_whole_frames_and_drops_partial_tail() {
+        let data = [1u8, 2, 3, 4, 5, 6, 7];
+        let mut reader = &data[..];
+        let mut buffer = [0u8; 3];
+
+        assert!(read_frame(&mut reader, &mut buffer).unwrap());
+        assert_eq!(buffer, [1, 2, 3]);
+        assert!(read_frame(&mut reader, &mut buffer).unwrap());
+        assert_eq!(buffer, [4, 5, 6]);
+        assert!(!read_frame(&mut reader, &mut buffer).unwrap());
+    }
+
+    #[test]
+    fn time_range_builds_input_seek_args() {
+        assert!(TimeRange::default().input_args().is_empty());
+
+        let range = TimeRange {
+            start: Some(1.5),
+            duration: Some(2.0),
+            ..TimeRange::default()
+        };
+        assert_eq!(range.input_args(), ["-ss", "1.5", "-t", "2"]);
+    }
+
+    #[test]
+    fn accurate_seek_moves_ss_after_input() {
+        let position = |args: &[OsString], arg: &str| args.iter().position(|a| a == arg).unwrap();
+        let fast = TimeRange {
+            start: Some(3.0),
+            ..TimeRange::default()
+        };
+        let accurate = TimeRange { accurate: true, ..fast };
+
+        for range in [fast, accurate] {
+            let stream = stream_args(Path::new("in.mp4"), &range, None, None);
+            let extract = extract_args(Path::new("in.mp4"), &range, None, None, None, Path::new("frames"));
+            for args in [stream, extract] {
+                let seeks_after_input = position(&args, "-ss") > position(&args, "-i");
+                assert_eq!(seeks_after_input, range.accurate, "{args:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn fps_filter_only_when_requested() {
+        assert!(decode_filter_args(None, None).is_empty());
+        assert_eq!(decode_filter_args(None, Some(15.0)), ["-vf", "fps=15.000000"]);
+    }
+
+    #[test]
+    fn crop_filter_runs_before_fps() {
+        let crop = Crop { width: 320, height: 240, x: 10, y: 20 };
+        assert_eq!(decode_filter_args(Some(crop), None), ["-vf", "crop=320:240:10:20"]);
+        assert_eq!(decode_filter_args(Some(crop), Some(12.0)), ["-vf", "crop=320:240:10:20,fps=12.000000"]);
+    }
+
+    #[test]
+    fn parses_crop_specs() {
+        let crop = Crop { width: 640, height: 360, x: 0, y: 60 };
+        assert_eq!("640:360:0:60".parse::<Crop>(), Ok(crop));
+        assert_eq!("640x360+0+60".parse::<Crop>(), Ok(crop));
+        assert!("640:360:0".parse::<Crop>().is_err());
+        assert!("0:360:0:60".parse::<Crop>().is_err());
+        assert!("640:360:-1:60".parse::<Crop>().is_err());
+    }
+
+    #[test]
+    fn rejects_crop_outside_the_frame() {
+        let crop = Crop { width: 640, height: 360, x: 0, y: 60 };
+        assert!(crop.check_fits(640, 480).is_ok());
+        assert!(crop.check_fits(640, 400).is_err());
+        assert!(Crop { x: 1, ..crop }.check_fits(640, 480).is_err());
+        assert!(Crop { x: u32::MAX, ..crop }.check_fits(640, 480).is_err());
+    }
+
+    #[test]
+    fn comparison_filter_matches_layout() {
+        assert_eq!(
+            comparison_filter(CompareLayout::Vertical, (70, 60), (64, 56)),
+            "[0:v]pad=70:ih[a];[1:v]pad=70:ih[b];[a][b]vstack"
+        );
+        assert_eq!(
+            comparison_filter(CompareLayout::Horizontal, (70, 60), (64, 56)),
+            "[0:v]pad=iw:60[a];[1:v]pad=iw:60[b];[a][b]hstack"
+        );
+    }
+
+    #[test]
+    fn validates_x264_presets() {
+        assert!(validate_preset("veryfast").is_ok());
+        assert!(validate_preset("placebo").is_ok());
+        assert!(matches!(
+            validate_preset("turbo"),
+            Err(AppError::InvalidPreset(name)) if name == "turbo"
+        ));
+    }
+
+    #[test]
+    fn detects_url_inputs() {
+        assert!(is_url(Path::new("https://example.com/clip.mp4")));
+        assert!(is_url(Path::new("HTTP://example.com/clip")));
+        assert!(!is_url(Path::new("clip.mp4")));
+        assert!(!is_url(Path::new("ftp://example.com/clip.mp4")));
+        assert!(!is_image_path(Path::new("https://example.com/photo.png")));
+    }
+
+    #[test]
+    fn detects_image_inputs() {
+        assert!(is_image_path(Path::new("photo.JPG")));
+        assert!(is_image_path(Path::new("dir/frame.png")));
+        assert!(!is_image_path(Path::new("clip.mp4")));
+        assert!(!is_image_path(Path::new("noext")));
+        assert!(!is_image_path(Path::new("anim.gif")));
+        assert!(is_gif_path(Path::new("dir/anim.GIF")));
+        assert!(!is_gif_path(Path::new("https://example.com/anim.gif")));
+    }
+
+    #[test]
+    fn flags_extensions_that_are_not_media() {
+        assert_eq!(unsupported_extension(Path::new("notes.TXT")), Some("txt".to_string()));
+        assert_eq!(unsupported_extension(Path::new("clips.zip")), Some("zip".to_string()));
+        assert_eq!(unsupported_extension(Path::new("clip.MOV")), None);
+        assert_eq!(unsupported_extension(Path::new("still.jpeg")), None);
+        assert_eq!(unsupported_extension(Path::new("anim.gif")), None);
+        assert_eq!(unsupported_extension(Path::new("recording")), None);
+        assert_eq!(unsupported_extension(Path::new("https://example.com/watch.php")), None);
+    }
+
+    #[test]
+    fn infers_output_format_from_extension() {
+        assert_eq!(OutputFormat::from_path(Path::new("out.gif")), Some(OutputFormat::Gif));
+        assert_eq!(OutputFormat::from_path(Path::new("out.WEBP")), Some(OutputFormat::Webp));
+        assert_eq!(OutputFormat::from_path(Path::new("out.mp4")), Some(OutputFormat::Mp4));
+        assert_eq!(OutputFormat::from_path(Path::new("out.webm")), Some(OutputFormat::Webm));
+        assert_eq!(OutputFormat::from_path(Path::new("out.apng")), Some(OutputFormat::Apng));
+        assert_eq!(OutputFormat::from_path(Path::new("out.mkv")), Some(OutputFormat::Mkv));
+        assert_eq!(OutputFormat::from_path(Path::new("out.mov")), None);
+        assert_eq!(OutputFormat::from_path(Path::new("out")), None);
+    }
 }
@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Directory holding extracted and converted frames for one run. A persistent
+/// work dir lets an interrupted run resume; `manifest.json` records which
+/// settings produced the cached frames so stale ones are discarded.
+#[derive(Debug)]
+pub struct WorkDir {
+    root: PathBuf,
+    manifest: Manifest,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Settings that determine the extracted frames
+    extraction: String,
+    /// Whether extraction finished, so partially extracted frames aren't reused
+    extracted: bool,
+    /// Settings that determine the converted ASCII frames
+    render: String,
+}
+
+impl WorkDir {
+    /// Open (or create) `root`. With `clean`, anything already there is removed.
+    /// Cached frames are dropped when `extraction_key` differs from the last run.
+    pub fn open(root: &Path, extraction_key: String, clean: bool) -> Result<Self> {
+        if clean && root.exists() {
+            fs::remove_dir_all(root)?;
+        }
+        fs::create_dir_all(root)?;
+
+        let manifest_path = root.join(MANIFEST_FILE);
+        let manifest = match fs::read_to_string(&manifest_path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+            Err(_) => Manifest::default(),
+        };
+
+        let mut work_dir = Self {
+            root: root.to_path_buf(),
+            manifest,
+        };
+
+        if work_dir.manifest.extraction != extraction_key {
+            remove_dir_if_exists(&work_dir.extracted_dir())?;
+            remove_dir_if_exists(&work_dir.ascii_dir())?;
+            work_dir.manifest = Manifest {
+                extraction: extraction_key,
+                ..Manifest::default()
+            };
+            work_dir.save()?;
+        }
+
+        fs::create_dir_all(work_dir.extracted_dir())?;
+        fs::create_dir_all(work_dir.ascii_dir())?;
+        Ok(work_dir)
+    }
+
+    /// Refer to `root` without touching the filesystem, for reporting the
+    /// paths a run would use.
+    pub fn unopened(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            manifest: Manifest::default(),
+        }
+    }
+
+    pub fn extracted_dir(&self) -> PathBuf {
+        self.root.join("extracted")
+    }
+
+    pub fn ascii_dir(&self) -> PathBuf {
+        self.root.join("ascii")
+    }
+
+    /// Flattened copies of the converted frames for an opaque companion MP4.
+    /// Kept inside `ascii_dir` so they are discarded along with it.
+    pub fn opaque_dir(&self) -> PathBuf {
+        self.ascii_dir().join("opaque")
+    }
+
+    pub fn frames_extracted(&self) -> bool {
+        self.manifest.extracted
+    }
+
+    pub fn mark_extracted(&mut self) -> Result<()> {
+        self.manifest.extracted = true;
+        self.save()
+    }
+
+    /// Record the render settings, discarding converted frames if they changed.
+    pub fn set_render_key(&mut self, render_key: String) -> Result<()> {
+        if self.manifest.render == render_key {
+            return Ok(());
+        }
+
+        remove_dir_if_exists(&self.ascii_dir())?;
+        fs::create_dir_all(self.ascii_dir())?;
+        self.manifest.render = render_key;
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.manifest)
+            .map_err(|err| AppError::Io(std::io::Error::other(err)))?;
+        fs::write(self.root.join(MANIFEST_FILE), json)?;
+        Ok(())
+    }
+}
+
+fn remove_dir_if_exists(dir: &Path) -> Result<()> {
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn keeps_frames_when_keys_match() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("work");
+
+        let mut work = WorkDir::open(&root, "extract-a".to_string(), false).unwrap();
+        work.mark_extracted().unwrap();
+        work.set_render_key("render-a".to_string()).unwrap();
+        fs::write(work.ascii_dir().join("frame_00000000.png"), b"x").unwrap();
+
+        let mut work = WorkDir::open(&root, "extract-a".to_string(), false).unwrap();
+        assert!(work.frames_extracted());
+        work.set_render_key("render-a".to_string()).unwrap();
+        assert!(work.ascii_dir().join("frame_00000000.png").exists());
+    }
+
+    #[test]
+    fn discards_stale_frames() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("work");
+
+        let mut work = WorkDir::open(&root, "extract-a".to_string(), false).unwrap();
+        work.mark_extracted().unwrap();
+        work.set_render_key("render-a".to_string()).unwrap();
+        fs::write(work.ascii_dir().join("frame_00000000.png"), b"x").unwrap();
+
+        // Render settings changed: converted frames go, extracted ones stay
+        let mut work = WorkDir::open(&root, "extract-a".to_string(), false).unwrap();
+        work.set_render_key("render-b".to_string()).unwrap();
+        assert!(work.frames_extracted());
+        assert!(!work.ascii_dir().join("frame_00000000.png").exists());
+
+        // Extraction settings changed: everything starts over
+        let work = WorkDir::open(&root, "extract-b".to_string(), false).unwrap();
+        assert!(!work.frames_extracted());
+
+        // --clean always starts over
+        let mut work = WorkDir::open(&root, "extract-b".to_string(), false).unwrap();
+        work.mark_extracted().unwrap();
+        let work = WorkDir::open(&root, "extract-b".to_string(), true).unwrap();
+        assert!(!work.frames_extracted());
+    }
+}
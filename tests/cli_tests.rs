@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::process::Command;
+
+use image::{GrayImage, Luma};
+use tempfile::TempDir;
+
+use video_ascii_cli::video;
+
+fn cli() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_video-ascii-cli"))
+}
+
+#[test]
+fn quiet_run_prints_nothing() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    GrayImage::from_pixel(16, 16, Luma([60])).save(&input).expect("write input image");
+
+    // A one-character charset would normally warn
+    let output = cli().arg(&input).args(["--charset", "@", "--quiet"]).output().expect("run cli");
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty(), "stdout: {}", String::from_utf8_lossy(&output.stdout));
+    assert!(output.stderr.is_empty(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(temp.path().join("input_ascii.png").exists());
+}
+
+#[test]
+fn bench_reports_frames_per_second() {
+    let output = cli()
+        .args(["--columns", "40", "bench", "--iterations", "10", "--width", "64", "--height", "48"])
+        .output()
+        .expect("run cli");
+
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let fps = stdout
+        .split_once(": ")
+        .and_then(|(_, rest)| rest.split_whitespace().next())
+        .and_then(|value| value.parse::<f64>().ok());
+    assert!(fps.is_some_and(|fps| fps > 0.0), "stdout: {stdout}");
+}
+
+#[test]
+fn info_prints_the_grid_as_json() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    GrayImage::from_pixel(40, 24, Luma([60])).save(&input).expect("write input image");
+
+    let output = cli().arg(&input).args(["--info", "--stats-json", "--font-scale", "2"]).output().expect("run cli");
+
+    assert!(output.status.success(), "{output:?}");
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse json");
+    assert_eq!(info["grid"], serde_json::json!({ "columns": 5, "rows": 3 }));
+    assert_eq!(info["output_resolution"], serde_json::json!({ "width": 80, "height": 48 }));
+    assert!(info["fps"].is_null());
+    assert!(!temp.path().join("input_ascii.png").exists());
+}
+
+#[test]
+fn quiet_failure_prints_only_the_error() {
+    let temp = TempDir::new().expect("temp dir");
+    let output = cli().arg(temp.path().join("missing.png")).arg("--quiet").output().expect("run cli");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.lines().count(), 1, "stderr: {stderr}");
+    assert!(stderr.starts_with("error: "), "stderr: {stderr}");
+}
+
+#[test]
+fn dash_output_streams_the_video_to_stdout() {
+    if !video::tools_available(Path::new(video::DEFAULT_FFMPEG), Path::new(video::DEFAULT_FFPROBE)) {
+        eprintln!("Skipping ffmpeg-dependent test: ffmpeg/ffprobe not available.");
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(Path::new(video::DEFAULT_FFMPEG), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let output = cli().arg(&input).args(["-o", "-", "--columns", "8"]).current_dir(temp.path()).output().expect("run cli");
+
+    assert!(output.status.success(), "{output:?}");
+    // Matroska files open with the EBML magic number
+    assert!(output.stdout.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]), "stdout: {} bytes", output.stdout.len());
+    assert!(!temp.path().join("-").exists());
+}
+
+#[test]
+fn text_ansi_prints_colored_text_to_stdout() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    GrayImage::from_pixel(16, 8, Luma([60])).save(&input).expect("write input image");
+
+    let output = cli().arg(&input).args(["--text-ansi", "-o", "-"]).current_dir(temp.path()).output().expect("run cli");
+
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("\x1b[38;2;60;60;60m"), "stdout: {stdout:?}");
+    assert_eq!(stdout.lines().count(), 1, "stdout: {stdout:?}");
+    assert!(!temp.path().join("-").exists());
+}
@@ -1,12 +1,28 @@
-use image::{GrayImage, Luma};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use image::{GrayImage, Luma, Rgb, RgbImage};
 use tempfile::TempDir;
 
-use video_ascii_cli::ascii::{AsciiOptions, convert_frame_to_ascii};
-use video_ascii_cli::pipeline::{PipelineConfig, run};
-use video_ascii_cli::video;
+use video_ascii_cli::ascii::{ANSI16, AsciiOptions, LumaMethod, Palette, RenderMode, convert_frame_to_ascii};
+use video_ascii_cli::error::AppError;
+use video_ascii_cli::pipeline::{
+    Flip, PipelineConfig, PipelineProgress, Resolution, Stage, info, plan, run, run_concat, run_tiled, run_with_progress,
+};
+use video_ascii_cli::sidecar::{sidecar_path, write_sidecar};
+use video_ascii_cli::video::{self, BitDepth, CompareLayout, Crop, EncodeOptions, OutputFormat, TimeRange, Timing, VideoCodec};
+
+fn ffmpeg() -> &'static Path {
+    Path::new(video::DEFAULT_FFMPEG)
+}
+
+fn ffprobe() -> &'static Path {
+    Path::new(video::DEFAULT_FFPROBE)
+}
 
 fn skip_if_no_ffmpeg() -> bool {
-    if !video::tools_available() {
+    if !video::tools_available(ffmpeg(), ffprobe()) {
         eprintln!("Skipping ffmpeg-dependent test: ffmpeg/ffprobe not available.");
         true
     } else {
@@ -14,6 +30,10 @@ fn skip_if_no_ffmpeg() -> bool {
     }
 }
 
+fn base_config(input: &Path, output: &Path) -> PipelineConfig {
+    PipelineConfig::builder(input, output).columns(20).build()
+}
+
 #[test]
 fn video_loading_reads_metadata() {
     if skip_if_no_ffmpeg() {
@@ -23,8 +43,8 @@ fn video_loading_reads_metadata() {
     let temp = TempDir::new().expect("temp dir");
     let video_path = temp.path().join("input.mp4");
 
-    video::create_test_video(&video_path, 64, 48, 5, 1.0).expect("create test video");
-    let meta = video::probe_video(&video_path).expect("probe metadata");
+    video::create_test_video(ffmpeg(), &video_path, 64, 48, 5, 1.0).expect("create test video");
+    let meta = video::probe_video(ffprobe(), &video_path).expect("probe metadata");
 
     assert_eq!(meta.width, 64);
     assert_eq!(meta.height, 48);
@@ -50,6 +70,484 @@ fn ascii_conversion_outputs_black_and_white_pixels() {
     assert!(converted.pixels().all(|p| p[0] == 0 || p[0] == 255));
 }
 
+#[test]
+fn image_input_converts_without_ffmpeg() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    let output = temp.path().join("input_ascii.png");
+
+    GrayImage::from_pixel(40, 24, Luma([30])).save(&input).expect("write input image");
+
+    let config = PipelineConfig {
+        ffmpeg: PathBuf::from("/nonexistent/ffmpeg"),
+        ffprobe: PathBuf::from("/nonexistent/ffprobe"),
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("run pipeline");
+
+    assert_eq!(stats.frames_processed, 1);
+    assert_eq!((stats.input_resolution.width, stats.input_resolution.height), (40, 24));
+
+    let json = serde_json::to_value(&stats).expect("serialize stats");
+    assert_eq!(json["frames_processed"], 1);
+    assert_eq!(json["output_resolution"]["width"], 40);
+    assert!(json["elapsed_seconds"].as_f64().is_some());
+    let converted = image::open(&output).expect("open output").to_luma8();
+    assert_eq!((converted.width(), converted.height()), (40, 24));
+}
+
+#[test]
+fn fit_sizes_a_wide_source_to_the_target_canvas() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("wide.png");
+    GrayImage::from_pixel(320, 180, Luma([60])).save(&input).expect("write input image");
+
+    let fit = Some(Resolution { width: 1280, height: 720 });
+    for (font_scale, cell_padding) in [(1, 0), (2, 0), (1, 3)] {
+        let config = PipelineConfig { fit, font_scale, cell_padding, ..base_config(&input, &temp.path().join("wide_ascii.png")) };
+        let stats = run(&config).expect("run pipeline");
+        let Resolution { width, height } = stats.output_resolution;
+        // Within one output cell of 1280x720
+        let cell = 8 * font_scale + cell_padding;
+        assert!(width.abs_diff(1280) <= cell && height.abs_diff(720) <= cell, "{width}x{height}");
+    }
+}
+
+#[test]
+fn text_ansi_writes_an_image_as_colored_text() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    let output = temp.path().join("input_ascii.txt");
+    // Red on the left two cells, blue on the right one, two rows of cells
+    RgbImage::from_fn(24, 16, |x, _| if x < 16 { Rgb([200, 0, 0]) } else { Rgb([0, 0, 200]) })
+        .save(&input)
+        .expect("write input image");
+
+    let config = PipelineConfig {
+        text_ansi: true,
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("write text");
+    assert_eq!(stats.frames_processed, 1);
+    assert_eq!(stats.output_resolution, Resolution { width: 3, height: 2 });
+
+    let text = std::fs::read_to_string(&output).expect("read text");
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        // One escape per color run, not per cell
+        assert!(line.starts_with("\x1b[38;2;200;0;0m"), "{line:?}");
+        assert_eq!(line.matches("\x1b[38;2;").count(), 2, "{line:?}");
+        assert!(line.contains("\x1b[38;2;0;0;200m"), "{line:?}");
+    }
+    // A still image isn't drawn over the terminal's existing contents
+    assert!(!text.contains("\x1b[H"));
+}
+
+#[test]
+fn text_ansi_redraws_each_video_frame_in_place() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("input_ascii.txt");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        text_ansi: true,
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("write text");
+
+    let text = std::fs::read_to_string(&output).expect("read text");
+    assert!(text.starts_with("\x1b[2J\x1b[H"));
+    assert_eq!(text.matches("\x1b[H").count(), stats.frames_processed);
+    assert!(stats.frames_processed >= 4);
+    assert_eq!(stats.output_resolution, Resolution { width: 8, height: 6 });
+}
+
+#[test]
+fn odd_sized_image_keeps_its_exact_size_in_every_mode() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("odd.png");
+    // Neither side is a multiple of the 8px cell
+    GrayImage::from_fn(53, 29, |x, y| Luma([((x + y) * 4) as u8])).save(&input).expect("write input image");
+
+    for mode in [RenderMode::Brightness, RenderMode::Edges, RenderMode::Halfblock, RenderMode::Blocks] {
+        let output = temp.path().join(format!("odd_{mode:?}.png"));
+        let stats = run(&PipelineConfig { mode, ..base_config(&input, &output) }).expect("run pipeline");
+        assert_eq!((stats.output_resolution.width, stats.output_resolution.height), (53, 29), "{mode:?}");
+        assert_eq!(image::image_dimensions(&output).expect("read output"), (53, 29), "{mode:?}");
+    }
+}
+
+#[test]
+fn sidecar_records_input_hash_and_stats() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    let output = temp.path().join("input_ascii.png");
+    GrayImage::from_pixel(16, 16, Luma([90])).save(&input).expect("write input image");
+
+    let config = base_config(&input, &output);
+    let stats = run(&config).expect("run pipeline");
+    let path = write_sidecar(&config, &stats).expect("write sidecar");
+    assert_eq!(path, sidecar_path(&output));
+
+    let json: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).expect("read sidecar")).expect("parse sidecar");
+    assert_eq!(json["input_sha256"].as_str().map(str::len), Some(64));
+    assert_eq!(json["frames_processed"], 1);
+    assert_eq!(json["shades"], config.shades);
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn plan_for_image_writes_nothing() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    let output = temp.path().join("out/input_ascii.png");
+    GrayImage::from_pixel(40, 24, Luma([30])).save(&input).expect("write input image");
+
+    let config = PipelineConfig {
+        font_scale: 2,
+        ..base_config(&input, &output)
+    };
+    let plan = plan(&config).expect("plan");
+
+    assert_eq!(plan.expected_frames, Some(1));
+    assert_eq!((plan.output_resolution.width, plan.output_resolution.height), (80, 48));
+    assert!(plan.commands.is_empty());
+    assert!(!output.parent().unwrap().exists());
+}
+
+#[test]
+fn cell_padding_grows_the_image_output_as_planned() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    let output = temp.path().join("input_ascii.png");
+    GrayImage::from_pixel(40, 20, Luma([30])).save(&input).expect("write input image");
+
+    let config = PipelineConfig {
+        font_scale: 2,
+        cell_padding: 1,
+        ..base_config(&input, &output)
+    };
+    let planned = plan(&config).expect("plan").output_resolution;
+    let stats = run(&config).expect("run pipeline");
+
+    // 5 columns and 3 rows (the last one partial), each cell followed by a 1px gutter
+    assert_eq!((planned.width, planned.height), (85, 43));
+    assert_eq!(stats.output_resolution, planned);
+    assert_eq!(image::image_dimensions(&output).expect("output size"), (85, 43));
+}
+
+#[test]
+fn plan_for_video_lists_commands_without_creating_files() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let work_dir = temp.path().join("work");
+    let output = temp.path().join("output.mp4");
+    let config = PipelineConfig {
+        work_dir: Some(work_dir.clone()),
+        ..base_config(&input, &output)
+    };
+    let plan = plan(&config).expect("plan");
+
+    assert_eq!(plan.expected_frames, Some(5));
+    assert_eq!(plan.commands.len(), 2);
+    assert!(plan.commands[1].contains("libx264"));
+    assert!(!work_dir.exists());
+    assert!(!output.exists());
+}
+
+#[test]
+fn info_reports_the_probed_video_and_its_grid() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+    let meta = video::probe_video(ffprobe(), &input).expect("probe metadata");
+
+    let info = info(&base_config(&input, &temp.path().join("output.mp4"))).expect("info");
+
+    assert_eq!((info.input_resolution.width, info.input_resolution.height), (meta.width, meta.height));
+    assert_eq!(info.fps, Some(meta.effective_fps()));
+    assert_eq!(info.duration_seconds, meta.duration);
+    assert_eq!((info.grid.columns, info.grid.rows), (8, 6));
+    assert!(!temp.path().join("output.mp4").exists());
+}
+
+#[test]
+fn bg_per_frame_keys_rendered_background() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("dark.png");
+    GrayImage::from_pixel(32, 32, Luma([30])).save(&input).expect("write input image");
+
+    let transparent_pixels = |bg_per_frame: bool, name: &str| {
+        let output = temp.path().join(name);
+        let config = PipelineConfig {
+            transparent: true,
+            bg_per_frame,
+            ..base_config(&input, &output)
+        };
+        run(&config).expect("run pipeline");
+        let rgba = image::open(&output).expect("open output").to_rgba8();
+        rgba.pixels().filter(|p| p[3] == 0).count()
+    };
+
+    // The source gray (30) never appears in the black-and-white rendering, so
+    // only keying on the rendered frame removes anything
+    assert_eq!(transparent_pixels(false, "global.png"), 0);
+    assert!(transparent_pixels(true, "per_frame.png") > 0);
+}
+
+#[test]
+fn rows_sizes_output_by_character_rows() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("wide.png");
+    let output = temp.path().join("wide_ascii.png");
+
+    // 16:9 frame
+    GrayImage::from_pixel(320, 180, Luma([128])).save(&input).expect("write input image");
+
+    let config = PipelineConfig {
+        rows: Some(30),
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("run pipeline");
+
+    assert_eq!(stats.output_resolution.height, 30 * 8);
+    assert_eq!(stats.output_resolution.width, 320 * 240 / 180);
+}
+
+#[test]
+fn crop_converts_only_the_region_and_rejects_out_of_bounds() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.png");
+    let output = temp.path().join("input_ascii.png");
+    GrayImage::from_pixel(40, 24, Luma([30])).save(&input).expect("write input image");
+
+    let crop = Crop { width: 32, height: 16, x: 8, y: 8 };
+    let stats = run(&PipelineConfig {
+        crop: Some(crop),
+        ..base_config(&input, &output)
+    })
+    .expect("run pipeline");
+    assert_eq!((stats.input_resolution.width, stats.input_resolution.height), (32, 16));
+
+    let config = PipelineConfig {
+        crop: Some(Crop { y: 9, ..crop }),
+        ..base_config(&input, &output)
+    };
+    assert!(matches!(run(&config), Err(AppError::CropOutOfBounds { .. })));
+}
+
+#[test]
+fn output_equal_to_input_is_rejected() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("clip.png");
+    GrayImage::from_pixel(16, 16, Luma([128])).save(&input).expect("write input image");
+
+    // Same file through a different spelling of the path
+    let output = temp.path().join(".").join("clip.png");
+    let err = run(&base_config(&input, &output)).expect_err("must refuse to overwrite input");
+    assert!(matches!(err, AppError::OutputEqualsInput(_)));
+
+    let config = PipelineConfig {
+        compare_output: Some(input.clone()),
+        ..base_config(&input, &temp.path().join("clip_ascii.png"))
+    };
+    assert!(matches!(run(&config), Err(AppError::OutputEqualsInput(_))));
+    assert!(image::open(&input).is_ok(), "input must be left intact");
+}
+
+#[test]
+fn text_input_is_rejected_by_extension() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("notes.txt");
+    std::fs::write(&input, "not a video").expect("write input");
+
+    let config = base_config(&input, &temp.path().join("notes_ascii.mp4"));
+    match run(&config) {
+        Err(AppError::UnsupportedInput { ext }) => assert_eq!(ext, "txt"),
+        other => panic!("expected UnsupportedInput, got {other:?}"),
+    }
+    assert!(matches!(info(&config), Err(AppError::UnsupportedInput { .. })));
+
+    // --force hands the file on, so it fails later for another reason
+    let forced = PipelineConfig { force: true, ..config };
+    assert!(!matches!(run(&forced), Err(AppError::UnsupportedInput { .. })));
+}
+
+#[test]
+fn gif_input_round_trips_to_gif_with_its_duration() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.gif");
+    let output = temp.path().join("input_ascii.gif");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test gif");
+
+    let config = PipelineConfig {
+        encode: EncodeOptions {
+            format: OutputFormat::Gif,
+            timing: Timing::Preserve,
+            ..EncodeOptions::default()
+        },
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("run pipeline");
+    assert_eq!(stats.frames_processed, 5);
+
+    // Five frames 0.2 s apart, like the source's delays
+    let timestamps = video::probe_frame_timestamps(ffprobe(), &output, &TimeRange::default()).expect("probe output");
+    assert_eq!(timestamps.len(), 5);
+    assert!((timestamps[4] - 0.8).abs() < 0.05, "{timestamps:?}");
+}
+
+#[test]
+fn audio_copy_writes_an_opaque_mp4_beside_the_transparent_output() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("input_ascii.webp");
+    let audio_copy = temp.path().join("input_ascii.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        encode: EncodeOptions {
+            format: OutputFormat::Webp,
+            ..EncodeOptions::default()
+        },
+        transparent: true,
+        audio_copy_output: Some(audio_copy.clone()),
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("run pipeline");
+
+    assert!(output.exists(), "transparent output");
+    assert_eq!(stats.audio_copy_output.as_deref(), Some(audio_copy.as_path()));
+    let metadata = video::probe_video(ffprobe(), &audio_copy).expect("probe audio copy");
+    assert_eq!(
+        (metadata.width, metadata.height),
+        (stats.output_resolution.width, stats.output_resolution.height)
+    );
+}
+
+#[test]
+fn comparison_pads_inputs_of_different_sizes() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let original = temp.path().join("original.mp4");
+    let ascii = temp.path().join("ascii.mp4");
+    video::create_test_video(ffmpeg(), &original, 72, 48, 5, 1.0).expect("create original");
+    video::create_test_video(ffmpeg(), &ascii, 64, 40, 5, 1.0).expect("create ascii");
+
+    for (layout, size) in [(CompareLayout::Vertical, (72, 88)), (CompareLayout::Horizontal, (136, 48))] {
+        let output = temp.path().join(format!("compare_{layout:?}.mp4"));
+        video::create_comparison_video(
+            ffmpeg(),
+            &original,
+            &ascii,
+            &TimeRange::default(),
+            &output,
+            layout,
+            ((72, 48), (64, 40)),
+        )
+        .expect("stack mismatched inputs");
+
+        let metadata = video::probe_video(ffprobe(), &output).expect("probe comparison");
+        assert_eq!((metadata.width, metadata.height), size);
+    }
+}
+
+#[test]
+fn max_width_caps_output_resolution() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("wide.png");
+    let output = temp.path().join("wide_ascii.png");
+
+    GrayImage::from_pixel(3840, 64, Luma([128])).save(&input).expect("write input image");
+
+    let config = PipelineConfig {
+        max_width: Some(1280),
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("run pipeline");
+
+    assert!(stats.output_resolution.width <= 1280);
+    let converted = image::open(&output).expect("open output");
+    assert_eq!(converted.width(), 1280);
+    assert_eq!(converted.height(), 21, "aspect ratio kept");
+}
+
+#[test]
+fn horizontal_flip_moves_left_edge_to_the_right() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("edge.png");
+    let output = temp.path().join("edge_ascii.png");
+
+    // Black band down the left edge, white everywhere else
+    GrayImage::from_fn(64, 16, |x, _| Luma([if x < 16 { 0 } else { 255 }])).save(&input).expect("write input image");
+
+    let ink_in = |converted: &GrayImage, columns: std::ops::Range<u32>| {
+        converted.enumerate_pixels().filter(|(x, _, pixel)| columns.contains(x) && pixel[0] < 128).count()
+    };
+    let config = base_config(&input, &output);
+    run(&config).expect("run pipeline");
+    let plain = image::open(&output).expect("open output").to_luma8();
+    assert!(ink_in(&plain, 0..16) > 0 && ink_in(&plain, 48..64) == 0);
+
+    run(&PipelineConfig {
+        flip: Flip::Horizontal,
+        ..config
+    })
+    .expect("run flipped");
+    let flipped = image::open(&output).expect("open output").to_luma8();
+    assert_eq!(ink_in(&flipped, 0..16), 0);
+    assert_eq!(ink_in(&flipped, 48..64), ink_in(&plain, 0..16));
+}
+
+#[test]
+fn palette_limits_halfblock_image_colors() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("gradient.png");
+    let output = temp.path().join("gradient_ascii.png");
+    RgbImage::from_fn(128, 64, |x, y| Rgb([(x * 2) as u8, (y * 4) as u8, 128])).save(&input).expect("write input image");
+
+    let colors = |palette| {
+        run(&PipelineConfig {
+            mode: RenderMode::Halfblock,
+            palette,
+            ..base_config(&input, &output)
+        })
+        .expect("run pipeline");
+        let converted = image::open(&output).expect("open output").to_rgb8();
+        converted.pixels().copied().collect::<std::collections::HashSet<_>>()
+    };
+    assert!(colors(None).len() > 8);
+    assert!(colors(Some(Palette::MedianCut(8))).len() <= 8);
+    assert!(colors(Some(Palette::Ansi16)).iter().all(|color| ANSI16.contains(color)));
+}
+
 #[test]
 fn output_generation_creates_ascii_video_file() {
     if skip_if_no_ffmpeg() {
@@ -60,19 +558,11 @@ fn output_generation_creates_ascii_video_file() {
     let input = temp.path().join("input.mp4");
     let output = temp.path().join("output_ascii.mp4");
 
-    video::create_test_video(&input, 80, 60, 6, 1.0).expect("create test video");
+    video::create_test_video(ffmpeg(), &input, 80, 60, 6, 1.0).expect("create test video");
 
     let config = PipelineConfig {
-        input: input.clone(),
-        output: output.clone(),
-        columns: 20,
         fps: Some(6.0),
-        charset: "@%#*+=-:. ".to_string(),
-        shades: 1,
-        transparent: false,
-        bg_color: None,
-        threshold: 0,
-        compare: false,
+        ..base_config(&input, &output)
     };
 
     let stats = run(&config).expect("run pipeline");
@@ -80,10 +570,695 @@ fn output_generation_creates_ascii_video_file() {
     assert!(output.exists());
     assert!(stats.frames_processed > 0);
     assert!((stats.output_fps - 6.0).abs() < 0.01);
+    assert!((stats.duration_seconds - stats.frames_processed as f64 / 6.0).abs() < 1e-9);
+    assert_eq!((stats.output_resolution.width, stats.output_resolution.height), (80, 60));
 
-    let output_meta = video::probe_video(&output).expect("probe output video");
-    // Input 80x60 → 10 columns x 7 rows (80/8, 60/8 rounded down)
-    // Output: 10*8 x 7*8 = 80 x 56
+    let output_meta = video::probe_video(ffprobe(), &output).expect("probe output video");
+    // Input 80x60 → 10 columns x 8 rows, the last row a partial cell
+    // Output keeps the input size: 80 x 60
     assert_eq!(output_meta.width, 80);
-    assert_eq!(output_meta.height, 56);
+    assert_eq!(output_meta.height, 60);
+}
+
+#[test]
+fn disk_frames_mode_matches_streamed_frame_count() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let streamed = run(&base_config(&input, &temp.path().join("streamed.mp4"))).expect("streamed run");
+    let disk = run(&PipelineConfig {
+        disk_frames: true,
+        ..base_config(&input, &temp.path().join("disk.mp4"))
+    })
+    .expect("disk run");
+
+    assert_eq!(streamed.frames_processed, disk.frames_processed);
+}
+
+#[test]
+fn progress_hook_reports_each_stage() {
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("still.png");
+    GrayImage::from_pixel(16, 16, Luma([40])).save(&input).expect("write input image");
+
+    let mut updates = Vec::new();
+    run_with_progress(&base_config(&input, &temp.path().join("still_ascii.png")), &mut |update| {
+        updates.push(update)
+    })
+    .expect("image run");
+    let converting = |current| PipelineProgress {
+        stage: Stage::Converting,
+        current,
+        total: Some(1),
+    };
+    assert_eq!(updates, [converting(0), converting(1)]);
+
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let video_input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &video_input, 64, 48, 5, 1.0).expect("create test video");
+    let mut updates = Vec::new();
+    let stats = run_with_progress(&base_config(&video_input, &temp.path().join("out.mp4")), &mut |update| {
+        updates.push(update)
+    })
+    .expect("video run");
+
+    let frames: Vec<u64> = updates.iter().filter(|u| u.stage == Stage::Converting).map(|u| u.current).collect();
+    assert_eq!(frames, (0..=stats.frames_processed as u64).collect::<Vec<_>>());
+    assert_eq!(updates.last().map(|u| (u.stage, u.current)), Some((Stage::Encoding, 1)));
+}
+
+#[test]
+fn max_frames_stops_conversion_early() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 10, 2.0).expect("create test video");
+
+    for disk_frames in [false, true] {
+        let output = temp.path().join(format!("limited_{disk_frames}.mp4"));
+        let stats = run(&PipelineConfig {
+            max_frames: Some(5),
+            disk_frames,
+            ..base_config(&input, &output)
+        })
+        .expect("limited run");
+        assert_eq!(stats.frames_processed, 5);
+
+        let meta = video::probe_video(ffprobe(), &output).expect("probe output video");
+        assert!(meta.duration.is_some_and(|duration| duration < 1.0), "{meta:?}");
+    }
+}
+
+#[test]
+fn batched_disk_frames_match_streamed_frame_count() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 10, 1.0).expect("create test video");
+
+    let streamed = run(&base_config(&input, &temp.path().join("streamed.mp4"))).expect("streamed run");
+    let batched = run(&PipelineConfig {
+        disk_frames: true,
+        batch_size: Some(3),
+        ..base_config(&input, &temp.path().join("batched.mp4"))
+    })
+    .expect("batched run");
+
+    assert_eq!(streamed.frames_processed, batched.frames_processed);
+}
+
+#[test]
+fn trimmed_range_yields_fewer_frames() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 10, 2.0).expect("create test video");
+
+    let full = run(&base_config(&input, &temp.path().join("full.mp4"))).expect("full run");
+    let clip = run(&PipelineConfig {
+        range: TimeRange {
+            start: Some(0.5),
+            duration: Some(0.5),
+            ..TimeRange::default()
+        },
+        ..base_config(&input, &temp.path().join("clip.mp4"))
+    })
+    .expect("clip run");
+
+    assert!(clip.frames_processed < full.frames_processed);
+}
+
+#[test]
+fn frames_out_writes_numbered_pngs_without_encoding() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("unused.mp4");
+    let frames_dir = temp.path().join("frames");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let stats = run(&PipelineConfig {
+        frames_out: Some(frames_dir.clone()),
+        transparent: true,
+        ..base_config(&input, &output)
+    })
+    .expect("run pipeline");
+
+    assert_eq!(stats.output, frames_dir);
+    assert!(!output.exists(), "nothing should be encoded");
+    let pngs = std::fs::read_dir(&frames_dir).expect("read frames dir").count();
+    assert_eq!(pngs, stats.frames_processed);
+    let first = image::open(frames_dir.join("frame_00000001.png")).expect("open first frame");
+    assert!(first.color().has_alpha());
+}
+
+#[test]
+fn hold_halves_distinct_converted_frames() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let frames_dir = temp.path().join("frames");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 10, 1.0).expect("create test video");
+
+    let stats = run(&PipelineConfig {
+        frames_out: Some(frames_dir.clone()),
+        hold: 2,
+        ..base_config(&input, &temp.path().join("unused.mp4"))
+    })
+    .expect("run pipeline");
+
+    // Every frame is still written, so the duration is unchanged
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&frames_dir)
+        .expect("read frames dir")
+        .map(|entry| entry.expect("dir entry").path())
+        .collect();
+    paths.sort();
+    assert_eq!(paths.len(), stats.frames_processed);
+
+    let contents: Vec<Vec<u8>> = paths.iter().map(|path| std::fs::read(path).expect("read frame")).collect();
+    for pair in contents.chunks(2) {
+        assert_eq!(pair[0], pair[pair.len() - 1], "held frames should repeat");
+    }
+    let distinct: std::collections::HashSet<&Vec<u8>> = contents.iter().collect();
+    assert!(distinct.len() <= stats.frames_processed.div_ceil(2));
+}
+
+#[test]
+fn dedup_reuses_repeated_frames_without_changing_them() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 2, 1.0).expect("create test video");
+
+    // Sampling 2 fps at 10 fps repeats every source frame five times
+    let convert = |dedup: bool| {
+        let frames_dir = temp.path().join(format!("frames_{dedup}"));
+        let stats = run(&PipelineConfig {
+            frames_out: Some(frames_dir.clone()),
+            fps: Some(10.0),
+            dedup,
+            ..base_config(&input, &temp.path().join("unused.mp4"))
+        })
+        .expect("run pipeline");
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&frames_dir)
+            .expect("read frames dir")
+            .map(|entry| entry.expect("dir entry").path())
+            .collect();
+        paths.sort();
+        let contents: Vec<Vec<u8>> = paths.iter().map(|path| std::fs::read(path).expect("read frame")).collect();
+        (stats, contents)
+    };
+
+    let (plain, expected) = convert(false);
+    let (deduped, contents) = convert(true);
+    assert_eq!(plain.frames_reused, 0);
+    assert!(deduped.frames_reused >= deduped.frames_processed / 2, "{deduped:?}");
+    assert_eq!(deduped.frames_processed, plain.frames_processed);
+    assert_eq!(contents, expected);
+}
+
+#[test]
+fn transparent_apng_round_trips_alpha() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("output_ascii.apng");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        encode: EncodeOptions {
+            format: OutputFormat::Apng,
+            ..EncodeOptions::default()
+        },
+        transparent: true,
+        ..base_config(&input, &output)
+    };
+    let stats = run(&config).expect("run pipeline");
+
+    // The first APNG frame decodes as a regular PNG with its alpha intact
+    let first = image::load(
+        std::io::BufReader::new(std::fs::File::open(&output).expect("open output")),
+        image::ImageFormat::Png,
+    )
+    .expect("decode apng")
+    .to_rgba8();
+    assert_eq!(first.dimensions(), (stats.output_resolution.width, stats.output_resolution.height));
+    assert!(first.pixels().any(|p| p[3] == 0));
+}
+
+#[test]
+fn transparent_webm_keeps_alpha() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("output_ascii.webm");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        encode: EncodeOptions {
+            format: OutputFormat::Webm,
+            ..EncodeOptions::default()
+        },
+        transparent: true,
+        ..base_config(&input, &output)
+    };
+    run(&config).expect("run pipeline");
+
+    // libvpx stores alpha out of band and flags the stream with alpha_mode=1
+    let probe = Command::new(ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream_tags=alpha_mode",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(&output)
+        .output()
+        .expect("run ffprobe");
+    assert_eq!(String::from_utf8_lossy(&probe.stdout).trim(), "1");
+}
+
+#[test]
+fn hung_ffmpeg_is_killed_after_the_timeout() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 320, 240, 30, 10.0).expect("create test video");
+
+    // Extracting 300 frames to disk can't finish in a millisecond
+    let config = PipelineConfig {
+        disk_frames: true,
+        timeout: Some(Duration::from_millis(1)),
+        ..base_config(&input, &temp.path().join("output_ascii.mp4"))
+    };
+    match run(&config) {
+        Err(AppError::CommandTimeout { program, .. }) => assert!(program.contains("ffmpeg")),
+        other => panic!("expected CommandTimeout, got {other:?}"),
+    }
+}
+
+#[test]
+fn streamed_decoding_is_killed_after_the_timeout() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 320, 240, 30, 10.0).expect("create test video");
+
+    // ffmpeg can't start up and decode a frame in a millisecond
+    let limit = Some(Duration::from_millis(1));
+    let mut stream = video::with_command_timeout(limit, || {
+        video::stream_frames(ffmpeg(), &input, &TimeRange::default(), None, None, 320, 240)
+    })
+    .expect("start stream");
+    match stream.next() {
+        Some(Err(AppError::CommandTimeout { program, .. })) => assert!(program.contains("ffmpeg")),
+        other => panic!("expected CommandTimeout, got {other:?}"),
+    }
+    assert!(stream.next().is_none());
+
+    // The default run streams frames; probing is covered by the same limit
+    let config = PipelineConfig {
+        timeout: limit.map(|limit| limit * 10),
+        ..base_config(&input, &temp.path().join("output_ascii.mp4"))
+    };
+    assert!(!config.disk_frames);
+    assert!(matches!(run(&config), Err(AppError::CommandTimeout { .. })));
+}
+
+#[test]
+fn deterministic_runs_write_identical_files() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let encode = |name: &str| {
+        let output = temp.path().join(name);
+        let config = PipelineConfig {
+            encode: EncodeOptions {
+                deterministic: true,
+                ..EncodeOptions::default()
+            },
+            ..base_config(&input, &output)
+        };
+        run(&config).expect("run pipeline");
+        std::fs::read(&output).expect("read output")
+    };
+    assert_eq!(encode("first.mp4"), encode("second.mp4"));
+}
+
+#[test]
+fn mkv_output_is_muxed_as_matroska() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("output_ascii.mkv");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        encode: EncodeOptions {
+            format: OutputFormat::Mkv,
+            ..EncodeOptions::default()
+        },
+        ..base_config(&input, &output)
+    };
+    run(&config).expect("run pipeline");
+
+    let probe = Command::new(ffprobe())
+        .args(["-v", "error", "-show_entries", "format=format_name", "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(&output)
+        .output()
+        .expect("run ffprobe");
+    assert!(String::from_utf8_lossy(&probe.stdout).trim().starts_with("matroska"));
+}
+
+#[test]
+fn ten_bit_mp4_round_trips_its_pixel_format() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+    let formats = video::encoder_pixel_formats(ffmpeg(), "libx264").unwrap_or_default();
+    if !formats.iter().any(|name| name == "yuv420p10le") {
+        eprintln!("Skipping 10-bit test: libx264 was built without 10-bit support.");
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("output_ascii.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        encode: EncodeOptions {
+            bit_depth: BitDepth::Ten,
+            ..EncodeOptions::default()
+        },
+        shades: 64,
+        ..base_config(&input, &output)
+    };
+    run(&config).expect("run pipeline");
+
+    let probe = Command::new(ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=pix_fmt",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(&output)
+        .output()
+        .expect("run ffprobe");
+    assert_eq!(String::from_utf8_lossy(&probe.stdout).trim(), "yuv420p10le");
+}
+
+#[test]
+fn tiles_stack_each_variant_into_one_grid_video() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("tiles.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = base_config(&input, &output);
+    let variants: Vec<PipelineConfig> = ["@. ", "#+ ", "%=-"]
+        .into_iter()
+        .map(|charset| PipelineConfig {
+            charset: charset.to_string(),
+            ..config.clone()
+        })
+        .collect();
+    let stats = run_tiled(&config, &variants).expect("run tiled");
+
+    // Three tiles fill a 2x2 grid
+    let meta = video::probe_video(ffprobe(), &output).expect("probe output");
+    assert_eq!((meta.width, meta.height), (128, 96));
+    assert_eq!((stats.output_resolution.width, stats.output_resolution.height), (128, 96));
+    assert_eq!(stats.frames_processed, 5);
+    assert!(matches!(run_tiled(&config, &variants[..1]), Err(AppError::TooFewVariants(1))));
+}
+
+#[test]
+fn concat_joins_inputs_into_one_video_at_the_largest_size() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let wide = temp.path().join("wide.mp4");
+    let narrow = temp.path().join("narrow.mp4");
+    let output = temp.path().join("joined.mp4");
+    video::create_test_video(ffmpeg(), &wide, 64, 48, 5, 1.0).expect("create wide video");
+    video::create_test_video(ffmpeg(), &narrow, 32, 48, 5, 1.0).expect("create narrow video");
+
+    let config = base_config(&wide, &output);
+    let parts = vec![config.clone(), base_config(&narrow, &output)];
+    let stats = run_concat(&config, &parts).expect("run concat");
+
+    // The narrow part is padded onto the wide one's canvas
+    let meta = video::probe_video(ffprobe(), &output).expect("probe output");
+    assert_eq!(meta.frame_count, Some(10));
+    assert_eq!(stats.frames_processed, 10);
+    assert_eq!((meta.width, meta.height), (64, 48));
+    assert_eq!((stats.output_resolution.width, stats.output_resolution.height), (64, 48));
+}
+
+#[test]
+fn av1_mp4_round_trips_its_codec() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+    let encode = EncodeOptions {
+        codec: VideoCodec::Av1,
+        preset: "ultrafast".to_string(),
+        ..EncodeOptions::default()
+    };
+    if video::verify_encoders(ffmpeg(), &encode, false).is_err() {
+        eprintln!("Skipping AV1 test: ffmpeg was built without libsvtav1.");
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("output_ascii.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    run(&PipelineConfig {
+        encode,
+        ..base_config(&input, &output)
+    })
+    .expect("run pipeline");
+
+    let probe = Command::new(ffprobe())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=codec_name",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(&output)
+        .output()
+        .expect("run ffprobe");
+    assert_eq!(String::from_utf8_lossy(&probe.stdout).trim(), "av1");
+    let meta = video::probe_video(ffprobe(), &output).expect("probe output");
+    assert_eq!((meta.width, meta.height), (64, 48));
+}
+
+#[test]
+fn lower_fps_extracts_fewer_frames() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 10, 2.0).expect("create test video");
+
+    let full = run(&PipelineConfig {
+        disk_frames: true,
+        ..base_config(&input, &temp.path().join("full.mp4"))
+    })
+    .expect("full run");
+    let downsampled = run(&PipelineConfig {
+        fps: Some(5.0),
+        disk_frames: true,
+        ..base_config(&input, &temp.path().join("half.mp4"))
+    })
+    .expect("downsampled run");
+
+    assert!(downsampled.frames_processed < full.frames_processed);
+}
+
+#[test]
+fn out_fps_stretches_duration_by_rate_ratio() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let output = temp.path().join("slow.mp4");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 10, 2.0).expect("create test video");
+
+    // Sampled at 10 fps, played back at 5: half speed, twice as long
+    run(&PipelineConfig {
+        fps: Some(10.0),
+        out_fps: Some(5.0),
+        ..base_config(&input, &output)
+    })
+    .expect("slow motion run");
+
+    let source = video::probe_video(ffprobe(), &input).expect("probe input").duration.expect("input duration");
+    let slowed = video::probe_video(ffprobe(), &output).expect("probe output").duration.expect("output duration");
+    assert!((slowed / source - 2.0).abs() < 0.15, "source {source}s, output {slowed}s");
+}
+
+#[test]
+fn work_dir_run_resumes_from_cached_frames() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let work_dir = temp.path().join("work");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        work_dir: Some(work_dir.clone()),
+        disk_frames: true,
+        ..base_config(&input, &temp.path().join("out.mp4"))
+    };
+    let first = run(&config).expect("first run");
+
+    let ascii_frames = video::list_frames(&work_dir.join("ascii")).expect("cached frames");
+    assert_eq!(ascii_frames.len(), first.frames_processed);
+
+    let second = run(&config).expect("resumed run");
+    assert_eq!(second.frames_processed, first.frames_processed);
+}
+
+#[test]
+fn work_dir_rerenders_cached_frames_when_a_render_setting_changes() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let work_dir = temp.path().join("work");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        work_dir: Some(work_dir.clone()),
+        ..base_config(&input, &temp.path().join("out.mp4"))
+    };
+    run(&config).expect("first run");
+
+    // Replace a cached frame with a marker no render produces
+    let cached = video::list_frames(&work_dir.join("ascii")).expect("cached frames")[0].clone();
+    let (width, height) = image::image_dimensions(&cached).expect("frame size");
+    let marker = GrayImage::from_pixel(width, height, Luma([77]));
+    marker.save(&cached).expect("write marker");
+
+    run(&config).expect("resumed run");
+    assert_eq!(image::open(&cached).expect("open frame").to_luma8(), marker, "same settings reuse the cache");
+
+    run(&PipelineConfig { luma: LumaMethod::Average, ..config }).expect("rerun with another luma");
+    assert_ne!(image::open(&cached).expect("open frame").to_luma8(), marker, "changed luma re-renders");
+}
+
+#[test]
+fn truncated_frame_fails_with_its_path_unless_skipped() {
+    if skip_if_no_ffmpeg() {
+        return;
+    }
+
+    let temp = TempDir::new().expect("temp dir");
+    let input = temp.path().join("input.mp4");
+    let work_dir = temp.path().join("work");
+    video::create_test_video(ffmpeg(), &input, 64, 48, 5, 1.0).expect("create test video");
+
+    let config = PipelineConfig {
+        work_dir: Some(work_dir.clone()),
+        disk_frames: true,
+        ..base_config(&input, &temp.path().join("out.mp4"))
+    };
+    let first = run(&config).expect("first run");
+
+    // Cut a cached extracted frame in half; the next run reads it back
+    let extracted = video::list_frames(&work_dir.join("extracted")).expect("extracted frames");
+    let bad = &extracted[1];
+    let bytes = std::fs::read(bad).expect("read frame");
+    std::fs::write(bad, &bytes[..bytes.len() / 2]).expect("truncate frame");
+
+    match run(&config) {
+        Err(AppError::FrameDecode { path, .. }) => assert_eq!(&path, bad),
+        other => panic!("expected FrameDecode, got {other:?}"),
+    }
+
+    let skipped = run(&PipelineConfig {
+        skip_bad_frames: true,
+        ..config
+    })
+    .expect("run skipping bad frames");
+    assert_eq!(skipped.frames_processed, first.frames_processed - 1);
 }